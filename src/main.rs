@@ -13,6 +13,8 @@
  *  - Configure GCP authentication for GKE when required
  *  - Configure CORS middleware
  *  - Register routes and attach shared state
+ *  - Start the optional SSH gateway listener
+ *  - Start background delivery of spooled terminal events
  *  - Start the HTTP server on the configured port
  *
  * Key characteristics:
@@ -29,7 +31,14 @@
  */
 use kube::{config::AuthInfo, Client, Config};
 use rustls_pemfile::certs;
+use services::{
+    gateway_identity::GatewayIdentity, lab_web_cookie_keyring::LabWebCookieKeyring,
+    lab_web_routing::LabWebRouting, sessions_ms_client::SessionsMsClient,
+    terminal_event_outbox::TerminalEventOutbox, terminal_event_sinks::TerminalEventSinks,
+    web_traffic_capture::WebTrafficCapture,
+};
 use std::io::BufReader;
+use std::sync::Arc;
 use tower_http::cors::{Any, CorsLayer};
 use tracing::{error, info};
 use tracing_subscriber::EnvFilter;
@@ -58,12 +67,23 @@ async fn main() {
         }
     };
 
+    if let Err(e) = services::ssh_gateway::start_ssh_gateway(state.clone()).await {
+        error!("Failed to start SSH gateway: {}", e);
+        std::process::exit(1);
+    }
+
+    services::terminal_event_outbox::start_terminal_event_delivery(
+        state.terminal_outbox.clone(),
+        state.sessions_ms.clone(),
+    );
+
     let cors = CorsLayer::new()
         .allow_origin(Any)
         .allow_methods(Any)
         .allow_headers(Any);
 
-    let app = routes::init_routes().layer(cors).with_state(state);
+    let app = routes::init_routes().layer(cors).with_state(state.clone());
+    let app = routes::with_lab_web_hosts(app, state);
 
     let port = std::env::var("PORT").unwrap_or_else(|_| DEFAULT_PORT.to_string());
     let addr = format!("0.0.0.0:{}", port);
@@ -78,6 +98,12 @@ async fn main() {
 
 async fn init_state() -> Result<models::State, String> {
     let local_mode = parse_bool_env("LAB_API_LOCAL_MODE", false);
+    let terminal_outbox = Arc::new(TerminalEventOutbox::from_env());
+    let terminal_event_sinks = Arc::new(TerminalEventSinks::from_env(terminal_outbox.clone()));
+    let web_cookie_keys = Arc::new(LabWebCookieKeyring::from_env());
+    let gateway_identity = Arc::new(GatewayIdentity::from_env(local_mode));
+    let web_routing = Arc::new(LabWebRouting::from_env());
+    let sessions_ms = Arc::new(SessionsMsClient::from_env());
 
     if local_mode {
         info!("LAB_API_LOCAL_MODE=true -> using local kubeconfig and skipping GCP auth init");
@@ -85,10 +111,26 @@ async fn init_state() -> Result<models::State, String> {
             .await
             .map_err(|e| format!("Kubernetes client init failed: {}", e))?;
 
+        let web_traffic = Arc::new(WebTrafficCapture::start(
+            kube_client.clone(),
+            terminal_event_sinks.clone(),
+            terminal_outbox.clone(),
+        ));
+
         return Ok(models::State {
             token_provider: None,
             kube_client,
             local_mode: true,
+            ssh_credentials: Default::default(),
+            terminal_outbox,
+            terminal_event_sinks,
+            web_cookie_keys,
+            web_cookie_revocations: Default::default(),
+            web_routing,
+            gateway_identity,
+            sessions_ms,
+            ide_tokens: Default::default(),
+            web_traffic,
         });
     }
 
@@ -100,11 +142,26 @@ async fn init_state() -> Result<models::State, String> {
     })?;
 
     let kube_client = create_gke_client(&token_provider).await?;
+    let web_traffic = Arc::new(WebTrafficCapture::start(
+        kube_client.clone(),
+        terminal_event_sinks.clone(),
+        terminal_outbox.clone(),
+    ));
 
     Ok(models::State {
         token_provider: Some(token_provider),
         kube_client,
         local_mode: false,
+        ssh_credentials: Default::default(),
+        terminal_outbox,
+        terminal_event_sinks,
+        web_cookie_keys,
+        web_cookie_revocations: Default::default(),
+        web_routing,
+        gateway_identity,
+        sessions_ms,
+        ide_tokens: Default::default(),
+        web_traffic,
     })
 }
 
@@ -0,0 +1,37 @@
+/**
+ * @file files — runtime file transfer models.
+ *
+ * @remarks
+ * Defines the query and response structures used to move files
+ * into and out of a lab runtime as tar archives.
+ *
+ * Includes:
+ *
+ *  - Transfer query parameters (`FileTransferQuery`)
+ *  - Upload response structures (`FileUploadResponse`, `FileUploadResponseData`)
+ *
+ * Key characteristics:
+ *
+ *  - Paths are absolute container paths
+ *  - Archive bodies are streamed, not represented here
+ *
+ * @packageDocumentation
+ */
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize)]
+pub struct FileTransferQuery {
+    pub path: String,
+}
+
+#[derive(Serialize)]
+pub struct FileUploadResponse {
+    pub success: bool,
+    pub data: FileUploadResponseData,
+}
+
+#[derive(Serialize)]
+pub struct FileUploadResponseData {
+    pub path: String,
+    pub bytes: u64,
+}
@@ -7,7 +7,9 @@
  *
  * Exposes:
  *
+ *  - Runtime file transfer models (`files`)
  *  - Runtime lifecycle models (`spawn`)
+ *  - SSH gateway credential models (`ssh`)
  *  - Application state (`state`)
  *
  * Key characteristics:
@@ -21,10 +23,15 @@
  *
  * @packageDocumentation
  */
+mod files;
 mod spawn;
+mod ssh;
 mod state;
 
+pub use files::{FileTransferQuery, FileUploadResponse, FileUploadResponseData};
 pub use spawn::{
-    SpawnRequest, SpawnResponse, SpawnResponseData, StatusResponse, StopRequest, StopResponse,
+    IdeKind, SpawnRequest, SpawnResponse, SpawnResponseData, StatusResponse, StopRequest,
+    StopResponse, TerminalCapturePolicy, WebRewritePolicy, WebShellSettings,
 };
+pub use ssh::{SshCredentialsRequest, SshCredentialsResponse, SshCredentialsResponseData};
 pub use state::State;
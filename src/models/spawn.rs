@@ -11,13 +11,17 @@
  *  - Spawn response structures (`SpawnResponse`, `SpawnResponseData`)
  *  - Stop request/response (`StopRequest`, `StopResponse`)
  *  - Status response (`StatusResponse`)
+ *  - Per-lab web shell launch settings (`WebShellSettings`)
+ *  - Per-lab terminal capture privacy policy (`TerminalCapturePolicy`)
+ *  - Per-lab web proxy prefix rewriting (`WebRewritePolicy`)
+ *  - Browser IDE started for `ide` delivery (`IdeKind`)
  *
  * Key characteristics:
  *
  *  - Identifies sessions and runtimes via UUIDs
  *  - Supports multiple lab types and delivery modes
  *  - Provides runtime metadata (container_id, runtime_kind)
- *  - Exposes access endpoints (webshell_url, optional app_url)
+ *  - Exposes access endpoints (webshell_url, optional app_url or desktop_url)
  *
  * This module represents the contract between the lab runtime API
  * and its consumers (gateway, frontend), handling lifecycle operations:
@@ -25,6 +29,8 @@
  *
  * @packageDocumentation
  */
+use std::collections::BTreeMap;
+
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
@@ -40,6 +46,109 @@ pub struct SpawnRequest {
     pub app_port: Option<i32>,
     #[serde(default)]
     pub session_flags: serde_json::Value,
+    #[serde(default)]
+    pub webshell: Option<WebShellSettings>,
+    #[serde(default)]
+    pub terminal_capture: Option<TerminalCapturePolicy>,
+    #[serde(default)]
+    pub web_rewrite: Option<WebRewritePolicy>,
+    #[serde(default)]
+    pub ide: Option<IdeKind>,
+}
+
+/// What terminal activity lab-api may capture for analytics. Runtimes spawned
+/// without a policy behave as `commands-only`.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum TerminalCapturePolicy {
+    /// Nothing typed or printed in the terminal is inspected or forwarded.
+    Off,
+    #[default]
+    CommandsOnly,
+    CommandsWithOutput,
+}
+
+impl TerminalCapturePolicy {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Off => "off",
+            Self::CommandsOnly => "commands-only",
+            Self::CommandsWithOutput => "commands-with-output",
+        }
+    }
+
+    pub fn captures_commands(self) -> bool {
+        self != Self::Off
+    }
+}
+
+/// How the web proxy keeps a lab app under its `/web/{container_id}/` prefix.
+/// Runtimes spawned without a policy behave as `headers`.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum WebRewritePolicy {
+    /// Responses are passed through untouched.
+    Off,
+    /// Root-relative `Location` redirects and `Set-Cookie` paths are prefixed.
+    #[default]
+    Headers,
+    /// Headers, plus root-relative URLs in HTML and CSS bodies.
+    HeadersAndContent,
+}
+
+impl WebRewritePolicy {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Off => "off",
+            Self::Headers => "headers",
+            Self::HeadersAndContent => "headers-and-content",
+        }
+    }
+}
+
+/// Browser IDE started next to the lab container for `ide` delivery.
+/// Runtimes spawned without one get `code-server`.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum IdeKind {
+    #[default]
+    CodeServer,
+    Jupyter,
+}
+
+impl IdeKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::CodeServer => "code-server",
+            Self::Jupyter => "jupyter",
+        }
+    }
+}
+
+/// How the web shell is launched inside a runtime; every field falls back to
+/// the default current-user bash/sh shell when omitted.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct WebShellSettings {
+    /// Terminal and IDE runtimes allow the web shell unless disabled; web and
+    /// desktop runtimes only allow it when a lab opts in explicitly.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub container: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub working_dir: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    /// Caps for per-command output excerpts under the `commands-with-output`
+    /// capture policy; lab-api defaults apply when omitted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_excerpt_max_bytes: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_excerpt_max_lines: Option<u32>,
 }
 
 #[derive(Serialize)]
@@ -58,6 +167,8 @@ pub struct SpawnResponseData {
     // app_url stays in the backend contract temporarily while LAB-WEB consumers
     // migrate to the bootstrap-tab flow; the frontend no longer relies on it.
     pub app_url: Option<String>,
+    /// noVNC WebSocket endpoint for desktop runtimes.
+    pub desktop_url: Option<String>,
 }
 
 #[derive(Deserialize)]
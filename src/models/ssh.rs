@@ -0,0 +1,41 @@
+/**
+ * @file ssh — SSH gateway credential models.
+ *
+ * @remarks
+ * Defines the request and response structures used to issue
+ * per-runtime credentials for the optional SSH gateway.
+ *
+ * Includes:
+ *
+ *  - Credential request payload (`SshCredentialsRequest`)
+ *  - Credential response structures (`SshCredentialsResponse`, `SshCredentialsResponseData`)
+ *
+ * Key characteristics:
+ *
+ *  - Credentials are scoped to a single runtime (container_id)
+ *  - Supports password login and an optional OpenSSH public key
+ *  - Exposes the public SSH host and port learners connect to
+ *
+ * @packageDocumentation
+ */
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize, Default)]
+pub struct SshCredentialsRequest {
+    pub public_key: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct SshCredentialsResponse {
+    pub success: bool,
+    pub data: SshCredentialsResponseData,
+}
+
+#[derive(Serialize)]
+pub struct SshCredentialsResponseData {
+    pub username: String,
+    pub password: String,
+    pub host: String,
+    pub port: u16,
+    pub expires_in_secs: u64,
+}
@@ -10,6 +10,16 @@
  *  - GCP token provider (`TokenProvider`) for authenticated API calls
  *  - Kubernetes client (`kube::Client`) for runtime orchestration
  *  - Execution mode flag (`local_mode`)
+ *  - SSH gateway credential registry (`ssh_credentials`)
+ *  - Durable terminal analytics outbox (`terminal_outbox`)
+ *  - Configured terminal event sinks (`terminal_event_sinks`)
+ *  - Lab web session cookie signing keys (`web_cookie_keys`)
+ *  - Revoked lab web session cookies (`web_cookie_revocations`)
+ *  - Path or subdomain routing for web labs (`web_routing`)
+ *  - Gateway JWT verification for user-scoped routes (`gateway_identity`)
+ *  - Pooled, cached sessions-ms lookups (`sessions_ms`)
+ *  - Browser IDE tokens of `ide` runtimes (`ide_tokens`)
+ *  - Web proxy request events queue (`web_traffic`)
  *
  * Key characteristics:
  *
@@ -27,9 +37,28 @@ use std::sync::Arc;
 use gcp_auth::TokenProvider;
 use kube::Client;
 
+use crate::services::{
+    gateway_identity::GatewayIdentity, lab_ide::IdeTokenCache,
+    lab_web_cookie_keyring::LabWebCookieKeyring,
+    lab_web_cookie_revocations::LabWebCookieRevocations, lab_web_routing::LabWebRouting,
+    sessions_ms_client::SessionsMsClient, ssh_gateway::SshCredentialRegistry,
+    terminal_event_outbox::TerminalEventOutbox, terminal_event_sinks::TerminalEventSinks,
+    web_traffic_capture::WebTrafficCapture,
+};
+
 #[derive(Clone)]
 pub struct State {
     pub token_provider: Option<Arc<dyn TokenProvider>>,
     pub kube_client: Client,
     pub local_mode: bool,
+    pub ssh_credentials: Arc<SshCredentialRegistry>,
+    pub terminal_outbox: Arc<TerminalEventOutbox>,
+    pub terminal_event_sinks: Arc<TerminalEventSinks>,
+    pub web_cookie_keys: Arc<LabWebCookieKeyring>,
+    pub web_cookie_revocations: Arc<LabWebCookieRevocations>,
+    pub web_routing: Arc<LabWebRouting>,
+    pub gateway_identity: Arc<GatewayIdentity>,
+    pub sessions_ms: Arc<SessionsMsClient>,
+    pub ide_tokens: Arc<IdeTokenCache>,
+    pub web_traffic: Arc<WebTrafficCapture>,
 }
@@ -0,0 +1,98 @@
+/**
+ * @file files — HTTP routes for runtime file transfer.
+ *
+ * @remarks
+ * Lets learners upload tools into a lab runtime and download
+ * artifacts from it as tar archives.
+ *
+ * Endpoints:
+ *
+ *  - `POST /spawn/files/{pod_name}?path=` → extract an uploaded tar archive
+ *  - `GET /spawn/files/{pod_name}?path=` → download a path as a tar archive
+ *
+ * Key characteristics:
+ *
+ *  - Bodies are streamed to and from the runtime
+ *  - Paths are restricted to configured roots
+ *  - Callers must present a gateway token for the user who owns the runtime
+ *  - Transfers are audit-logged with the verified requesting user
+ *
+ * @packageDocumentation
+ */
+use axum::{
+    body::Body,
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use tracing::info;
+
+use crate::{
+    models::{self, FileTransferQuery, FileUploadResponse, FileUploadResponseData},
+    services::runtime_files,
+};
+
+pub async fn upload_files(
+    State(state): State<models::State>,
+    Path(pod_name): Path<String>,
+    Query(query): Query<FileTransferQuery>,
+    headers: HeaderMap,
+    body: Body,
+) -> Result<Json<FileUploadResponse>, StatusCode> {
+    let user_id = state.gateway_identity.authenticate(&headers).await?;
+    info!(
+        pod_name = %pod_name,
+        path = %query.path,
+        user_id = %user_id,
+        action = "file_upload",
+        "runtime file upload requested"
+    );
+
+    let bytes =
+        runtime_files::upload_archive(&state, user_id, &pod_name, &query.path, body).await?;
+
+    Ok(Json(FileUploadResponse {
+        success: true,
+        data: FileUploadResponseData {
+            path: query.path,
+            bytes,
+        },
+    }))
+}
+
+pub async fn download_files(
+    State(state): State<models::State>,
+    Path(pod_name): Path<String>,
+    Query(query): Query<FileTransferQuery>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    let user_id = state.gateway_identity.authenticate(&headers).await?;
+    info!(
+        pod_name = %pod_name,
+        path = %query.path,
+        user_id = %user_id,
+        action = "file_download",
+        "runtime file download requested"
+    );
+
+    let (archive_name, body) =
+        runtime_files::download_archive(&state, user_id, &pod_name, &query.path).await?;
+    let disposition = HeaderValue::from_str(&format!(
+        "attachment; filename=\"{}\"",
+        archive_name.replace(['"', '\\'], "_")
+    ))
+    .map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    Ok((
+        [
+            (
+                header::CONTENT_TYPE,
+                HeaderValue::from_static("application/x-tar"),
+            ),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        body,
+    )
+        .into_response())
+}
@@ -0,0 +1,26 @@
+/**
+ * @file metrics — Prometheus scrape endpoint.
+ *
+ * @remarks
+ * Exposes lab-api counters in the Prometheus text format.
+ *
+ * Endpoint:
+ *
+ *  - `GET /metrics` → terminal event outbox counters and spool size
+ *
+ * Key characteristics:
+ *
+ *  - Unauthenticated; keep it reachable from the cluster network only
+ *
+ * @packageDocumentation
+ */
+use axum::{extract::State, http::header, response::IntoResponse};
+
+use crate::models;
+
+pub async fn metrics(State(state): State<models::State>) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        state.terminal_outbox.render_metrics(),
+    )
+}
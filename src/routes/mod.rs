@@ -8,11 +8,22 @@
  * Registered routes:
  *
  *  - `GET /health` → service health check
+ *  - `GET /metrics` → Prometheus metrics for terminal event delivery
  *  - `POST /spawn` → create a new lab runtime (Pod)
  *  - `POST /spawn/stop` → stop and delete a runtime
  *  - `GET /spawn/status/{container_id}` → retrieve runtime status
  *  - `POST /web/open-session/{session_id}` → open a secured web lab session
+ *  - `POST /web/close-session/{session_id}` → revoke and clear the web session cookie
+ *  - `ANY /web/{container_id}/{*path}` → cookie-checked proxy to a web runtime
+ *  - `GET /web/desktop/{container_id}` → cookie-checked noVNC WebSocket to a
+ *    desktop runtime
+ *  - `GET /web/host-session/{container_id}` → host-only cookie bootstrap, reached
+ *    as `/__altair/session` on a runtime host
+ *  - `ANY {container_id}.<subdomain base>/{*path}` → the same proxy, in
+ *    subdomain routing mode
  *  - `GET /spawn/webshell/{pod_name}` → WebSocket terminal access
+ *  - `POST /spawn/ssh-credentials/{container_id}` → issue SSH gateway credentials
+ *  - `GET|POST /spawn/files/{pod_name}?path=` → download or upload a tar archive
  *
  * Key characteristics:
  *
@@ -25,14 +36,18 @@
  *
  * @packageDocumentation
  */
+mod files;
 mod spawn;
+mod ssh;
 mod web;
 mod web_shell;
 
 pub mod health;
+pub mod metrics;
 
 use axum::{
-    routing::{get, post},
+    middleware,
+    routing::{any, get, post},
     Router,
 };
 
@@ -41,6 +56,7 @@ use crate::models::State;
 pub fn init_routes() -> Router<State> {
     Router::new()
         .route("/health", get(health::health))
+        .route("/metrics", get(metrics::metrics))
         .route("/spawn", post(spawn::spawn_lab))
         .route("/spawn/stop", post(spawn::stop_lab))
         .route("/spawn/status/{container_id}", get(spawn::status_lab))
@@ -48,8 +64,41 @@ pub fn init_routes() -> Router<State> {
             "/web/open-session/{session_id}",
             post(web::open_web_session),
         )
+        .route(
+            "/web/close-session/{session_id}",
+            post(web::close_web_session),
+        )
+        .route(
+            "/web/host-session/{container_id}",
+            get(web::open_web_host_session),
+        )
+        .route(
+            "/web/desktop/{container_id}",
+            get(web::proxy_desktop_session),
+        )
+        .route("/web/{container_id}/", any(web::proxy_web_session))
+        .route("/web/{container_id}/{*path}", any(web::proxy_web_session))
         .route(
             "/spawn/webshell/{pod_name}",
             get(web_shell::lab_terminal_ws),
         )
+        .route(
+            "/spawn/ssh-credentials/{container_id}",
+            post(ssh::issue_ssh_credentials),
+        )
+        .route(
+            "/spawn/files/{pod_name}",
+            get(files::download_files).post(files::upload_files),
+        )
+}
+
+/// Wraps the finished app so runtime hosts (subdomain routing mode) are
+/// dispatched to the web proxy before any other route is matched.
+pub fn with_lab_web_hosts(app: Router, state: State) -> Router {
+    Router::new()
+        .fallback_service(app)
+        .layer(middleware::from_fn_with_state(
+            state,
+            web::route_lab_web_hosts,
+        ))
 }
@@ -14,16 +14,17 @@
  * Key characteristics:
  *
  *  - Delegates orchestration logic to `services::spawn`
- *  - Supports both terminal and web runtimes
- *  - Dynamically builds access URLs (webshell or app)
+ *  - Supports terminal, web, desktop and browser IDE runtimes
+ *  - Dynamically builds access URLs (webshell, app or desktop)
  *  - Uses environment variables for base URLs
  *  - Returns structured responses for frontend consumption
  *
  * Features:
  *
- *  - Automatic runtime kind resolution (web vs terminal)
+ *  - Automatic runtime kind resolution (terminal, web, desktop or ide)
  *  - WebSocket endpoint generation for terminal sessions
- *  - HTTP endpoint generation for web labs
+ *  - HTTP endpoint generation for web and IDE labs
+ *  - noVNC WebSocket endpoint generation for desktop labs
  *
  * This module acts as the public API layer for runtime management,
  * bridging client requests with Kubernetes-backed execution.
@@ -40,7 +41,7 @@ use crate::{
     models::{
         SpawnRequest, SpawnResponse, SpawnResponseData, StatusResponse, StopRequest, StopResponse,
     },
-    services::spawn,
+    services::{desktop_proxy, spawn},
 };
 
 pub async fn spawn_lab(
@@ -51,6 +52,8 @@ pub async fn spawn_lab(
     let runtime_kind = match payload.lab_delivery.as_str() {
         "web" => "web".to_string(),
         "terminal" => "terminal".to_string(),
+        "desktop" => "desktop".to_string(),
+        "ide" => "ide".to_string(),
         _ => return Err(StatusCode::BAD_REQUEST),
     };
     let pod_name = spawn::spawn_lab(state, payload).await?;
@@ -60,26 +63,21 @@ pub async fn spawn_lab(
     let app_base_url =
         std::env::var("LAB_APP_BASE_URL").unwrap_or_else(|_| "http://localhost:8085".to_string());
 
-    let (webshell_url, app_url) = if runtime_kind == "web" {
+    let webshell_url = format!(
+        "{}/spawn/webshell/{}",
+        webshell_base_url.trim_end_matches('/'),
+        pod_name
+    );
+    let app_url = format!("{}/web/{}", app_base_url.trim_end_matches('/'), pod_name);
+
+    let (webshell_url, app_url, desktop_url) = match runtime_kind.as_str() {
         // LAB-WEB still publishes app_url for backend compatibility, even though
         // the learner flow now opens the runtime through the bootstrap tab.
-        (
-            None,
-            Some(format!(
-                "{}/web/{}",
-                app_base_url.trim_end_matches('/'),
-                pod_name
-            )),
-        )
-    } else {
-        (
-            Some(format!(
-                "{}/spawn/webshell/{}",
-                webshell_base_url.trim_end_matches('/'),
-                pod_name
-            )),
-            None,
-        )
+        "web" => (None, Some(app_url), None),
+        "desktop" => (None, None, Some(desktop_proxy::desktop_url(&pod_name))),
+        // IDE labs keep the web shell next to the IDE served at app_url.
+        "ide" => (Some(webshell_url), Some(app_url), None),
+        _ => (Some(webshell_url), None, None),
     };
 
     Ok(Json(SpawnResponse {
@@ -90,6 +88,7 @@ pub async fn spawn_lab(
             runtime_kind,
             webshell_url,
             app_url,
+            desktop_url,
             status: "running".to_string(),
         },
     }))
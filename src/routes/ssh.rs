@@ -0,0 +1,72 @@
+/**
+ * @file ssh — HTTP route for SSH gateway credentials.
+ *
+ * @remarks
+ * Issues short-lived SSH credentials that map a login to one lab runtime.
+ *
+ * Endpoint:
+ *
+ *  - `POST /spawn/ssh-credentials/{container_id}` → issue SSH credentials
+ *
+ * Key characteristics:
+ *
+ *  - Only available when the SSH gateway is enabled
+ *  - Callers must present a gateway token for the user who owns the runtime
+ *  - Requires the runtime to exist and allow shell access
+ *  - Accepts an optional OpenSSH public key for key-based login
+ *
+ * This route acts as the entry point for learners who prefer
+ * their own SSH client over the browser terminal.
+ *
+ * @packageDocumentation
+ */
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    Json,
+};
+use tracing::{info, warn};
+
+use crate::{
+    models::{self, SshCredentialsRequest, SshCredentialsResponse},
+    services::{spawn::ensure_runtime_owner, web_shell},
+};
+
+pub async fn issue_ssh_credentials(
+    State(state): State<models::State>,
+    Path(container_id): Path<String>,
+    headers: HeaderMap,
+    payload: Option<Json<SshCredentialsRequest>>,
+) -> Result<Json<SshCredentialsResponse>, StatusCode> {
+    if !crate::parse_bool_env("LAB_SSH_GATEWAY_ENABLED", false) {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let user_id = state.gateway_identity.authenticate(&headers).await?;
+    let runtime = web_shell::resolve_terminal_runtime(&state, &container_id)
+        .await
+        .map_err(|_| StatusCode::FORBIDDEN)?;
+    ensure_runtime_owner(&runtime.pod, user_id)?;
+
+    let Json(payload) = payload.unwrap_or_default();
+    let data = state
+        .ssh_credentials
+        .issue(&container_id, payload.public_key.as_deref())
+        .map_err(|reason| {
+            warn!(container_id = %container_id, reason = %reason, "Rejected SSH credential request");
+            StatusCode::BAD_REQUEST
+        })?;
+
+    info!(
+        container_id = %container_id,
+        user_id = %user_id,
+        username = %data.username,
+        action = "ssh_credentials",
+        "issued SSH gateway credentials"
+    );
+
+    Ok(Json(SshCredentialsResponse {
+        success: true,
+        data,
+    }))
+}
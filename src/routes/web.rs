@@ -1,24 +1,42 @@
 /**
- * @file web — HTTP route for opening web lab sessions.
+ * @file web — HTTP routes for opening and proxying web lab sessions.
  *
  * @remarks
- * Handles the secure bootstrap flow used to open web-based lab runtimes.
+ * Handles the secure bootstrap flow used to open web-based lab runtimes,
+ * and the cookie-checked proxy that serves them.
  *
  * Responsibilities:
  *
- *  - Extract and validate the authenticated user identifier
- *  - Query the Sessions service for the requested web runtime
+ *  - Authenticate the caller through the gateway identity verifier
+ *  - Query the Sessions service for the requested web runtime through the
+ *    shared, cached sessions-ms client
  *  - Ensure the runtime belongs to the current user
- *  - Validate that the runtime is a running web session
+ *  - Validate that the runtime is a running web, desktop or IDE session
  *  - Issue a signed, short-lived HTTP-only cookie
- *  - Return the redirect URL to the lab web proxy
+ *  - Return the redirect URL to the lab web proxy, or to the runtime's own
+ *    host in subdomain routing mode
+ *  - Exchange a one-time bootstrap token for a host-only cookie on
+ *    runtime hosts
+ *  - Verify the cookie before proxying requests and WebSockets to the runtime
+ *  - Sign proxied requests in to IDE runtimes with the runtime's IDE token
+ *  - Bridge noVNC to desktop runtimes behind the same cookie
+ *  - Reissue the cookie once it passes half its lifetime
+ *  - Keep path-prefixed lab apps under their prefix with the lab's
+ *    `WebRewritePolicy`
+ *  - Revoke and clear the cookie when the learner closes the session
  *
  * Key characteristics:
  *
  *  - Uses JWT-signed cookie claims for runtime access
- *  - Restricts cookie scope to the lab web path
- *  - Validates internal service URLs to avoid unsafe HTTP targets
- *  - Supports local development through loopback-only HTTP
+ *  - Every cookie carries a `jti`, so it can be revoked before it expires
+ *  - Renewed cookies keep their `jti` and never outlive the runtime's
+ *    `activeDeadlineSeconds`
+ *  - Signs with the rotating cookie keyring; any non-retired key verifies
+ *  - Restricts cookie scope to the lab web path, or to the runtime host
+ *  - Desktop runtimes always use the lab web path: noVNC runs in the
+ *    frontend and only needs the WebSocket
+ *  - Cookies only name the runtime's IDE kind; the proxy adds the IDE token
+ *    from a server-side cache, so it never reaches the browser
  *
  * This route protects web lab access by binding a running runtime
  * to the authenticated user before redirecting to the web session.
@@ -29,36 +47,44 @@ use std::time::{SystemTime, UNIX_EPOCH};
 
 use axum::{
     body::Body,
-    extract::{Path, State},
-    http::{HeaderMap, Response, StatusCode},
+    extract::{ws::WebSocketUpgrade, Path, Query, Request, State},
+    http::{header, HeaderMap, Response, StatusCode, Uri},
+    middleware::Next,
+    response::IntoResponse,
+    Extension,
 };
-use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
 use reqwest::Url;
 use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
 use uuid::Uuid;
 
-use crate::models::State as AppState;
+use crate::{
+    models::{IdeKind, State as AppState, WebRewritePolicy},
+    services::{
+        desktop_proxy,
+        lab_ide::resolve_ide_kind,
+        lab_web_cookie_revocations::lab_web_cookie_ttl_seconds,
+        lab_web_routing::{LabWebRouting, HOST_SESSION_PATH},
+        spawn::{find_runtime_pod, runtime_deadline},
+        web_proxy,
+        web_response_rewriting::{resolve_web_rewrite_policy, PrefixRewrite},
+    },
+};
 
-const HDR_USER_ID: &str = "x-altair-user-id";
 const DEFAULT_COOKIE_NAME: &str = "altair_web_session";
-const DEFAULT_COOKIE_TTL_SECONDS: u64 = 3600;
-
-#[derive(Deserialize)]
-struct SessionsApiResponse<T> {
-    data: T,
-}
-
-#[derive(Deserialize)]
-struct WebRuntimeLookup {
-    user_id: Uuid,
-    runtime_kind: String,
-    container_id: String,
-    status: String,
-}
+const COOKIE_KIND: &str = "lab_web";
+const BOOTSTRAP_KIND: &str = "lab_web_bootstrap";
+const BOOTSTRAP_TTL_SECONDS: u64 = 60;
+const PATH_COOKIE_PATH: &str = "/lab-api/web";
+const HOST_COOKIE_PATH: &str = "/";
 
 #[derive(Serialize)]
 struct OpenWebSessionResponse {
-    redirect_url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    redirect_url: Option<String>,
+    /// noVNC WebSocket endpoint, for desktop runtimes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    desktop_url: Option<String>,
 }
 
 #[derive(Serialize)]
@@ -67,140 +93,522 @@ struct OpenWebSessionApiResponse {
     data: OpenWebSessionResponse,
 }
 
+#[derive(Serialize)]
+struct CloseWebSessionApiResponse {
+    success: bool,
+}
+
+#[derive(Deserialize)]
+pub struct WebProxyPath {
+    container_id: String,
+    #[serde(default)]
+    path: String,
+}
+
+#[derive(Deserialize)]
+pub struct HostSessionQuery {
+    token: String,
+}
+
+/// Marks a request that arrived on a runtime host in subdomain routing mode.
+#[derive(Clone)]
+pub struct LabWebHost(String);
+
 #[derive(Serialize, Deserialize)]
 struct LabWebCookieClaims {
     kind: String,
     cid: String,
     uid: String,
+    jti: Uuid,
+    iat: usize,
     exp: usize,
+    /// Prefix rewriting for the lab, read from its Pod when the cookie is issued.
+    #[serde(default)]
+    rw: WebRewritePolicy,
+    /// Browser IDE of an `ide` runtime, read from its Pod when the cookie is issued.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ide: Option<IdeKind>,
 }
 
 pub async fn open_web_session(
-    State(_state): State<AppState>,
+    State(state): State<AppState>,
     Path(session_id): Path<Uuid>,
     headers: HeaderMap,
 ) -> Result<Response<Body>, StatusCode> {
-    let user_id = extract_user_id(&headers)?;
-    let runtime = fetch_web_runtime(session_id).await?;
+    let user_id = state.gateway_identity.authenticate(&headers).await?;
+    let runtime = state.sessions_ms.web_runtime(session_id).await?;
 
     if runtime.user_id != user_id {
         return Err(StatusCode::FORBIDDEN);
     }
 
-    if runtime.runtime_kind != "web" {
+    if !matches!(runtime.runtime_kind.as_str(), "web" | "desktop" | "ide") {
         return Err(StatusCode::BAD_REQUEST);
     }
+    let is_desktop = runtime.runtime_kind == "desktop";
 
     if runtime.status != "running" {
         return Err(StatusCode::CONFLICT);
     }
 
-    let cookie_name =
-        std::env::var("LAB_WEB_COOKIE_NAME").unwrap_or_else(|_| DEFAULT_COOKIE_NAME.to_string());
-    let ttl_seconds = std::env::var("LAB_WEB_COOKIE_TTL_SECONDS")
-        .ok()
-        .and_then(|value| value.parse::<u64>().ok())
-        .filter(|value| *value > 0)
-        .unwrap_or(DEFAULT_COOKIE_TTL_SECONDS);
-    let signing_secret = std::env::var("LAB_WEB_COOKIE_SIGNING_SECRET")
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    let claims = LabWebCookieClaims {
-        kind: "lab_web".to_string(),
-        cid: runtime.container_id.clone(),
-        uid: runtime.user_id.to_string(),
-        exp: current_unix_timestamp(ttl_seconds)?,
+    let uid = runtime.user_id.to_string();
+    // A cookie set here would belong to the API host, so runtime hosts get a
+    // short-lived token instead and set their own host-only cookie.
+    let origin = (!is_desktop)
+        .then(|| state.web_routing.runtime_origin(&runtime.container_id))
+        .flatten();
+    let (redirect_url, cookie_value) = match origin {
+        Some(origin) => {
+            let bootstrap = new_lab_web_claims(
+                BOOTSTRAP_KIND,
+                &runtime.container_id,
+                &uid,
+                BOOTSTRAP_TTL_SECONDS,
+            )?;
+            let token = sign_lab_web_claims(&state, &bootstrap)?;
+            (Some(build_host_session_url(origin, &token)), None)
+        }
+        None => {
+            let ttl_seconds = lab_web_cookie_ttl_seconds();
+            let mut claims =
+                new_lab_web_claims(COOKIE_KIND, &runtime.container_id, &uid, ttl_seconds)?;
+            if !is_desktop {
+                if let Some((_, pod)) = find_runtime_pod(&state, &runtime.container_id).await {
+                    claims.rw = resolve_web_rewrite_policy(&pod);
+                    claims.ide = resolve_ide_kind(&pod);
+                }
+            }
+            let token = sign_lab_web_claims(&state, &claims)?;
+            (
+                (!is_desktop).then(|| {
+                    build_open_web_redirect_url(&lab_app_base_url(), &runtime.container_id)
+                }),
+                Some(build_lab_web_cookie(
+                    &lab_web_cookie_name(),
+                    &token,
+                    ttl_seconds,
+                    PATH_COOKIE_PATH,
+                )),
+            )
+        }
     };
 
-    let token = encode(
-        &Header::new(Algorithm::HS256),
-        &claims,
-        &EncodingKey::from_secret(signing_secret.as_bytes()),
-    )
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    let cookie_value = build_lab_web_cookie(&cookie_name, &token, ttl_seconds);
-    let app_base_url =
-        std::env::var("LAB_APP_BASE_URL").unwrap_or_else(|_| "http://localhost:8085".to_string());
-
     let payload = serde_json::to_vec(&OpenWebSessionApiResponse {
         success: true,
         data: OpenWebSessionResponse {
-            redirect_url: build_open_web_redirect_url(&app_base_url, &runtime.container_id),
+            redirect_url,
+            desktop_url: is_desktop.then(|| desktop_proxy::desktop_url(&runtime.container_id)),
         },
     })
     .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    Response::builder()
+    let mut response = Response::builder()
         .status(StatusCode::OK)
-        .header("content-type", "application/json")
-        .header("set-cookie", cookie_value)
+        .header("content-type", "application/json");
+    if let Some(cookie_value) = cookie_value {
+        response = response.header("set-cookie", cookie_value);
+    }
+
+    response
         .body(Body::from(payload))
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
 }
 
-fn extract_user_id(headers: &HeaderMap) -> Result<Uuid, StatusCode> {
-    headers
-        .get(HDR_USER_ID)
-        .and_then(|value| value.to_str().ok())
-        .and_then(|value| Uuid::parse_str(value).ok())
-        .ok_or(StatusCode::UNAUTHORIZED)
+/// Exchanges a bootstrap token for the session cookie on a runtime host.
+/// Each token works once.
+pub async fn open_web_host_session(
+    State(state): State<AppState>,
+    Path(container_id): Path<String>,
+    Query(query): Query<HostSessionQuery>,
+    host: Option<Extension<LabWebHost>>,
+) -> Result<Response<Body>, StatusCode> {
+    if host.is_none_or(|Extension(LabWebHost(host))| host != container_id) {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let bootstrap = state
+        .web_cookie_keys
+        .verify::<LabWebCookieClaims>(&query.token)
+        .map_err(|reason| {
+            warn!(
+                container_id = %container_id,
+                reason = %reason,
+                action = "open_web_host_session",
+                "rejected lab web bootstrap token"
+            );
+            StatusCode::UNAUTHORIZED
+        })?;
+
+    if bootstrap.kind != BOOTSTRAP_KIND || bootstrap.cid != container_id {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    if state
+        .web_cookie_revocations
+        .is_revoked(bootstrap.jti, &bootstrap.cid, bootstrap.iat as u64)
+    {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+    state
+        .web_cookie_revocations
+        .revoke_token(bootstrap.jti, bootstrap.exp as u64);
+
+    let ttl_seconds = lab_web_cookie_ttl_seconds();
+    let mut claims = new_lab_web_claims(COOKIE_KIND, &container_id, &bootstrap.uid, ttl_seconds)?;
+    if let Some((_, pod)) = find_runtime_pod(&state, &container_id).await {
+        claims.ide = resolve_ide_kind(&pod);
+    }
+    let token = sign_lab_web_claims(&state, &claims)?;
+
+    Response::builder()
+        .status(StatusCode::SEE_OTHER)
+        .header(header::LOCATION, "/")
+        .header(header::CACHE_CONTROL, "no-store")
+        .header(header::REFERRER_POLICY, "no-referrer")
+        .header(
+            header::SET_COOKIE,
+            build_lab_web_cookie(
+                &lab_web_cookie_name(),
+                &token,
+                ttl_seconds,
+                HOST_COOKIE_PATH,
+            ),
+        )
+        .body(Body::empty())
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
 }
 
-async fn fetch_web_runtime(session_id: Uuid) -> Result<WebRuntimeLookup, StatusCode> {
-    let sessions_ms_base =
-        std::env::var("SESSIONS_MS_URL").unwrap_or_else(|_| "http://localhost:3003".to_string());
-    let target_url = build_sessions_ms_runtime_lookup_url(&sessions_ms_base, session_id)?;
+/// Serves runtime hosts through the `/web/` routes: the runtime named by the
+/// `Host` header becomes the path prefix the proxy expects.
+pub async fn route_lab_web_hosts(
+    State(state): State<AppState>,
+    mut request: Request,
+    next: Next,
+) -> Response<Body> {
+    let host = request.uri().host().map(str::to_string).or_else(|| {
+        request
+            .headers()
+            .get(header::HOST)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string)
+    });
+    let Some(container_id) = host.and_then(|host| state.web_routing.container_for_host(&host))
+    else {
+        return next.run(request).await;
+    };
 
-    let response = reqwest::Client::new()
-        .get(target_url)
-        .send()
-        .await
-        .map_err(|_| StatusCode::BAD_GATEWAY)?;
+    let Ok(uri) = runtime_host_uri(&container_id, request.uri()) else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+    *request.uri_mut() = uri;
+    request.extensions_mut().insert(LabWebHost(container_id));
 
-    if !response.status().is_success() {
-        return Err(
-            StatusCode::from_u16(response.status().as_u16()).unwrap_or(StatusCode::BAD_GATEWAY)
-        );
+    next.run(request).await
+}
+
+fn runtime_host_uri(container_id: &str, uri: &Uri) -> Result<Uri, axum::http::uri::InvalidUri> {
+    let path = if uri.path() == HOST_SESSION_PATH {
+        format!("/web/host-session/{container_id}")
+    } else {
+        format!("/web/{container_id}{}", uri.path())
+    };
+
+    match uri.query() {
+        Some(query) => format!("{path}?{query}").parse(),
+        None => path.parse(),
+    }
+}
+
+/// Forwards a request to the runtime named in the path, if the lab web cookie grants it.
+pub async fn proxy_web_session(
+    State(state): State<AppState>,
+    Path(target): Path<WebProxyPath>,
+    request: Request,
+) -> Result<Response<Body>, StatusCode> {
+    let cookie_name = lab_web_cookie_name();
+    let claims = verify_lab_web_cookie(
+        &state,
+        request.headers(),
+        &cookie_name,
+        &target.container_id,
+        "web_proxy",
+    )?;
+
+    // Runtime hosts own their origin, so only path-prefixed labs need rewriting.
+    let (cookie_path, rewrite) = if request.extensions().get::<LabWebHost>().is_some() {
+        (HOST_COOKIE_PATH, None)
+    } else {
+        (
+            PATH_COOKIE_PATH,
+            PrefixRewrite::new(claims.rw, &web_path_prefix(&target.container_id)),
+        )
+    };
+    let renewed_cookie = renew_lab_web_cookie(&state, &claims, &cookie_name, cookie_path).await;
+    let ide = match claims.ide {
+        Some(kind) => {
+            state
+                .ide_tokens
+                .access(state.kube_client.clone(), &target.container_id, kind)
+                .await
+        }
+        None => None,
+    };
+    let mut response = if web_proxy::is_websocket_upgrade(request.headers()) {
+        web_proxy::forward_web_socket(
+            &target.container_id,
+            &target.path,
+            &cookie_name,
+            ide.as_ref(),
+            state.web_traffic.clone(),
+            request,
+        )
+        .await?
+    } else {
+        web_proxy::forward_web_request(
+            &target.container_id,
+            &target.path,
+            &cookie_name,
+            rewrite.as_ref(),
+            ide.as_ref(),
+            &state.web_traffic,
+            request,
+        )
+        .await?
+    };
+    if let Some(cookie) = renewed_cookie.and_then(|cookie| cookie.parse().ok()) {
+        response.headers_mut().append(header::SET_COOKIE, cookie);
+    }
+
+    Ok(response)
+}
+
+/// Bridges noVNC to the desktop runtime named in the path, if the lab web
+/// cookie grants it.
+pub async fn proxy_desktop_session(
+    State(state): State<AppState>,
+    Path(container_id): Path<String>,
+    headers: HeaderMap,
+    ws: WebSocketUpgrade,
+) -> Result<Response<Body>, StatusCode> {
+    verify_lab_web_cookie(
+        &state,
+        &headers,
+        &lab_web_cookie_name(),
+        &container_id,
+        "desktop_proxy",
+    )?;
+    let upstream = desktop_proxy::connect_desktop(&container_id).await?;
+
+    Ok(ws.protocols(["binary"]).on_upgrade(move |socket| {
+        desktop_proxy::bridge_desktop_session(socket, upstream, container_id)
+    }))
+}
+
+/// Claims of a valid, unrevoked lab web cookie for `container_id`.
+fn verify_lab_web_cookie(
+    state: &AppState,
+    headers: &HeaderMap,
+    cookie_name: &str,
+    container_id: &str,
+    action: &'static str,
+) -> Result<LabWebCookieClaims, StatusCode> {
+    let token = read_cookie(headers, cookie_name).ok_or(StatusCode::UNAUTHORIZED)?;
+    let claims = state
+        .web_cookie_keys
+        .verify::<LabWebCookieClaims>(token)
+        .map_err(|reason| {
+            warn!(
+                container_id = %container_id,
+                reason = %reason,
+                action,
+                "rejected lab web cookie"
+            );
+            StatusCode::UNAUTHORIZED
+        })?;
+
+    if claims.kind != COOKIE_KIND || claims.cid != container_id {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    if state
+        .web_cookie_revocations
+        .is_revoked(claims.jti, &claims.cid, claims.iat as u64)
+    {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+
+    Ok(claims)
+}
+
+/// Returns a fresh `Set-Cookie` value once `claims` are past half their
+/// lifetime, capped at the runtime's deadline.
+async fn renew_lab_web_cookie(
+    state: &AppState,
+    claims: &LabWebCookieClaims,
+    cookie_name: &str,
+    cookie_path: &str,
+) -> Option<String> {
+    let now = current_unix_timestamp(0).ok()?;
+    let ttl_seconds = lab_web_cookie_ttl_seconds();
+    if !is_due_for_renewal(claims, now, ttl_seconds) {
+        return None;
     }
 
-    let body = response
-        .bytes()
-        .await
-        .map_err(|_| StatusCode::BAD_GATEWAY)?;
+    let (_, pod) = find_runtime_pod(state, &claims.cid).await?;
+    let deadline = usize::try_from(runtime_deadline(&pod)?).ok()?;
+    let exp = renewed_expiry(now, ttl_seconds, deadline, claims.exp)?;
+    let renewed = LabWebCookieClaims {
+        kind: claims.kind.clone(),
+        cid: claims.cid.clone(),
+        uid: claims.uid.clone(),
+        jti: claims.jti,
+        iat: now,
+        exp,
+        rw: claims.rw,
+        ide: claims.ide,
+    };
+
+    let token = state
+        .web_cookie_keys
+        .sign(&renewed)
+        .map_err(|reason| {
+            warn!(reason = %reason, action = "web_proxy", "failed to renew lab web cookie");
+        })
+        .ok()?;
+
+    Some(build_lab_web_cookie(
+        cookie_name,
+        &token,
+        (exp - now) as u64,
+        cookie_path,
+    ))
+}
+
+/// Cookies already cut short by the runtime deadline are not renewed again,
+/// which keeps the Pod lookup off every request near the end of a lab.
+fn is_due_for_renewal(claims: &LabWebCookieClaims, now: usize, ttl_seconds: u64) -> bool {
+    let lifetime = claims.exp.saturating_sub(claims.iat);
 
-    serde_json::from_slice::<SessionsApiResponse<WebRuntimeLookup>>(&body)
-        .map(|payload| payload.data)
-        .map_err(|_| StatusCode::BAD_GATEWAY)
+    lifetime >= ttl_seconds as usize && now >= claims.iat.saturating_add(lifetime / 2)
 }
 
-fn build_sessions_ms_runtime_lookup_url(
-    sessions_ms_base: &str,
-    session_id: Uuid,
-) -> Result<Url, StatusCode> {
-    let mut url = Url::parse(sessions_ms_base).map_err(|_| StatusCode::BAD_GATEWAY)?;
-    validate_sensitive_internal_url(&url)?;
-    url.set_path(&format!("/internal/sessions/{session_id}/web-runtime"));
-    url.set_query(None);
-    Ok(url)
+fn renewed_expiry(
+    now: usize,
+    ttl_seconds: u64,
+    deadline: usize,
+    current_exp: usize,
+) -> Option<usize> {
+    let exp = now.saturating_add(ttl_seconds as usize).min(deadline);
+
+    (exp > current_exp).then_some(exp)
 }
 
-fn validate_sensitive_internal_url(url: &Url) -> Result<(), StatusCode> {
-    match url.scheme() {
-        "https" => Ok(()),
-        "http" if is_loopback_host(url) => Ok(()),
-        _ => Err(StatusCode::BAD_GATEWAY),
+/// Revokes the caller's lab web cookie for the session's runtime and clears it.
+pub async fn close_web_session(
+    State(state): State<AppState>,
+    Path(session_id): Path<Uuid>,
+    headers: HeaderMap,
+) -> Result<Response<Body>, StatusCode> {
+    let user_id = state.gateway_identity.authenticate(&headers).await?;
+    let runtime = state.sessions_ms.web_runtime(session_id).await?;
+
+    if runtime.user_id != user_id {
+        return Err(StatusCode::FORBIDDEN);
     }
+
+    let cookie_name = lab_web_cookie_name();
+    // A missing or already invalid cookie has nothing left to revoke; it is
+    // still cleared below.
+    let claims = read_cookie(&headers, &cookie_name)
+        .and_then(|token| {
+            state
+                .web_cookie_keys
+                .verify::<LabWebCookieClaims>(token)
+                .ok()
+        })
+        .filter(|claims| claims.cid == runtime.container_id && claims.uid == user_id.to_string());
+    if let Some(claims) = claims {
+        state
+            .web_cookie_revocations
+            .revoke_token(claims.jti, claims.exp as u64);
+        info!(
+            session_id = %session_id,
+            container_id = %claims.cid,
+            action = "close_web_session",
+            "revoked lab web cookie"
+        );
+    }
+    // Host-only cookies on runtime hosts never reach this route; the runtime
+    // belongs to this user alone, so revoke everything issued for it.
+    if matches!(*state.web_routing, LabWebRouting::Subdomain { .. }) {
+        state
+            .web_cookie_revocations
+            .revoke_runtime(&runtime.container_id);
+    }
+
+    let payload = serde_json::to_vec(&CloseWebSessionApiResponse { success: true })
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("content-type", "application/json")
+        .header(
+            "set-cookie",
+            build_lab_web_cookie(&cookie_name, "", 0, PATH_COOKIE_PATH),
+        )
+        .body(Body::from(payload))
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
 }
 
-fn is_loopback_host(url: &Url) -> bool {
-    matches!(url.host_str(), Some("localhost" | "127.0.0.1" | "::1"))
+fn lab_web_cookie_name() -> String {
+    std::env::var("LAB_WEB_COOKIE_NAME").unwrap_or_else(|_| DEFAULT_COOKIE_NAME.to_string())
 }
 
-fn build_lab_web_cookie(name: &str, token: &str, ttl_seconds: u64) -> String {
-    format!(
-        "{name}={token}; HttpOnly; Secure; SameSite=Lax; Path=/lab-api/web; Max-Age={ttl_seconds}"
-    )
+fn read_cookie<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|cookies| cookies.split(';'))
+        .find_map(|cookie| {
+            let (key, value) = cookie.trim().split_once('=')?;
+            (key == name).then_some(value)
+        })
+}
+
+/// No `Domain` attribute, so on runtime hosts the cookie stays host-only.
+fn build_lab_web_cookie(name: &str, token: &str, ttl_seconds: u64, path: &str) -> String {
+    format!("{name}={token}; HttpOnly; Secure; SameSite=Lax; Path={path}; Max-Age={ttl_seconds}")
+}
+
+fn new_lab_web_claims(
+    kind: &str,
+    container_id: &str,
+    user_id: &str,
+    ttl_seconds: u64,
+) -> Result<LabWebCookieClaims, StatusCode> {
+    let issued_at = current_unix_timestamp(0)?;
+
+    Ok(LabWebCookieClaims {
+        kind: kind.to_string(),
+        cid: container_id.to_string(),
+        uid: user_id.to_string(),
+        jti: Uuid::new_v4(),
+        iat: issued_at,
+        exp: issued_at.saturating_add(ttl_seconds as usize),
+        rw: WebRewritePolicy::default(),
+        ide: None,
+    })
+}
+
+fn sign_lab_web_claims(
+    state: &AppState,
+    claims: &LabWebCookieClaims,
+) -> Result<String, StatusCode> {
+    state.web_cookie_keys.sign(claims).map_err(|reason| {
+        warn!(reason = %reason, action = "open_web_session", "failed to sign lab web cookie");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })
 }
 
 fn current_unix_timestamp(ttl_seconds: u64) -> Result<usize, StatusCode> {
@@ -211,6 +619,25 @@ fn current_unix_timestamp(ttl_seconds: u64) -> Result<usize, StatusCode> {
     Ok(now.as_secs().saturating_add(ttl_seconds) as usize)
 }
 
+fn build_host_session_url(mut origin: Url, token: &str) -> String {
+    origin.set_path(HOST_SESSION_PATH);
+    origin.query_pairs_mut().append_pair("token", token);
+    origin.into()
+}
+
+fn lab_app_base_url() -> String {
+    std::env::var("LAB_APP_BASE_URL").unwrap_or_else(|_| "http://localhost:8085".to_string())
+}
+
+/// Path under which the browser sees the runtime, e.g. `/web/ctf-runtime-42`.
+fn web_path_prefix(container_id: &str) -> String {
+    let redirect = build_open_web_redirect_url(&lab_app_base_url(), container_id);
+
+    Url::parse(&redirect)
+        .map(|url| url.path().trim_end_matches('/').to_string())
+        .unwrap_or_else(|_| format!("/web/{container_id}"))
+}
+
 fn build_open_web_redirect_url(app_base_url: &str, container_id: &str) -> String {
     format!(
         "{}/web/{}/",
@@ -222,9 +649,11 @@ fn build_open_web_redirect_url(app_base_url: &str, container_id: &str) -> String
 #[cfg(test)]
 mod tests {
     use super::{
-        build_open_web_redirect_url, build_sessions_ms_runtime_lookup_url, is_loopback_host,
+        build_host_session_url, build_open_web_redirect_url, is_due_for_renewal, read_cookie,
+        renewed_expiry, runtime_host_uri, LabWebCookieClaims,
     };
-    use axum::http::StatusCode;
+    use crate::models::WebRewritePolicy;
+    use axum::http::HeaderMap;
     use reqwest::Url;
     use uuid::Uuid;
 
@@ -240,57 +669,73 @@ mod tests {
     }
 
     #[test]
-    fn sessions_ms_lookup_url_keeps_trusted_host() {
-        let session_id = Uuid::parse_str("9bc97880-f720-41c1-9e8a-a2010e2f02c2").unwrap();
-        let url =
-            build_sessions_ms_runtime_lookup_url("https://sessions.example.test/base", session_id)
-                .unwrap();
+    fn runtime_host_requests_are_routed_to_the_web_proxy_paths() {
+        let uri = "/static/app.js?v=1".parse().unwrap();
+        assert_eq!(
+            runtime_host_uri("ctf-runtime-42", &uri).unwrap(),
+            "/web/ctf-runtime-42/static/app.js?v=1"
+        );
 
+        let uri = "/__altair/session?token=a.b.c".parse().unwrap();
         assert_eq!(
-            url,
-            Url::parse(
-                "https://sessions.example.test/internal/sessions/9bc97880-f720-41c1-9e8a-a2010e2f02c2/web-runtime"
-            )
-            .unwrap()
+            runtime_host_uri("ctf-runtime-42", &uri).unwrap(),
+            "/web/host-session/ctf-runtime-42?token=a.b.c"
         );
     }
 
     #[test]
-    fn sessions_ms_lookup_url_accepts_local_http_for_development() {
-        let session_id = Uuid::parse_str("9bc97880-f720-41c1-9e8a-a2010e2f02c2").unwrap();
-        let url =
-            build_sessions_ms_runtime_lookup_url("http://localhost:3003", session_id).unwrap();
+    fn host_session_url_carries_the_bootstrap_token() {
+        let origin = Url::parse("https://ctf-runtime-42.labs.example/").unwrap();
 
         assert_eq!(
-            url,
-            Url::parse(
-                "http://localhost:3003/internal/sessions/9bc97880-f720-41c1-9e8a-a2010e2f02c2/web-runtime"
-            )
-            .unwrap()
+            build_host_session_url(origin, "a.b.c"),
+            "https://ctf-runtime-42.labs.example/__altair/session?token=a.b.c"
         );
     }
 
     #[test]
-    fn sessions_ms_lookup_url_rejects_remote_http() {
-        let session_id = Uuid::parse_str("9bc97880-f720-41c1-9e8a-a2010e2f02c2").unwrap();
-
+    fn cookie_is_renewed_after_half_its_lifetime_up_to_the_runtime_deadline() {
+        let claims = LabWebCookieClaims {
+            kind: "lab_web".to_string(),
+            cid: "ctf-runtime-1".to_string(),
+            uid: Uuid::new_v4().to_string(),
+            jti: Uuid::new_v4(),
+            iat: 10_000,
+            exp: 13_600,
+            rw: WebRewritePolicy::default(),
+            ide: None,
+        };
+
+        assert!(!is_due_for_renewal(&claims, 11_000, 3600));
+        assert!(is_due_for_renewal(&claims, 11_800, 3600));
         assert_eq!(
-            build_sessions_ms_runtime_lookup_url("http://sessions.example.test", session_id)
-                .unwrap_err(),
-            StatusCode::BAD_GATEWAY
+            renewed_expiry(11_800, 3600, 20_000, claims.exp),
+            Some(15_400)
         );
+        assert_eq!(
+            renewed_expiry(11_800, 3600, 14_000, claims.exp),
+            Some(14_000)
+        );
+        assert_eq!(renewed_expiry(11_800, 3600, 13_000, claims.exp), None);
+
+        let capped = LabWebCookieClaims {
+            iat: 11_800,
+            exp: 14_000,
+            ..claims
+        };
+        assert!(!is_due_for_renewal(&capped, 13_500, 3600));
     }
 
     #[test]
-    fn loopback_detection_accepts_local_targets_only() {
-        assert!(is_loopback_host(
-            &Url::parse("http://localhost:3003").unwrap()
-        ));
-        assert!(is_loopback_host(
-            &Url::parse("http://127.0.0.1:3003").unwrap()
-        ));
-        assert!(!is_loopback_host(
-            &Url::parse("https://sessions.example.test").unwrap()
-        ));
+    fn lab_web_cookie_is_found_among_other_cookies() {
+        let mut headers = HeaderMap::new();
+        headers.append("cookie", "theme=dark".parse().unwrap());
+        headers.append(
+            "cookie",
+            "PHPSESSID=abc; altair_web_session=a.b.c".parse().unwrap(),
+        );
+
+        assert_eq!(read_cookie(&headers, "altair_web_session"), Some("a.b.c"));
+        assert_eq!(read_cookie(&headers, "missing"), None);
     }
 }
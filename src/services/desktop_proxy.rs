@@ -0,0 +1,173 @@
+/**
+ * @file desktop_proxy — VNC over WebSocket for desktop lab runtimes.
+ *
+ * @remarks
+ * Desktop labs run an image with a VNC server. The learner's browser runs
+ * noVNC, which speaks RFB over a WebSocket; lab-api plays the websockify
+ * part and bridges that WebSocket to the runtime's VNC Service
+ * (`{container_id}-desktop`) once the route has verified the lab web
+ * session cookie.
+ *
+ * Responsibilities:
+ *
+ *  - Build the `desktop_url` noVNC connects to
+ *  - Resolve and connect to the runtime's VNC Service
+ *  - Copy binary frames to the VNC server and its output back as frames
+ *
+ * Key characteristics:
+ *
+ *  - Upstream addresses come from `LAB_DESKTOP_UPSTREAM_ADDR_TEMPLATE`
+ *    (default `{service}.{namespace}.svc.cluster.local:5900`)
+ *  - `desktop_url` is built from `WEBSHELL_BASE_URL`, like the terminal URL
+ *  - The upstream connection is opened before the upgrade, so an
+ *    unreachable desktop fails the handshake with `502`
+ *
+ * @packageDocumentation
+ */
+use std::time::Duration;
+
+use axum::{
+    extract::ws::{Message, WebSocket},
+    http::StatusCode,
+};
+use futures::{SinkExt, StreamExt};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+use tracing::{info, warn};
+
+use crate::services::{
+    spawn::{build_desktop_service_name, namespace_for_delivery, DESKTOP_VNC_PORT},
+    web_proxy::is_runtime_name,
+};
+
+const CONNECT_TIMEOUT_SECS: u64 = 5;
+const READ_BUFFER_BYTES: usize = 16 * 1024;
+
+/// WebSocket URL noVNC connects to for `container_id`.
+pub fn desktop_url(container_id: &str) -> String {
+    let webshell_base_url =
+        std::env::var("WEBSHELL_BASE_URL").unwrap_or_else(|_| "ws://localhost:8085".to_string());
+
+    format!(
+        "{}/web/desktop/{}",
+        webshell_base_url.trim_end_matches('/'),
+        container_id
+    )
+}
+
+/// Opens a TCP connection to the runtime's VNC server.
+pub async fn connect_desktop(container_id: &str) -> Result<TcpStream, StatusCode> {
+    let addr = build_upstream_addr(container_id)?;
+
+    match timeout(
+        Duration::from_secs(CONNECT_TIMEOUT_SECS),
+        TcpStream::connect(&addr),
+    )
+    .await
+    {
+        Ok(Ok(stream)) => Ok(stream),
+        Ok(Err(error)) => {
+            warn!(
+                container_id = %container_id,
+                error = %error,
+                action = "desktop_proxy",
+                "desktop runtime did not answer"
+            );
+            Err(StatusCode::BAD_GATEWAY)
+        }
+        Err(_) => {
+            warn!(
+                container_id = %container_id,
+                action = "desktop_proxy",
+                "timed out connecting to desktop runtime"
+            );
+            Err(StatusCode::GATEWAY_TIMEOUT)
+        }
+    }
+}
+
+/// Copies RFB bytes between noVNC and the VNC server until either side closes.
+pub async fn bridge_desktop_session(socket: WebSocket, upstream: TcpStream, container_id: String) {
+    let (mut ws_tx, mut ws_rx) = socket.split();
+    let (mut vnc_rx, mut vnc_tx) = upstream.into_split();
+
+    let to_desktop = async {
+        while let Some(Ok(message)) = ws_rx.next().await {
+            let data = match message {
+                Message::Binary(data) => data,
+                Message::Close(_) => break,
+                // RFB is binary; noVNC never sends text frames.
+                _ => continue,
+            };
+            if vnc_tx.write_all(&data).await.is_err() {
+                break;
+            }
+        }
+        let _ = vnc_tx.shutdown().await;
+    };
+
+    let to_browser = async {
+        let mut buffer = vec![0; READ_BUFFER_BYTES];
+        loop {
+            match vnc_rx.read(&mut buffer).await {
+                Ok(0) | Err(_) => break,
+                Ok(read) => {
+                    let frame = buffer[..read].to_vec();
+                    if ws_tx.send(Message::Binary(frame.into())).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+        let _ = ws_tx.send(Message::Close(None)).await;
+    };
+
+    tokio::select! {
+        _ = to_desktop => {}
+        _ = to_browser => {}
+    }
+
+    info!(
+        container_id = %container_id,
+        action = "desktop_proxy",
+        "desktop session closed"
+    );
+}
+
+fn build_upstream_addr(container_id: &str) -> Result<String, StatusCode> {
+    if !is_runtime_name(container_id) {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let template = std::env::var("LAB_DESKTOP_UPSTREAM_ADDR_TEMPLATE").unwrap_or_else(|_| {
+        format!("{{service}}.{{namespace}}.svc.cluster.local:{DESKTOP_VNC_PORT}")
+    });
+
+    Ok(template
+        .replace("{service}", &build_desktop_service_name(container_id))
+        .replace("{namespace}", &namespace_for_delivery("desktop")))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::StatusCode;
+
+    use super::{build_upstream_addr, desktop_url};
+
+    #[test]
+    fn upstream_addr_targets_the_runtime_desktop_service() {
+        assert_eq!(
+            build_upstream_addr("ctf-runtime-42").unwrap(),
+            "ctf-runtime-42-desktop.labs-web.svc.cluster.local:5900"
+        );
+        assert_eq!(
+            build_upstream_addr("../kube-system").unwrap_err(),
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            desktop_url("ctf-runtime-42"),
+            "ws://localhost:8085/web/desktop/ctf-runtime-42"
+        );
+    }
+}
@@ -0,0 +1,455 @@
+/**
+ * @file gateway_identity — verified caller identity for user-scoped routes.
+ *
+ * @remarks
+ * Resolves the calling user from a gateway-issued JWT instead of trusting a
+ * plain `x-altair-user-id` header that anyone reaching lab-api could forge.
+ *
+ * Configuration:
+ *
+ *  - `GATEWAY_JWT_JWKS_URL` → verify RS256/ES256 tokens against a JWKS
+ *  - `GATEWAY_JWT_HS256_SECRET` → verify HS256 tokens with a shared secret
+ *  - `GATEWAY_JWT_ISSUER` / `GATEWAY_JWT_AUDIENCE` → optional `iss` / `aud` checks
+ *  - `GATEWAY_JWT_USER_CLAIM` → claim holding the user id (default `sub`)
+ *  - `GATEWAY_JWKS_CACHE_SECS` → JWKS cache lifetime (default 300)
+ *  - `LAB_API_TRUST_USER_ID_HEADER` → accept `x-altair-user-id` when no token
+ *    is sent; defaults to `LAB_API_LOCAL_MODE` and is refused outside it
+ *
+ * Key characteristics:
+ *
+ *  - Tokens are read from `Authorization: Bearer …`
+ *  - A token that is present but invalid is always rejected, never bypassed
+ *  - Unknown `kid`s trigger a rate-limited JWKS refresh to follow key rotation
+ *  - The JWKS URL must be HTTPS, or HTTP on loopback for development
+ *  - With no verification key and no header fallback every user-scoped route
+ *    answers `401`; this is logged as an error at startup
+ *
+ * @packageDocumentation
+ */
+use std::time::{Duration, Instant};
+
+use axum::http::{header, HeaderMap, StatusCode};
+use jsonwebtoken::{
+    decode, decode_header,
+    jwk::{Jwk, JwkSet},
+    Algorithm, DecodingKey, Validation,
+};
+use reqwest::Url;
+use serde_json::{Map, Value};
+use tokio::sync::RwLock;
+use tracing::{error, warn};
+use uuid::Uuid;
+
+const HDR_USER_ID: &str = "x-altair-user-id";
+const DEFAULT_USER_CLAIM: &str = "sub";
+const DEFAULT_JWKS_CACHE_SECS: u64 = 300;
+const JWKS_MIN_REFRESH_SECS: u64 = 30;
+const JWKS_TIMEOUT_SECS: u64 = 5;
+const ASYMMETRIC_ALGORITHMS: [Algorithm; 2] = [Algorithm::RS256, Algorithm::ES256];
+
+enum GatewayJwtKeys {
+    Hs256(DecodingKey),
+    Jwks(JwksCache),
+}
+
+struct JwksCache {
+    url: Url,
+    ttl: Duration,
+    client: reqwest::Client,
+    cached: RwLock<Option<(JwkSet, Instant)>>,
+}
+
+impl JwksCache {
+    async fn key(&self, kid: Option<&str>) -> Result<DecodingKey, String> {
+        if let Some(key) = self.cached_key(kid, self.ttl).await {
+            return key;
+        }
+
+        let mut cached = self.cached.write().await;
+        // Another request may have refreshed the set while we waited.
+        let fresh = cached.as_ref().is_some_and(|(_, fetched_at)| {
+            fetched_at.elapsed() < Duration::from_secs(JWKS_MIN_REFRESH_SECS)
+        });
+        if !fresh {
+            *cached = Some((self.fetch().await?, Instant::now()));
+        }
+        let (keys, _) = cached.as_ref().ok_or("JWKS unavailable")?;
+
+        find_jwk(keys, kid)
+            .ok_or_else(|| format!("no JWKS key for kid `{}`", kid.unwrap_or("-")))
+            .and_then(decoding_key)
+    }
+
+    async fn cached_key(
+        &self,
+        kid: Option<&str>,
+        ttl: Duration,
+    ) -> Option<Result<DecodingKey, String>> {
+        let cached = self.cached.read().await;
+        let (keys, fetched_at) = cached.as_ref()?;
+        if fetched_at.elapsed() >= ttl {
+            return None;
+        }
+        find_jwk(keys, kid).map(decoding_key)
+    }
+
+    async fn fetch(&self) -> Result<JwkSet, String> {
+        let response = self
+            .client
+            .get(self.url.clone())
+            .send()
+            .await
+            .map_err(|error| format!("failed to fetch JWKS: {error}"))?;
+        if !response.status().is_success() {
+            return Err(format!("JWKS endpoint answered {}", response.status()));
+        }
+
+        response
+            .json::<JwkSet>()
+            .await
+            .map_err(|error| format!("JWKS is not valid: {error}"))
+    }
+}
+
+fn find_jwk<'a>(keys: &'a JwkSet, kid: Option<&str>) -> Option<&'a Jwk> {
+    match kid {
+        Some(kid) => keys.find(kid),
+        // Without a kid only an unambiguous single-key set can be used.
+        None if keys.keys.len() == 1 => keys.keys.first(),
+        None => None,
+    }
+}
+
+fn decoding_key(jwk: &Jwk) -> Result<DecodingKey, String> {
+    DecodingKey::from_jwk(jwk).map_err(|error| format!("unusable JWKS key: {error}"))
+}
+
+/// Authenticates callers of user-scoped routes.
+pub struct GatewayIdentity {
+    keys: Option<GatewayJwtKeys>,
+    issuer: Option<String>,
+    audience: Option<String>,
+    user_claim: String,
+    trust_user_header: bool,
+}
+
+impl GatewayIdentity {
+    pub fn from_env(local_mode: bool) -> Self {
+        let keys = match (
+            std::env::var("GATEWAY_JWT_JWKS_URL"),
+            std::env::var("GATEWAY_JWT_HS256_SECRET"),
+        ) {
+            (Ok(url), _) => match jwks_url(&url) {
+                Ok(url) => Some(GatewayJwtKeys::Jwks(JwksCache {
+                    url,
+                    ttl: Duration::from_secs(
+                        std::env::var("GATEWAY_JWKS_CACHE_SECS")
+                            .ok()
+                            .and_then(|value| value.trim().parse().ok())
+                            .unwrap_or(DEFAULT_JWKS_CACHE_SECS),
+                    ),
+                    client: reqwest::Client::builder()
+                        .timeout(Duration::from_secs(JWKS_TIMEOUT_SECS))
+                        .build()
+                        .unwrap_or_default(),
+                    cached: RwLock::new(None),
+                })),
+                Err(reason) => {
+                    warn!(reason = %reason, "Ignoring GATEWAY_JWT_JWKS_URL");
+                    None
+                }
+            },
+            (Err(_), Ok(secret)) => Some(GatewayJwtKeys::Hs256(DecodingKey::from_secret(
+                secret.as_bytes(),
+            ))),
+            _ => None,
+        };
+
+        let trust_user_header = trust_user_header(
+            crate::parse_bool_env("LAB_API_TRUST_USER_ID_HEADER", local_mode),
+            local_mode,
+        );
+        if keys.is_none() && !trust_user_header {
+            error!(
+                action = "gateway_identity",
+                "No gateway JWT verifier is configured; set GATEWAY_JWT_JWKS_URL or \
+                 GATEWAY_JWT_HS256_SECRET, or every user-scoped route answers 401"
+            );
+        }
+
+        Self {
+            keys,
+            issuer: std::env::var("GATEWAY_JWT_ISSUER").ok(),
+            audience: std::env::var("GATEWAY_JWT_AUDIENCE").ok(),
+            user_claim: std::env::var("GATEWAY_JWT_USER_CLAIM")
+                .unwrap_or_else(|_| DEFAULT_USER_CLAIM.to_string()),
+            trust_user_header,
+        }
+    }
+
+    /// The verified user id, or `401 Unauthorized`.
+    pub async fn authenticate(&self, headers: &HeaderMap) -> Result<Uuid, StatusCode> {
+        if let Some(token) = bearer_token(headers) {
+            return self.verify(token).await.map_err(|reason| {
+                warn!(reason = %reason, action = "authenticate", "rejected gateway token");
+                StatusCode::UNAUTHORIZED
+            });
+        }
+
+        if self.trust_user_header {
+            return headers
+                .get(HDR_USER_ID)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| Uuid::parse_str(value).ok())
+                .ok_or(StatusCode::UNAUTHORIZED);
+        }
+
+        Err(StatusCode::UNAUTHORIZED)
+    }
+
+    async fn verify(&self, token: &str) -> Result<Uuid, String> {
+        let keys = self
+            .keys
+            .as_ref()
+            .ok_or("no gateway JWT verification key is configured")?;
+        let header = decode_header(token).map_err(|error| format!("malformed token: {error}"))?;
+
+        let (key, algorithm) = match keys {
+            GatewayJwtKeys::Hs256(key) => (key.clone(), Algorithm::HS256),
+            GatewayJwtKeys::Jwks(jwks) => {
+                if !ASYMMETRIC_ALGORITHMS.contains(&header.alg) {
+                    return Err(format!("algorithm {:?} is not accepted", header.alg));
+                }
+                (jwks.key(header.kid.as_deref()).await?, header.alg)
+            }
+        };
+
+        // HS256 is fixed; JWKS tokens are limited to RS256/ES256 and must match their key type.
+        let mut validation = Validation::new(algorithm);
+        if let Some(issuer) = &self.issuer {
+            validation.set_issuer(&[issuer]);
+        }
+        match &self.audience {
+            Some(audience) => validation.set_audience(&[audience]),
+            None => validation.validate_aud = false,
+        }
+
+        let claims = decode::<Map<String, Value>>(token, &key, &validation)
+            .map_err(|error| format!("invalid token: {error}"))?
+            .claims;
+        claims
+            .get(&self.user_claim)
+            .and_then(Value::as_str)
+            .and_then(|value| Uuid::parse_str(value).ok())
+            .ok_or_else(|| format!("claim `{}` is not a user id", self.user_claim))
+    }
+}
+
+/// The `x-altair-user-id` fallback is forgeable, so it is only honoured in local mode.
+fn trust_user_header(requested: bool, local_mode: bool) -> bool {
+    if requested && !local_mode {
+        error!(
+            action = "gateway_identity",
+            "LAB_API_TRUST_USER_ID_HEADER is only honoured with LAB_API_LOCAL_MODE=true; ignoring it"
+        );
+        return false;
+    }
+    requested
+}
+
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    let value = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, token) = value.split_once(' ')?;
+    scheme
+        .eq_ignore_ascii_case("bearer")
+        .then(|| token.trim())
+        .filter(|token| !token.is_empty())
+}
+
+fn jwks_url(raw: &str) -> Result<Url, String> {
+    let url = Url::parse(raw).map_err(|error| format!("invalid URL: {error}"))?;
+    let loopback = matches!(url.host_str(), Some("localhost" | "127.0.0.1" | "::1"));
+    match url.scheme() {
+        "https" => Ok(url),
+        "http" if loopback => Ok(url),
+        _ => Err("JWKS must be served over HTTPS".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::{routing::get, Json, Router};
+    use jsonwebtoken::{encode, Algorithm, DecodingKey, EncodingKey, Header};
+    use p256::ecdsa::SigningKey;
+    use p256::pkcs8::EncodePrivateKey;
+    use serde_json::json;
+    use tokio::sync::RwLock;
+    use uuid::Uuid;
+
+    use super::{jwks_url, trust_user_header, GatewayIdentity, GatewayJwtKeys, JwksCache};
+
+    fn identity(keys: Option<GatewayJwtKeys>, trust_user_header: bool) -> GatewayIdentity {
+        GatewayIdentity {
+            keys,
+            issuer: Some("altair-gateway".to_string()),
+            audience: None,
+            user_claim: "sub".to_string(),
+            trust_user_header,
+        }
+    }
+
+    fn claims(user_id: Uuid) -> serde_json::Value {
+        json!({
+            "sub": user_id.to_string(),
+            "iss": "altair-gateway",
+            "exp": chrono::Utc::now().timestamp() + 300,
+        })
+    }
+
+    fn bearer(token: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("authorization", format!("Bearer {token}").parse().unwrap());
+        headers
+    }
+
+    /// Serves `jwks` on a loopback port and counts how often it is fetched.
+    async fn jwks_server(jwks: serde_json::Value) -> (String, Arc<AtomicUsize>) {
+        let hits = Arc::new(AtomicUsize::new(0));
+        let counter = hits.clone();
+        let app = Router::new().route(
+            "/jwks.json",
+            get(move || {
+                counter.fetch_add(1, Ordering::SeqCst);
+                let jwks = jwks.clone();
+                async move { Json(jwks) }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        (format!("http://{address}/jwks.json"), hits)
+    }
+
+    fn jwks_keys(url: &str) -> GatewayJwtKeys {
+        GatewayJwtKeys::Jwks(JwksCache {
+            url: jwks_url(url).unwrap(),
+            ttl: Duration::from_secs(300),
+            client: reqwest::Client::new(),
+            cached: RwLock::new(None),
+        })
+    }
+
+    #[tokio::test]
+    async fn es256_tokens_are_verified_against_a_cached_jwks() {
+        let signing_key = SigningKey::random(&mut rand_core::OsRng);
+        let der = signing_key.to_pkcs8_der().unwrap();
+        let jwk: serde_json::Value = serde_json::from_str(
+            &p256::PublicKey::from(signing_key.verifying_key()).to_jwk_string(),
+        )
+        .unwrap();
+        let mut jwk = jwk.as_object().unwrap().clone();
+        jwk.insert("kid".to_string(), json!("gw-1"));
+        jwk.insert("alg".to_string(), json!("ES256"));
+        let (url, hits) = jwks_server(json!({ "keys": [jwk] })).await;
+        let identity = identity(Some(jwks_keys(&url)), false);
+
+        let user_id = Uuid::new_v4();
+        let mut header = Header::new(Algorithm::ES256);
+        header.kid = Some("gw-1".to_string());
+        let token = encode(
+            &header,
+            &claims(user_id),
+            &EncodingKey::from_ec_der(der.as_bytes()),
+        )
+        .unwrap();
+
+        assert_eq!(identity.authenticate(&bearer(&token)).await, Ok(user_id));
+        assert_eq!(identity.authenticate(&bearer(&token)).await, Ok(user_id));
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+
+        let other_key = SigningKey::random(&mut rand_core::OsRng)
+            .to_pkcs8_der()
+            .unwrap();
+        let forged = encode(
+            &header,
+            &claims(user_id),
+            &EncodingKey::from_ec_der(other_key.as_bytes()),
+        )
+        .unwrap();
+        assert_eq!(
+            identity.authenticate(&bearer(&forged)).await,
+            Err(StatusCode::UNAUTHORIZED)
+        );
+    }
+
+    #[tokio::test]
+    async fn hs256_tokens_need_the_shared_secret_and_issuer() {
+        let identity = identity(
+            Some(GatewayJwtKeys::Hs256(DecodingKey::from_secret(b"gateway"))),
+            false,
+        );
+        let user_id = Uuid::new_v4();
+        let sign = |claims: &serde_json::Value, secret: &[u8]| {
+            encode(
+                &Header::default(),
+                claims,
+                &EncodingKey::from_secret(secret),
+            )
+            .unwrap()
+        };
+
+        let token = sign(&claims(user_id), b"gateway");
+        assert_eq!(identity.authenticate(&bearer(&token)).await, Ok(user_id));
+
+        let wrong_secret = sign(&claims(user_id), b"guess");
+        assert!(identity.authenticate(&bearer(&wrong_secret)).await.is_err());
+
+        let mut foreign = claims(user_id);
+        foreign["iss"] = json!("someone-else");
+        assert!(identity
+            .authenticate(&bearer(&sign(&foreign, b"gateway")))
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn user_id_header_is_only_trusted_when_enabled() {
+        let user_id = Uuid::new_v4();
+        let mut headers = HeaderMap::new();
+        headers.insert("x-altair-user-id", user_id.to_string().parse().unwrap());
+
+        assert_eq!(
+            identity(None, false).authenticate(&headers).await,
+            Err(StatusCode::UNAUTHORIZED)
+        );
+        assert_eq!(
+            identity(None, true).authenticate(&headers).await,
+            Ok(user_id)
+        );
+
+        // A bad token is not rescued by the header.
+        headers.insert("authorization", "Bearer not-a-jwt".parse().unwrap());
+        assert!(identity(None, true).authenticate(&headers).await.is_err());
+    }
+
+    #[test]
+    fn user_id_header_is_refused_outside_local_mode() {
+        assert!(trust_user_header(true, true));
+        assert!(!trust_user_header(false, true));
+        assert!(!trust_user_header(true, false));
+        assert!(!trust_user_header(false, false));
+    }
+
+    #[test]
+    fn jwks_must_be_fetched_over_https() {
+        assert!(jwks_url("https://gateway.example.test/.well-known/jwks.json").is_ok());
+        assert!(jwks_url("http://localhost:8080/jwks.json").is_ok());
+        assert!(jwks_url("http://gateway.example.test/jwks.json").is_err());
+    }
+}
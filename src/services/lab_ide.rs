@@ -0,0 +1,393 @@
+/**
+ * @file lab_ide — browser IDE sidecars for `ide` lab runtimes.
+ *
+ * @remarks
+ * IDE labs run the lab image as a keep-alive container and start a browser
+ * IDE next to it. Both containers share a workspace volume, seeded from the
+ * lab image's `/workspace`, and the IDE is served through the web proxy.
+ *
+ * Supported IDEs (`IdeKind`):
+ *
+ *  - `code-server` → image from `LAB_IDE_CODE_SERVER_IMAGE`
+ *    (default `codercom/code-server:4.96.4`)
+ *  - `jupyter` → image from `LAB_IDE_JUPYTER_IMAGE`
+ *    (default `quay.io/jupyter/base-notebook:2025-01-06`); its pages use
+ *    root-relative URLs, so it needs subdomain routing
+ *
+ * Key characteristics:
+ *
+ *  - Every runtime gets its own random IDE token, kept in a Secret
+ *    (`{container_id}-ide`); the lab web cookie only names the IDE kind
+ *  - The web proxy reads the token from the Secret, caches it per runtime for
+ *    a few minutes, and signs learners in to the IDE with it once the lab web
+ *    cookie is verified; the token never reaches the browser
+ *  - Other runtimes in the namespace cannot reach the IDE without the token
+ *  - Commands typed in Jupyter terminals feed the terminal capture pipeline;
+ *    code-server terminals use a binary protocol and are not captured
+ *
+ * @packageDocumentation
+ */
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use axum::http::{header, HeaderMap, HeaderValue};
+use k8s_openapi::{
+    api::core::v1::{
+        Container, ContainerPort, EmptyDirVolumeSource, EnvVar, EnvVarSource, Pod,
+        ResourceRequirements, Secret, SecretKeySelector, Volume, VolumeMount,
+    },
+    apimachinery::pkg::api::resource::Quantity,
+    ByteString,
+};
+use kube::{Api, Client};
+use rand::{distr::Alphanumeric, RngExt};
+use tracing::warn;
+
+use crate::{models::IdeKind, services::spawn::namespace_for_delivery};
+
+pub const IDE_LABEL: &str = "ide";
+pub const IDE_PORT: i32 = 8080;
+pub const WORKSPACE_PATH: &str = "/workspace";
+
+const IDE_CONTAINER_NAME: &str = "ide";
+const WORKSPACE_SEED_CONTAINER_NAME: &str = "workspace-seed";
+const WORKSPACE_VOLUME: &str = "workspace";
+const WORKSPACE_SEED_PATH: &str = "/altair-workspace";
+const TOKEN_KEY: &str = "token";
+const TOKEN_CHARS: usize = 40;
+const CODE_SERVER_SESSION_COOKIE: &str = "code-server-session";
+const DEFAULT_CODE_SERVER_IMAGE: &str = "codercom/code-server:4.96.4";
+const DEFAULT_JUPYTER_IMAGE: &str = "quay.io/jupyter/base-notebook:2025-01-06";
+/// How long a runtime's IDE token is reused before its Secret is read again.
+const TOKEN_CACHE_TTL: Duration = Duration::from_secs(300);
+
+/// Copies the lab image's workspace into the shared volume before either
+/// container starts; the volume would otherwise hide it.
+const WORKSPACE_SEED_SCRIPT: &str = r#"
+if [ -d /workspace ]; then
+  cp -R /workspace/. /altair-workspace/
+fi
+chmod -R a+rwX /altair-workspace
+"#;
+
+/// The IDE a runtime serves, and the token it was started with.
+#[derive(Clone, Debug, PartialEq)]
+pub struct IdeAccess {
+    pub kind: IdeKind,
+    pub token: String,
+}
+
+impl IdeAccess {
+    /// Signs a proxied request in to the IDE.
+    pub fn authorize(&self, headers: &mut HeaderMap) {
+        match self.kind {
+            // Started with `HASHED_PASSWORD`, code-server compares its session
+            // cookie with that value as-is.
+            IdeKind::CodeServer => {
+                let cookie = format!("{CODE_SERVER_SESSION_COOKIE}={}", self.token);
+                if let Ok(value) = HeaderValue::from_str(&cookie) {
+                    headers.append(header::COOKIE, value);
+                }
+            }
+            IdeKind::Jupyter => {
+                if let Ok(value) = HeaderValue::from_str(&format!("token {}", self.token)) {
+                    headers.insert(header::AUTHORIZATION, value);
+                }
+            }
+        }
+    }
+
+    /// Whether a WebSocket at `path` (relative to the runtime) is an IDE terminal
+    /// whose input can be read.
+    pub fn is_terminal_socket(&self, path: &str) -> bool {
+        match self.kind {
+            IdeKind::CodeServer => false,
+            IdeKind::Jupyter => path
+                .trim_start_matches('/')
+                .starts_with("terminals/websocket/"),
+        }
+    }
+}
+
+/// Keystrokes in one Jupyter terminal client frame, e.g. `["stdin", "ls\r"]`.
+pub fn terminal_stdin(frame: &str) -> Option<String> {
+    let message: Vec<serde_json::Value> = serde_json::from_str(frame).ok()?;
+    match message.as_slice() {
+        [kind, input, ..] if kind == "stdin" => input.as_str().map(str::to_string),
+        _ => None,
+    }
+}
+
+pub fn build_ide_secret_name(pod_name: &str) -> String {
+    format!("{pod_name}-ide")
+}
+
+/// Unknown values disable the IDE rather than guessing which one runs.
+pub fn resolve_ide_kind(pod: &Pod) -> Option<IdeKind> {
+    let raw = pod.metadata.labels.as_ref()?.get(IDE_LABEL)?;
+
+    serde_json::from_value(serde_json::Value::String(raw.clone()))
+        .map_err(|_| {
+            warn!(
+                pod_name = ?pod.metadata.name,
+                value = %raw,
+                action = "lab_ide",
+                "unknown IDE kind on runtime"
+            );
+        })
+        .ok()
+}
+
+/// IDE tokens by runtime, read from their Secrets on first use.
+#[derive(Default)]
+pub struct IdeTokenCache {
+    tokens: Mutex<HashMap<String, (String, Instant)>>,
+}
+
+impl IdeTokenCache {
+    /// Access to the `kind` IDE of runtime `container_id`, or `None` when its
+    /// token cannot be read.
+    pub async fn access(
+        &self,
+        client: Client,
+        container_id: &str,
+        kind: IdeKind,
+    ) -> Option<IdeAccess> {
+        let token = match self.cached(container_id) {
+            Some(token) => token,
+            None => {
+                let namespace = namespace_for_delivery("ide");
+                let token = load_ide_token(client, &namespace, container_id).await?;
+                self.remember(container_id, &token);
+                token
+            }
+        };
+
+        Some(IdeAccess { kind, token })
+    }
+
+    /// Drops a stopped runtime's token.
+    pub fn forget(&self, container_id: &str) {
+        self.lock().remove(container_id);
+    }
+
+    fn cached(&self, container_id: &str) -> Option<String> {
+        self.lock()
+            .get(container_id)
+            .filter(|(_, cached_at)| cached_at.elapsed() < TOKEN_CACHE_TTL)
+            .map(|(token, _)| token.clone())
+    }
+
+    fn remember(&self, container_id: &str, token: &str) {
+        let mut tokens = self.lock();
+        tokens.retain(|_, (_, cached_at)| cached_at.elapsed() < TOKEN_CACHE_TTL);
+        tokens.insert(
+            container_id.to_string(),
+            (token.to_string(), Instant::now()),
+        );
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, (String, Instant)>> {
+        self.tokens.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+async fn load_ide_token(client: Client, namespace: &str, container_id: &str) -> Option<String> {
+    let secret_name = build_ide_secret_name(container_id);
+    let secrets: Api<Secret> = Api::namespaced(client, namespace);
+    let secret = match secrets.get_opt(&secret_name).await {
+        Ok(secret) => secret?,
+        Err(error) => {
+            warn!(
+                namespace = %namespace,
+                secret_name = %secret_name,
+                error = %error,
+                action = "lab_ide",
+                "failed to read IDE token"
+            );
+            return None;
+        }
+    };
+    let token = secret.data?.remove(TOKEN_KEY)?;
+
+    String::from_utf8(token.0).ok()
+}
+
+pub fn new_ide_token() -> String {
+    rand::rng()
+        .sample_iter(Alphanumeric)
+        .take(TOKEN_CHARS)
+        .map(char::from)
+        .collect()
+}
+
+pub fn build_ide_secret(pod_name: &str, token: &str) -> Secret {
+    Secret {
+        metadata: kube::core::ObjectMeta {
+            name: Some(build_ide_secret_name(pod_name)),
+            ..Default::default()
+        },
+        type_: Some("Opaque".to_string()),
+        data: Some(BTreeMap::from([(
+            TOKEN_KEY.to_string(),
+            ByteString(token.as_bytes().to_vec()),
+        )])),
+        ..Default::default()
+    }
+}
+
+pub fn workspace_volume() -> Volume {
+    Volume {
+        name: WORKSPACE_VOLUME.into(),
+        empty_dir: Some(EmptyDirVolumeSource::default()),
+        ..Default::default()
+    }
+}
+
+pub fn workspace_mount() -> VolumeMount {
+    VolumeMount {
+        name: WORKSPACE_VOLUME.into(),
+        mount_path: WORKSPACE_PATH.into(),
+        ..Default::default()
+    }
+}
+
+pub fn build_workspace_seed_container(lab_image: &str) -> Container {
+    Container {
+        name: WORKSPACE_SEED_CONTAINER_NAME.into(),
+        image: Some(lab_image.to_string()),
+        command: Some(vec!["/bin/sh".to_string(), "-c".to_string()]),
+        args: Some(vec![WORKSPACE_SEED_SCRIPT.to_string()]),
+        volume_mounts: Some(vec![VolumeMount {
+            name: WORKSPACE_VOLUME.into(),
+            mount_path: WORKSPACE_SEED_PATH.into(),
+            ..Default::default()
+        }]),
+        ..Default::default()
+    }
+}
+
+pub fn build_ide_container(kind: IdeKind, pod_name: &str) -> Container {
+    let (image, token_env, args) = match kind {
+        IdeKind::CodeServer => (
+            std::env::var("LAB_IDE_CODE_SERVER_IMAGE")
+                .unwrap_or_else(|_| DEFAULT_CODE_SERVER_IMAGE.to_string()),
+            "HASHED_PASSWORD",
+            vec![
+                "--bind-addr".to_string(),
+                format!("0.0.0.0:{IDE_PORT}"),
+                "--auth".to_string(),
+                "password".to_string(),
+                "--disable-telemetry".to_string(),
+                WORKSPACE_PATH.to_string(),
+            ],
+        ),
+        IdeKind::Jupyter => (
+            std::env::var("LAB_IDE_JUPYTER_IMAGE")
+                .unwrap_or_else(|_| DEFAULT_JUPYTER_IMAGE.to_string()),
+            "JUPYTER_TOKEN",
+            vec![
+                "start-notebook.py".to_string(),
+                "--ServerApp.ip=0.0.0.0".to_string(),
+                format!("--ServerApp.port={IDE_PORT}"),
+                format!("--ServerApp.root_dir={WORKSPACE_PATH}"),
+            ],
+        ),
+    };
+
+    Container {
+        name: IDE_CONTAINER_NAME.into(),
+        image: Some(image),
+        args: Some(args),
+        env: Some(vec![EnvVar {
+            name: token_env.to_string(),
+            value_from: Some(EnvVarSource {
+                secret_key_ref: Some(SecretKeySelector {
+                    name: build_ide_secret_name(pod_name),
+                    key: TOKEN_KEY.to_string(),
+                    optional: Some(false),
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }]),
+        ports: Some(vec![ContainerPort {
+            container_port: IDE_PORT,
+            protocol: Some("TCP".to_string()),
+            ..Default::default()
+        }]),
+        resources: Some(ResourceRequirements {
+            limits: Some(BTreeMap::from([
+                ("memory".to_string(), Quantity("1Gi".into())),
+                ("cpu".to_string(), Quantity("1".into())),
+            ])),
+            requests: Some(BTreeMap::from([
+                ("memory".to_string(), Quantity("512Mi".into())),
+                ("cpu".to_string(), Quantity("250m".into())),
+            ])),
+            claims: None,
+        }),
+        volume_mounts: Some(vec![workspace_mount()]),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderMap;
+
+    use super::{terminal_stdin, IdeAccess, IdeTokenCache};
+    use crate::models::IdeKind;
+
+    #[test]
+    fn proxied_requests_carry_the_ide_token() {
+        let mut headers = HeaderMap::new();
+        headers.insert("cookie", "theme=dark".parse().unwrap());
+        IdeAccess {
+            kind: IdeKind::CodeServer,
+            token: "t0ken".to_string(),
+        }
+        .authorize(&mut headers);
+        let cookies: Vec<_> = headers.get_all("cookie").iter().collect();
+        assert_eq!(cookies, ["theme=dark", "code-server-session=t0ken"]);
+
+        let jupyter = IdeAccess {
+            kind: IdeKind::Jupyter,
+            token: "t0ken".to_string(),
+        };
+        let mut headers = HeaderMap::new();
+        headers.insert("authorization", "Bearer learner".parse().unwrap());
+        jupyter.authorize(&mut headers);
+        assert_eq!(headers.get("authorization").unwrap(), "token t0ken");
+    }
+
+    #[test]
+    fn cached_tokens_are_kept_per_runtime_until_forgotten() {
+        let cache = IdeTokenCache::default();
+        cache.remember("ctf-runtime-1", "t0ken");
+
+        assert_eq!(cache.cached("ctf-runtime-1").as_deref(), Some("t0ken"));
+        assert_eq!(cache.cached("ctf-runtime-2"), None);
+
+        cache.forget("ctf-runtime-1");
+        assert_eq!(cache.cached("ctf-runtime-1"), None);
+    }
+
+    #[test]
+    fn jupyter_terminal_input_is_read_from_stdin_frames() {
+        let jupyter = IdeAccess {
+            kind: IdeKind::Jupyter,
+            token: "t0ken".to_string(),
+        };
+
+        assert!(jupyter.is_terminal_socket("/terminals/websocket/1"));
+        assert!(!jupyter.is_terminal_socket("/api/kernels/abc/channels"));
+        assert_eq!(
+            terminal_stdin(r#"["stdin", "ls -la\r"]"#).as_deref(),
+            Some("ls -la\r")
+        );
+        assert_eq!(terminal_stdin(r#"["set_size", 24, 80]"#), None);
+        assert_eq!(terminal_stdin("not json"), None);
+    }
+}
@@ -0,0 +1,390 @@
+/**
+ * @file lab_web_cookie_keyring — signing keys for lab web session cookies.
+ *
+ * @remarks
+ * Holds one active signing key and any number of verification keys, so the
+ * cookie secret can be rotated without logging learners out of running web
+ * labs. Every issued cookie names its key with a `kid` JWT header.
+ *
+ * Key sources, first match wins:
+ *
+ *  - `LAB_WEB_COOKIE_KEYS_FILE` → JSON keyring file (e.g. a mounted Secret)
+ *  - `LAB_WEB_COOKIE_KEYS` → the same JSON inline
+ *  - `LAB_WEB_COOKIE_SIGNING_SECRET` → a single key, named by
+ *    `LAB_WEB_COOKIE_SIGNING_KID` (default `default`)
+ *
+ * Keyring format:
+ *
+ *  `{"active_kid":"2026-10","keys":[{"kid":"2026-10","secret":"…"},
+ *    {"kid":"2026-07","secret":"…","retired":true}]}`
+ *
+ * Key characteristics:
+ *
+ *  - The keyring file is re-read when it changes, checked at most every
+ *    `LAB_WEB_COOKIE_KEYS_RELOAD_SECS` (default 30)
+ *  - A keyring that fails to load keeps the previous keys in use
+ *  - Verification accepts any key that is not retired
+ *  - Cookies issued before key ids were introduced carry no `kid` and are
+ *    checked against every non-retired key
+ *
+ * @packageDocumentation
+ */
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant, SystemTime};
+
+use jsonwebtoken::{
+    decode, decode_header, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tracing::{info, warn};
+
+const DEFAULT_SIGNING_KID: &str = "default";
+const DEFAULT_RELOAD_SECS: u64 = 30;
+
+#[derive(Debug, Deserialize)]
+struct KeyringConfig {
+    active_kid: String,
+    keys: Vec<KeyConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+struct KeyConfig {
+    kid: String,
+    secret: String,
+    #[serde(default)]
+    retired: bool,
+}
+
+#[derive(Debug, Default)]
+struct KeySet {
+    active_kid: Option<String>,
+    keys: Vec<KeyConfig>,
+}
+
+impl KeySet {
+    fn parse(raw: &str) -> Result<Self, String> {
+        let config: KeyringConfig = serde_json::from_str(raw)
+            .map_err(|error| format!("keyring is not valid JSON: {error}"))?;
+
+        for (index, key) in config.keys.iter().enumerate() {
+            if key.kid.trim().is_empty() || key.secret.is_empty() {
+                return Err("every key needs a non-empty kid and secret".to_string());
+            }
+            if config.keys[..index]
+                .iter()
+                .any(|other| other.kid == key.kid)
+            {
+                return Err(format!("kid `{}` appears more than once", key.kid));
+            }
+        }
+        match config.keys.iter().find(|key| key.kid == config.active_kid) {
+            Some(key) if !key.retired => {}
+            Some(_) => return Err(format!("active key `{}` is retired", config.active_kid)),
+            None => return Err(format!("active key `{}` is missing", config.active_kid)),
+        }
+
+        Ok(Self {
+            active_kid: Some(config.active_kid),
+            keys: config.keys,
+        })
+    }
+
+    fn single(kid: String, secret: String) -> Self {
+        Self {
+            active_kid: Some(kid.clone()),
+            keys: vec![KeyConfig {
+                kid,
+                secret,
+                retired: false,
+            }],
+        }
+    }
+
+    fn active(&self) -> Option<&KeyConfig> {
+        let active_kid = self.active_kid.as_ref()?;
+        self.keys.iter().find(|key| &key.kid == active_kid)
+    }
+
+    fn verification_keys<'a>(
+        &'a self,
+        kid: Option<&'a str>,
+    ) -> impl Iterator<Item = &'a KeyConfig> + 'a {
+        self.keys
+            .iter()
+            .filter(move |key| !key.retired && kid.is_none_or(|kid| key.kid == kid))
+    }
+}
+
+struct LoadedKeys {
+    keys: Arc<KeySet>,
+    modified: Option<SystemTime>,
+    checked_at: Instant,
+}
+
+/// Signs and verifies lab web session cookies with rotating keys.
+pub struct LabWebCookieKeyring {
+    file: Option<PathBuf>,
+    reload_every: Duration,
+    loaded: RwLock<LoadedKeys>,
+}
+
+impl LabWebCookieKeyring {
+    pub fn from_env() -> Self {
+        let reload_every = Duration::from_secs(
+            std::env::var("LAB_WEB_COOKIE_KEYS_RELOAD_SECS")
+                .ok()
+                .and_then(|value| value.trim().parse().ok())
+                .unwrap_or(DEFAULT_RELOAD_SECS),
+        );
+
+        if let Ok(path) = std::env::var("LAB_WEB_COOKIE_KEYS_FILE") {
+            return Self::from_file(PathBuf::from(path), reload_every);
+        }
+
+        let keys = if let Ok(raw) = std::env::var("LAB_WEB_COOKIE_KEYS") {
+            KeySet::parse(&raw).unwrap_or_else(|reason| {
+                warn!(reason = %reason, "Ignoring invalid LAB_WEB_COOKIE_KEYS");
+                KeySet::default()
+            })
+        } else if let Ok(secret) = std::env::var("LAB_WEB_COOKIE_SIGNING_SECRET") {
+            let kid = std::env::var("LAB_WEB_COOKIE_SIGNING_KID")
+                .unwrap_or_else(|_| DEFAULT_SIGNING_KID.to_string());
+            KeySet::single(kid, secret)
+        } else {
+            KeySet::default()
+        };
+
+        Self::with_keys(keys, None, reload_every)
+    }
+
+    fn from_file(path: PathBuf, reload_every: Duration) -> Self {
+        let keyring = Self::with_keys(KeySet::default(), Some(path), reload_every);
+        keyring.reload();
+        keyring
+    }
+
+    fn with_keys(keys: KeySet, file: Option<PathBuf>, reload_every: Duration) -> Self {
+        Self {
+            file,
+            reload_every,
+            loaded: RwLock::new(LoadedKeys {
+                keys: Arc::new(keys),
+                modified: None,
+                checked_at: Instant::now(),
+            }),
+        }
+    }
+
+    /// Signs `claims` with the active key, naming it in the `kid` header.
+    pub fn sign<T: Serialize>(&self, claims: &T) -> Result<String, String> {
+        let keys = self.current();
+        let key = keys
+            .active()
+            .ok_or_else(|| "no active lab web cookie signing key".to_string())?;
+
+        let mut header = Header::new(Algorithm::HS256);
+        header.kid = Some(key.kid.clone());
+        encode(
+            &header,
+            claims,
+            &EncodingKey::from_secret(key.secret.as_bytes()),
+        )
+        .map_err(|error| format!("failed to sign lab web cookie: {error}"))
+    }
+
+    /// Verifies `token` against its key (or every key when it has no `kid`).
+    pub fn verify<T: DeserializeOwned>(&self, token: &str) -> Result<T, String> {
+        let header =
+            decode_header(token).map_err(|error| format!("malformed cookie token: {error}"))?;
+        if header.alg != Algorithm::HS256 {
+            return Err("unexpected cookie token algorithm".to_string());
+        }
+
+        let keys = self.current();
+        let validation = Validation::new(Algorithm::HS256);
+        let mut last_error = format!(
+            "unknown or retired key `{}`",
+            header.kid.as_deref().unwrap_or("-")
+        );
+        for key in keys.verification_keys(header.kid.as_deref()) {
+            match decode::<T>(
+                token,
+                &DecodingKey::from_secret(key.secret.as_bytes()),
+                &validation,
+            ) {
+                Ok(data) => return Ok(data.claims),
+                Err(error) => last_error = format!("invalid cookie token: {error}"),
+            }
+        }
+
+        Err(last_error)
+    }
+
+    fn current(&self) -> Arc<KeySet> {
+        let due = self.file.is_some()
+            && self
+                .loaded
+                .read()
+                .unwrap_or_else(|e| e.into_inner())
+                .checked_at
+                .elapsed()
+                >= self.reload_every;
+        if due {
+            self.reload();
+        }
+        self.loaded
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .keys
+            .clone()
+    }
+
+    /// Re-reads the keyring file if it changed since the last load.
+    fn reload(&self) {
+        let Some(path) = &self.file else {
+            return;
+        };
+        let mut loaded = self.loaded.write().unwrap_or_else(|e| e.into_inner());
+        loaded.checked_at = Instant::now();
+
+        let modified = std::fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .ok();
+        if modified.is_some() && modified == loaded.modified {
+            return;
+        }
+
+        match std::fs::read_to_string(path)
+            .map_err(|error| format!("failed to read keyring: {error}"))
+            .and_then(|raw| KeySet::parse(&raw))
+        {
+            Ok(keys) => {
+                info!(
+                    path = %path.display(),
+                    active_kid = ?keys.active_kid,
+                    keys = keys.keys.len(),
+                    "Loaded lab web cookie keyring"
+                );
+                loaded.keys = Arc::new(keys);
+                loaded.modified = modified;
+            }
+            Err(reason) => {
+                warn!(
+                    path = %path.display(),
+                    reason = %reason,
+                    "Keeping previous lab web cookie keys"
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    use jsonwebtoken::{encode, EncodingKey, Header};
+    use serde::{Deserialize, Serialize};
+    use uuid::Uuid;
+
+    use super::{KeySet, LabWebCookieKeyring};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Claims {
+        cid: String,
+        exp: usize,
+    }
+
+    fn claims() -> Claims {
+        Claims {
+            cid: "ctf-runtime-1".to_string(),
+            exp: (chrono::Utc::now().timestamp() + 600) as usize,
+        }
+    }
+
+    fn keyring(raw: &str) -> LabWebCookieKeyring {
+        LabWebCookieKeyring::with_keys(KeySet::parse(raw).unwrap(), None, Duration::ZERO)
+    }
+
+    #[test]
+    fn cookies_signed_before_a_rotation_stay_valid() {
+        let before = keyring(r#"{"active_kid":"k1","keys":[{"kid":"k1","secret":"one"}]}"#);
+        let token = before.sign(&claims()).unwrap();
+
+        let after = keyring(
+            r#"{"active_kid":"k2","keys":[{"kid":"k2","secret":"two"},{"kid":"k1","secret":"one"}]}"#,
+        );
+        assert_eq!(after.verify::<Claims>(&token).unwrap(), claims());
+        assert!(after.sign(&claims()).unwrap() != token);
+    }
+
+    #[test]
+    fn retired_and_unknown_keys_are_rejected() {
+        let token = keyring(r#"{"active_kid":"k1","keys":[{"kid":"k1","secret":"one"}]}"#)
+            .sign(&claims())
+            .unwrap();
+
+        let retired = keyring(
+            r#"{"active_kid":"k2","keys":[{"kid":"k2","secret":"two"},{"kid":"k1","secret":"one","retired":true}]}"#,
+        );
+        assert!(retired.verify::<Claims>(&token).is_err());
+
+        let unknown = keyring(r#"{"active_kid":"k3","keys":[{"kid":"k3","secret":"one"}]}"#);
+        assert!(unknown.verify::<Claims>(&token).is_err());
+    }
+
+    #[test]
+    fn cookies_without_a_kid_are_checked_against_every_key() {
+        let legacy = encode(
+            &Header::default(),
+            &claims(),
+            &EncodingKey::from_secret(b"one"),
+        )
+        .unwrap();
+        let keyring = keyring(
+            r#"{"active_kid":"k2","keys":[{"kid":"k2","secret":"two"},{"kid":"k1","secret":"one"}]}"#,
+        );
+
+        assert_eq!(keyring.verify::<Claims>(&legacy).unwrap(), claims());
+    }
+
+    #[test]
+    fn invalid_keyrings_are_rejected() {
+        assert!(
+            KeySet::parse(r#"{"active_kid":"k9","keys":[{"kid":"k1","secret":"one"}]}"#).is_err()
+        );
+        assert!(KeySet::parse(
+            r#"{"active_kid":"k1","keys":[{"kid":"k1","secret":"one","retired":true}]}"#
+        )
+        .is_err());
+        assert!(KeySet::parse(
+            r#"{"active_kid":"k1","keys":[{"kid":"k1","secret":"a"},{"kid":"k1","secret":"b"}]}"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn keyring_file_changes_are_picked_up() {
+        let path = std::env::temp_dir().join(format!("altair-keyring-{}.json", Uuid::new_v4()));
+        std::fs::write(
+            &path,
+            r#"{"active_kid":"k1","keys":[{"kid":"k1","secret":"one"}]}"#,
+        )
+        .unwrap();
+        let keyring = LabWebCookieKeyring::from_file(PathBuf::from(&path), Duration::ZERO);
+        let token = keyring.sign(&claims()).unwrap();
+
+        std::fs::write(
+            &path,
+            r#"{"active_kid":"k2","keys":[{"kid":"k2","secret":"two"},{"kid":"k1","secret":"one","retired":true}]}"#,
+        )
+        .unwrap();
+        // Force a reload even if the filesystem keeps the same mtime.
+        keyring.loaded.write().unwrap().modified = None;
+
+        assert!(keyring.verify::<Claims>(&token).is_err());
+        let _ = std::fs::remove_file(&path);
+    }
+}
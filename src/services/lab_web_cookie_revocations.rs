@@ -0,0 +1,132 @@
+/**
+ * @file lab_web_cookie_revocations — revoked lab web session cookies.
+ *
+ * @remarks
+ * Lab web cookies are stateless JWTs, so stopping a runtime or logging out
+ * of a web session does not invalidate a cookie that was already issued.
+ * This registry records what has been revoked so the web proxy can refuse
+ * it before the cookie expires on its own.
+ *
+ * Revocations:
+ *
+ *  - Single cookie → by its `jti`, kept until the cookie's `exp`
+ *  - Whole runtime → every cookie for the container issued up to the
+ *    revocation (`iat` at or before it), kept for one cookie lifetime
+ *
+ * Key characteristics:
+ *
+ *  - Expired entries are pruned on every write
+ *  - In-memory only: each lab-api replica keeps its own list, like the SSH
+ *    credential registry
+ *
+ * @packageDocumentation
+ */
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use uuid::Uuid;
+
+const DEFAULT_COOKIE_TTL_SECONDS: u64 = 3600;
+
+/// Lifetime of an issued lab web cookie, from `LAB_WEB_COOKIE_TTL_SECONDS`.
+pub fn lab_web_cookie_ttl_seconds() -> u64 {
+    std::env::var("LAB_WEB_COOKIE_TTL_SECONDS")
+        .ok()
+        .and_then(|value| value.parse::<u64>().ok())
+        .filter(|value| *value > 0)
+        .unwrap_or(DEFAULT_COOKIE_TTL_SECONDS)
+}
+
+#[derive(Default)]
+struct Revoked {
+    /// `jti` → cookie `exp`.
+    tokens: HashMap<Uuid, u64>,
+    /// container id → (revoked at, forget after).
+    runtimes: HashMap<String, (u64, u64)>,
+}
+
+/// In-memory revocation list for lab web cookies.
+#[derive(Default)]
+pub struct LabWebCookieRevocations {
+    revoked: Mutex<Revoked>,
+}
+
+impl LabWebCookieRevocations {
+    /// Revokes one cookie until it would have expired anyway.
+    pub fn revoke_token(&self, jti: Uuid, exp: u64) {
+        let mut revoked = self.lock_pruned(unix_now());
+        revoked.tokens.insert(jti, exp);
+    }
+
+    /// Revokes every cookie issued so far for `container_id`.
+    pub fn revoke_runtime(&self, container_id: &str) {
+        let now = unix_now();
+        let mut revoked = self.lock_pruned(now);
+        revoked.runtimes.insert(
+            container_id.to_string(),
+            (now, now.saturating_add(lab_web_cookie_ttl_seconds())),
+        );
+    }
+
+    /// Whether a cookie with these claims has been revoked.
+    pub fn is_revoked(&self, jti: Uuid, container_id: &str, issued_at: u64) -> bool {
+        let revoked = self.revoked.lock().unwrap_or_else(|e| e.into_inner());
+
+        revoked.tokens.contains_key(&jti)
+            || revoked
+                .runtimes
+                .get(container_id)
+                .is_some_and(|(revoked_at, _)| issued_at <= *revoked_at)
+    }
+
+    fn lock_pruned(&self, now: u64) -> std::sync::MutexGuard<'_, Revoked> {
+        let mut revoked = self.revoked.lock().unwrap_or_else(|e| e.into_inner());
+        revoked.tokens.retain(|_, exp| *exp > now);
+        revoked
+            .runtimes
+            .retain(|_, (_, forget_after)| *forget_after > now);
+        revoked
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::{unix_now, LabWebCookieRevocations};
+
+    #[test]
+    fn revoked_token_is_rejected_until_it_expires() {
+        let revocations = LabWebCookieRevocations::default();
+        let revoked = Uuid::new_v4();
+        let expired = Uuid::new_v4();
+        let now = unix_now();
+
+        revocations.revoke_token(expired, now - 1);
+        revocations.revoke_token(revoked, now + 60);
+
+        assert!(revocations.is_revoked(revoked, "ctf-runtime-1", now));
+        assert!(!revocations.is_revoked(Uuid::new_v4(), "ctf-runtime-1", now));
+        assert!(!revocations.is_revoked(expired, "ctf-runtime-1", now));
+    }
+
+    #[test]
+    fn runtime_revocation_covers_cookies_issued_before_it() {
+        let revocations = LabWebCookieRevocations::default();
+        let now = unix_now();
+
+        revocations.revoke_runtime("ctf-runtime-1");
+
+        assert!(revocations.is_revoked(Uuid::new_v4(), "ctf-runtime-1", now - 30));
+        assert!(!revocations.is_revoked(Uuid::new_v4(), "ctf-runtime-1", now + 30));
+        assert!(!revocations.is_revoked(Uuid::new_v4(), "ctf-runtime-2", now - 30));
+    }
+}
@@ -0,0 +1,137 @@
+/**
+ * @file lab_web_routing — how learners reach web lab runtimes.
+ *
+ * @remarks
+ * Web labs are served either under a path prefix on the API host
+ * (`/web/{container_id}/`) or on a host of their own
+ * (`{container_id}.labs.example`). The subdomain mode keeps lab apps that
+ * use absolute links, root-relative assets or cookies scoped to `/` working.
+ *
+ * Configuration:
+ *
+ *  - `LAB_WEB_ROUTING_MODE` → `path` (default) or `subdomain`
+ *  - `LAB_WEB_SUBDOMAIN_BASE_URL` → base for runtime hosts in subdomain
+ *    mode, e.g. `https://labs.example`; the runtime name is prepended
+ *
+ * Key characteristics:
+ *
+ *  - Subdomain mode without a usable base URL falls back to path mode
+ *  - Only a single DNS label directly under the base host maps to a runtime
+ *  - Runtime hosts bootstrap their host-only cookie at `HOST_SESSION_PATH`
+ *
+ * @packageDocumentation
+ */
+use reqwest::Url;
+use tracing::warn;
+
+use crate::services::web_proxy::is_runtime_name;
+
+/// Path on a runtime host that exchanges a bootstrap token for the session cookie.
+pub const HOST_SESSION_PATH: &str = "/__altair/session";
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum LabWebRouting {
+    #[default]
+    Path,
+    Subdomain {
+        base_url: Url,
+    },
+}
+
+impl LabWebRouting {
+    pub fn from_env() -> Self {
+        match std::env::var("LAB_WEB_ROUTING_MODE").as_deref() {
+            Ok("subdomain") => {
+                let base = std::env::var("LAB_WEB_SUBDOMAIN_BASE_URL").unwrap_or_default();
+                Self::subdomain(&base).unwrap_or_else(|reason| {
+                    warn!(
+                        reason = %reason,
+                        "Ignoring LAB_WEB_ROUTING_MODE=subdomain; serving web labs under /web/"
+                    );
+                    Self::Path
+                })
+            }
+            Ok("path") | Err(_) => Self::Path,
+            Ok(other) => {
+                warn!(mode = %other, "Unknown LAB_WEB_ROUTING_MODE; serving web labs under /web/");
+                Self::Path
+            }
+        }
+    }
+
+    fn subdomain(base: &str) -> Result<Self, String> {
+        let base_url =
+            Url::parse(base).map_err(|error| format!("invalid subdomain base URL: {error}"))?;
+        if !matches!(base_url.scheme(), "http" | "https") || base_url.host_str().is_none() {
+            return Err("subdomain base URL must be an http(s) URL with a host".to_string());
+        }
+
+        Ok(Self::Subdomain { base_url })
+    }
+
+    /// Origin serving `container_id` in subdomain mode.
+    pub fn runtime_origin(&self, container_id: &str) -> Option<Url> {
+        let Self::Subdomain { base_url } = self else {
+            return None;
+        };
+        let mut url = base_url.clone();
+        url.set_host(Some(&format!("{container_id}.{}", base_url.host_str()?)))
+            .ok()?;
+        url.set_path("/");
+        url.set_query(None);
+
+        Some(url)
+    }
+
+    /// Runtime named by a request `Host`, if it is a runtime host.
+    pub fn container_for_host(&self, host: &str) -> Option<String> {
+        let Self::Subdomain { base_url } = self else {
+            return None;
+        };
+        let base_host = base_url.host_str()?;
+        let host = host.rsplit_once(':').map_or(host, |(name, _)| name);
+        let label = host
+            .to_ascii_lowercase()
+            .strip_suffix(base_host)?
+            .strip_suffix('.')?
+            .to_string();
+
+        is_runtime_name(&label).then_some(label)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LabWebRouting;
+
+    #[test]
+    fn runtime_hosts_map_to_their_container() {
+        let routing = LabWebRouting::subdomain("https://labs.example").unwrap();
+
+        assert_eq!(
+            routing.container_for_host("ctf-runtime-42.labs.example:443"),
+            Some("ctf-runtime-42".to_string())
+        );
+        assert_eq!(routing.container_for_host("labs.example"), None);
+        assert_eq!(routing.container_for_host("a.b.labs.example"), None);
+        assert_eq!(
+            routing.container_for_host("ctf-runtime-42.evil.example"),
+            None
+        );
+        assert_eq!(
+            LabWebRouting::Path.container_for_host("ctf-runtime-42.labs.example"),
+            None
+        );
+    }
+
+    #[test]
+    fn runtime_origin_prepends_the_container_to_the_base_host() {
+        let routing = LabWebRouting::subdomain("https://labs.example:8443/ignored").unwrap();
+
+        assert_eq!(
+            routing.runtime_origin("ctf-runtime-42").unwrap().as_str(),
+            "https://ctf-runtime-42.labs.example:8443/"
+        );
+        assert!(LabWebRouting::subdomain("labs.example").is_err());
+    }
+}
@@ -1,2 +1,16 @@
+pub mod desktop_proxy;
+pub mod gateway_identity;
+pub mod lab_ide;
+pub mod lab_web_cookie_keyring;
+pub mod lab_web_cookie_revocations;
+pub mod lab_web_routing;
+pub mod runtime_files;
+pub mod sessions_ms_client;
 pub mod spawn;
+pub mod ssh_gateway;
+pub mod terminal_event_outbox;
+pub mod terminal_event_sinks;
+pub mod web_proxy;
+pub mod web_response_rewriting;
 pub mod web_shell;
+pub mod web_traffic_capture;
@@ -0,0 +1,575 @@
+/**
+ * @file runtime_files — file transfer into and out of lab runtimes.
+ *
+ * @remarks
+ * Streams tar archives between HTTP clients and a runtime container
+ * through Kubernetes exec, so learners can upload tools and download
+ * artifacts without typing them into the terminal.
+ *
+ * Responsibilities:
+ *
+ *  - Validate and normalize requested container paths
+ *  - Restrict transfers to configured roots (home directories by default)
+ *  - Extract uploaded archives with `tar -x` inside the lab container
+ *  - Stream `tar -c` output back to the client, answering 404/403/422 when
+ *    tar fails before the archive outgrows its first 64 KiB
+ *  - Enforce upload and download size limits
+ *  - Drain tar's output streams while it runs, so a chatty tar never blocks
+ *    on a full exec buffer
+ *  - Audit-log every transfer
+ *
+ * Key characteristics:
+ *
+ *  - Reuses the web shell runtime lookup, container and user settings
+ *  - Only the user the runtime was spawned for can transfer files
+ *  - Never buffers whole archives in memory
+ *  - Limits are configurable through environment variables
+ *
+ * @packageDocumentation
+ */
+use std::path::{Component, Path};
+
+use axum::{
+    body::{Body, Bytes},
+    http::StatusCode,
+};
+use futures::{stream, StreamExt};
+use k8s_openapi::api::core::v1::Pod;
+use kube::{
+    api::{AttachParams, AttachedProcess},
+    Api,
+};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::{
+    models::State,
+    services::{
+        spawn::ensure_runtime_owner,
+        web_shell::{exec_exit_status, resolve_terminal_runtime, TerminalRuntime},
+    },
+};
+
+const DEFAULT_ALLOWED_ROOTS: &str = "/home,/root";
+const DEFAULT_MAX_UPLOAD_BYTES: u64 = 50 * 1024 * 1024;
+const DEFAULT_MAX_DOWNLOAD_BYTES: u64 = 200 * 1024 * 1024;
+const BUFFER_SIZE: usize = 16 * 1024;
+const MAX_ERROR_CHARS: usize = 512;
+/// GNU tar writes an empty archive (10 KiB) before exiting with an error when
+/// a path cannot be read, so small outputs are held until tar has exited.
+const DOWNLOAD_HEAD_BYTES: usize = 64 * 1024;
+
+/// Extracts a tar archive streamed in `body` into `path` inside the runtime.
+pub async fn upload_archive(
+    state: &State,
+    user_id: Uuid,
+    pod_name: &str,
+    path: &str,
+    body: Body,
+) -> Result<u64, StatusCode> {
+    let path = validate_runtime_path(path).map_err(|reason| {
+        warn!(pod_name = %pod_name, reason = %reason, action = "file_upload", "Rejected upload path");
+        StatusCode::BAD_REQUEST
+    })?;
+    let runtime = load_runtime(state, pod_name, user_id).await?;
+    let max_bytes = limit_env("LAB_FILES_MAX_UPLOAD_BYTES", DEFAULT_MAX_UPLOAD_BYTES);
+    let quoted = shell_quote(&path);
+    let command = format!(
+        "mkdir -p {quoted} && exec tar -x -f - --no-same-owner --no-same-permissions -C {quoted}"
+    );
+    let mut exec = start_exec(state, &runtime, pod_name, &command).await?;
+    let mut stdin = exec.stdin().ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+    let stdout = tokio::spawn(drain_output(exec.stdout()));
+    let stderr = tokio::spawn(drain_error_output(exec.stderr()));
+
+    let mut received: u64 = 0;
+    let mut data = body.into_data_stream();
+    while let Some(chunk) = data.next().await {
+        let chunk = chunk.map_err(|_| StatusCode::BAD_REQUEST)?;
+        received += chunk.len() as u64;
+        if received > max_bytes {
+            warn!(
+                pod_name = %pod_name,
+                path = %path,
+                received_bytes = received,
+                max_bytes,
+                action = "file_upload",
+                "upload exceeded size limit"
+            );
+            return Err(StatusCode::PAYLOAD_TOO_LARGE);
+        }
+        if stdin.write_all(&chunk).await.is_err() {
+            break;
+        }
+    }
+    let _ = stdin.shutdown().await;
+    drop(stdin);
+
+    let _ = stdout.await;
+    let error_output = stderr.await.unwrap_or_default();
+    let exit_status = exec_exit_status(&mut exec).await;
+
+    info!(
+        namespace = %runtime.namespace,
+        pod_name = %pod_name,
+        path = %path,
+        bytes = received,
+        exit_status,
+        action = "file_upload",
+        "runtime file upload finished"
+    );
+
+    if exit_status != 0 {
+        warn!(
+            pod_name = %pod_name,
+            path = %path,
+            stderr = %error_output,
+            action = "file_upload",
+            "tar extraction failed in runtime"
+        );
+        return Err(StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    Ok(received)
+}
+
+/// Streams `path` from the runtime as a tar archive.
+pub async fn download_archive(
+    state: &State,
+    user_id: Uuid,
+    pod_name: &str,
+    path: &str,
+) -> Result<(String, Body), StatusCode> {
+    let path = validate_runtime_path(path).map_err(|reason| {
+        warn!(pod_name = %pod_name, reason = %reason, action = "file_download", "Rejected download path");
+        StatusCode::BAD_REQUEST
+    })?;
+    let target = Path::new(&path);
+    let (Some(parent), Some(name)) = (
+        target.parent().and_then(Path::to_str),
+        target.file_name().and_then(|name| name.to_str()),
+    ) else {
+        return Err(StatusCode::BAD_REQUEST);
+    };
+    let runtime = load_runtime(state, pod_name, user_id).await?;
+    let max_bytes = limit_env("LAB_FILES_MAX_DOWNLOAD_BYTES", DEFAULT_MAX_DOWNLOAD_BYTES);
+    let command = format!(
+        "exec tar -c -f - -C {} {}",
+        shell_quote(parent),
+        shell_quote(name)
+    );
+    let mut exec = start_exec(state, &runtime, pod_name, &command).await?;
+    let mut stdout = exec.stdout().ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+    let stderr = tokio::spawn(drain_error_output(exec.stderr()));
+
+    info!(
+        namespace = %runtime.namespace,
+        pod_name = %pod_name,
+        path = %path,
+        action = "file_download",
+        "runtime file download started"
+    );
+
+    let archive_name = format!("{name}.tar");
+    let (head, complete) = read_archive_head(&mut stdout).await;
+    if head.len() as u64 > max_bytes {
+        warn!(
+            pod_name = %pod_name,
+            path = %path,
+            max_bytes,
+            action = "file_download",
+            "download exceeded size limit"
+        );
+        return Err(StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    if complete {
+        let error_output = stderr.await.unwrap_or_default();
+        let exit_status = exec_exit_status(&mut exec).await;
+        if let Some(status) = download_failure_status(exit_status, &error_output) {
+            warn!(
+                pod_name = %pod_name,
+                path = %path,
+                exit_status,
+                stderr = %error_output,
+                action = "file_download",
+                "tar archive creation failed in runtime"
+            );
+            return Err(status);
+        }
+        info!(
+            pod_name = %pod_name,
+            path = %path,
+            bytes = head.len(),
+            action = "file_download",
+            "runtime file download finished"
+        );
+        return Ok((archive_name, Body::from(head)));
+    }
+
+    // The exec handle travels with the stream so the session stays open until
+    // the archive is fully sent; a late tar failure aborts the response.
+    let finished = {
+        let pod_name = pod_name.to_string();
+        let path = path.clone();
+        async move {
+            let error_output = stderr.await.unwrap_or_default();
+            let exit_status = exec_exit_status(&mut exec).await;
+            if download_failure_status(exit_status, &error_output).is_none() {
+                return Ok(());
+            }
+            warn!(
+                pod_name = %pod_name,
+                path = %path,
+                exit_status,
+                stderr = %error_output,
+                action = "file_download",
+                "tar archive creation failed in runtime"
+            );
+            Err(std::io::Error::other("tar archive creation failed"))
+        }
+    };
+    let body = stream_archive(
+        head,
+        stdout,
+        finished,
+        max_bytes,
+        pod_name.to_string(),
+        path,
+    );
+
+    Ok((archive_name, Body::from_stream(body)))
+}
+
+/// Reads up to `DOWNLOAD_HEAD_BYTES` of tar output; the flag tells whether tar
+/// finished writing within it.
+async fn read_archive_head(stdout: &mut (impl AsyncRead + Unpin)) -> (Vec<u8>, bool) {
+    let mut head = Vec::new();
+    let mut buf = vec![0u8; BUFFER_SIZE];
+    while head.len() < DOWNLOAD_HEAD_BYTES {
+        match stdout.read(&mut buf).await {
+            Ok(0) | Err(_) => return (head, true),
+            Ok(n) => head.extend_from_slice(&buf[..n]),
+        }
+    }
+    (head, false)
+}
+
+/// Sends `head`, then the rest of `stdout`, cutting the response off past
+/// `max_bytes` or when `finished` reports that tar failed.
+fn stream_archive<R, F>(
+    head: Vec<u8>,
+    stdout: R,
+    finished: F,
+    max_bytes: u64,
+    pod_name: String,
+    path: String,
+) -> impl futures::Stream<Item = std::io::Result<Bytes>> + Send
+where
+    R: AsyncRead + Unpin + Send + 'static,
+    F: std::future::Future<Output = std::io::Result<()>> + Send + 'static,
+{
+    let sent = head.len() as u64;
+    let rest = stream::unfold(Some((stdout, finished, sent)), move |state| {
+        let pod_name = pod_name.clone();
+        let path = path.clone();
+        async move {
+            let (mut stdout, finished, sent) = state?;
+            let mut buf = vec![0u8; BUFFER_SIZE];
+            match stdout.read(&mut buf).await {
+                Ok(0) => match finished.await {
+                    Ok(()) => {
+                        info!(
+                            pod_name = %pod_name,
+                            path = %path,
+                            bytes = sent,
+                            action = "file_download",
+                            "runtime file download finished"
+                        );
+                        None
+                    }
+                    Err(error) => Some((Err(error), None)),
+                },
+                Err(error) => Some((Err(error), None)),
+                Ok(n) if sent + n as u64 > max_bytes => {
+                    warn!(
+                        pod_name = %pod_name,
+                        path = %path,
+                        max_bytes,
+                        action = "file_download",
+                        "download exceeded size limit"
+                    );
+                    Some((
+                        Err(std::io::Error::other("download exceeded size limit")),
+                        None,
+                    ))
+                }
+                Ok(n) => {
+                    buf.truncate(n);
+                    Some((
+                        Ok(Bytes::from(buf)),
+                        Some((stdout, finished, sent + n as u64)),
+                    ))
+                }
+            }
+        }
+    });
+
+    stream::once(async move { Ok(Bytes::from(head)) }).chain(rest)
+}
+
+/// Maps a failed `tar` run to the status the client sees; `None` on success.
+fn download_failure_status(exit_status: u32, stderr: &str) -> Option<StatusCode> {
+    if exit_status == 0 {
+        return None;
+    }
+    Some(if stderr.contains("No such file or directory") {
+        StatusCode::NOT_FOUND
+    } else if stderr.contains("Permission denied") {
+        StatusCode::FORBIDDEN
+    } else {
+        StatusCode::UNPROCESSABLE_ENTITY
+    })
+}
+
+async fn load_runtime(
+    state: &State,
+    pod_name: &str,
+    user_id: Uuid,
+) -> Result<TerminalRuntime, StatusCode> {
+    let runtime = resolve_terminal_runtime(state, pod_name)
+        .await
+        .map_err(|_| StatusCode::NOT_FOUND)?;
+    ensure_runtime_owner(&runtime.pod, user_id)?;
+    Ok(runtime)
+}
+
+async fn start_exec(
+    state: &State,
+    runtime: &TerminalRuntime,
+    pod_name: &str,
+    command: &str,
+) -> Result<AttachedProcess, StatusCode> {
+    let pods: Api<Pod> = Api::namespaced(state.kube_client.clone(), &runtime.namespace);
+    let attach_params = AttachParams {
+        stdin: true,
+        stdout: true,
+        stderr: true,
+        tty: false,
+        container: runtime.launch.container.clone(),
+        ..Default::default()
+    };
+
+    pods.exec(
+        pod_name,
+        runtime.launch.exec_command(command),
+        &attach_params,
+    )
+    .await
+    .map_err(|error| {
+        warn!(
+            namespace = %runtime.namespace,
+            pod_name = %pod_name,
+            error = ?error,
+            action = "file_transfer",
+            "failed to start file transfer exec"
+        );
+        StatusCode::BAD_GATEWAY
+    })
+}
+
+/// Reads a stream to EOF and discards it.
+async fn drain_output(output: Option<impl AsyncRead + Unpin>) {
+    if let Some(mut output) = output {
+        let _ = tokio::io::copy(&mut output, &mut tokio::io::sink()).await;
+    }
+}
+
+/// Reads stderr to EOF, keeping its first `MAX_ERROR_CHARS` bytes. The exec
+/// buffers are small, so tar blocks until every stream is consumed.
+async fn drain_error_output(stderr: Option<impl AsyncRead + Unpin>) -> String {
+    let Some(mut stderr) = stderr else {
+        return String::new();
+    };
+    let mut output = Vec::new();
+    let mut buf = vec![0u8; BUFFER_SIZE];
+    loop {
+        match stderr.read(&mut buf).await {
+            Ok(0) | Err(_) => break,
+            Ok(n) => {
+                let keep = n.min(MAX_ERROR_CHARS - output.len());
+                output.extend_from_slice(&buf[..keep]);
+            }
+        }
+    }
+    String::from_utf8_lossy(&output).trim_end().to_string()
+}
+
+/// Normalizes an absolute path and checks it stays under an allowed root.
+fn validate_runtime_path(path: &str) -> Result<String, String> {
+    if !path.starts_with('/') || path.contains('\0') || path.contains('\n') {
+        return Err("path must be an absolute path".to_string());
+    }
+
+    let mut normalized = Vec::new();
+    for component in Path::new(path).components() {
+        match component {
+            Component::RootDir | Component::CurDir => {}
+            Component::Normal(part) => normalized.push(part.to_string_lossy().into_owned()),
+            Component::ParentDir | Component::Prefix(_) => {
+                return Err("path must not contain `..`".to_string())
+            }
+        }
+    }
+    let normalized = format!("/{}", normalized.join("/"));
+
+    let allowed_roots = std::env::var("LAB_FILES_ALLOWED_ROOTS")
+        .unwrap_or_else(|_| DEFAULT_ALLOWED_ROOTS.to_string());
+    let allowed = allowed_roots
+        .split(',')
+        .map(|root| root.trim().trim_end_matches('/'))
+        .filter(|root| root.starts_with('/') && root.len() > 1)
+        .any(|root| {
+            normalized
+                .strip_prefix(root)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+        });
+
+    if !allowed {
+        return Err(format!(
+            "path must be under one of: {}",
+            allowed_roots.replace(',', ", ")
+        ));
+    }
+
+    Ok(normalized)
+}
+
+fn limit_env(key: &str, default: u64) -> u64 {
+    std::env::var(key)
+        .ok()
+        .and_then(|value| value.parse::<u64>().ok())
+        .filter(|value| *value > 0)
+        .unwrap_or(default)
+}
+
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::StatusCode;
+    use futures::StreamExt;
+    use tokio::io::AsyncWriteExt;
+
+    use super::{
+        download_failure_status, drain_error_output, stream_archive, validate_runtime_path,
+        MAX_ERROR_CHARS,
+    };
+
+    #[test]
+    fn tar_failures_map_to_http_statuses() {
+        assert_eq!(download_failure_status(0, ""), None);
+        assert_eq!(
+            download_failure_status(
+                2,
+                "tar: loot: Cannot stat: No such file or directory\ntar: Exiting with failure status due to previous errors"
+            ),
+            Some(StatusCode::NOT_FOUND)
+        );
+        assert_eq!(
+            download_failure_status(2, "tar: loot: Cannot open: Permission denied"),
+            Some(StatusCode::FORBIDDEN)
+        );
+        assert_eq!(
+            download_failure_status(127, "sh: tar: not found"),
+            Some(StatusCode::UNPROCESSABLE_ENTITY)
+        );
+    }
+
+    #[tokio::test]
+    async fn oversized_stderr_is_drained_without_blocking_tar() {
+        // Same buffer size kube gives each exec stream.
+        let (mut tar_stderr, stderr) = tokio::io::duplex(1024);
+        let tar = tokio::spawn(async move {
+            for _ in 0..200 {
+                tar_stderr
+                    .write_all(
+                        b"tar: Ignoring unknown extended header keyword 'LIBARCHIVE.xattr'\n",
+                    )
+                    .await
+                    .unwrap();
+            }
+        });
+
+        let output = tokio::time::timeout(
+            std::time::Duration::from_secs(5),
+            drain_error_output(Some(stderr)),
+        )
+        .await
+        .unwrap();
+
+        tar.await.unwrap();
+        assert_eq!(output.len(), MAX_ERROR_CHARS);
+        assert!(output.starts_with("tar: Ignoring unknown extended header keyword"));
+    }
+
+    #[tokio::test]
+    async fn downloads_are_cut_off_past_the_size_limit() {
+        let chunks: Vec<_> = stream_archive(
+            vec![0u8; 10],
+            &[0u8; 100][..],
+            async { Ok(()) },
+            50,
+            "runtime".to_string(),
+            "/home/student/loot".to_string(),
+        )
+        .collect()
+        .await;
+
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].as_ref().unwrap().len(), 10);
+        assert!(chunks[1].is_err());
+    }
+
+    #[tokio::test]
+    async fn late_tar_failures_abort_the_download() {
+        let chunks: Vec<_> = stream_archive(
+            vec![0u8; 10],
+            &[0u8; 20][..],
+            async { Err(std::io::Error::other("tar failed")) },
+            50,
+            "runtime".to_string(),
+            "/home/student/loot".to_string(),
+        )
+        .collect()
+        .await;
+
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[1].as_ref().unwrap().len(), 20);
+        assert!(chunks[2].is_err());
+    }
+
+    #[test]
+    fn home_paths_are_normalized() {
+        assert_eq!(
+            validate_runtime_path("/home/student/./loot//").unwrap(),
+            "/home/student/loot"
+        );
+        assert_eq!(validate_runtime_path("/root").unwrap(), "/root");
+    }
+
+    #[test]
+    fn paths_outside_allowed_roots_are_rejected() {
+        assert!(validate_runtime_path("/etc/shadow").is_err());
+        assert!(validate_runtime_path("/homeless/file").is_err());
+        assert!(validate_runtime_path("relative/file").is_err());
+    }
+
+    #[test]
+    fn parent_traversal_is_rejected() {
+        assert!(validate_runtime_path("/home/student/../../etc").is_err());
+    }
+}
@@ -0,0 +1,576 @@
+/**
+ * @file sessions_ms_client — shared client for every sessions-ms call.
+ *
+ * @remarks
+ * Open-session and the web proxy ask sessions-ms which runtime backs a
+ * session; the terminal event outbox and the web shell post events to it.
+ * This client keeps those calls fast and bounded even when sessions-ms is
+ * slow or down.
+ *
+ * Responsibilities:
+ *
+ *  - Reuse one pooled HTTP client for every call
+ *  - Bound each attempt with a timeout
+ *  - Retry idempotent GETs on transport errors and 502/503/504
+ *  - Fail fast while sessions-ms keeps failing (circuit breaker)
+ *  - Cache web runtime lookups briefly, keyed by session id
+ *  - Post terminal event batches and runtime events once; the callers own
+ *    retrying
+ *
+ * Configuration:
+ *
+ *  - `SESSIONS_MS_URL` → base URL; web runtime lookups, which gate access to
+ *    a learner's runtime, need https (or http on a loopback host), while
+ *    events are posted to any URL such as in-cluster `http://sessions-ms`
+ *  - `SESSIONS_MS_TIMEOUT_MS` → per-attempt timeout (default 2000)
+ *  - `SESSIONS_MS_MAX_RETRIES` → retries after the first attempt (default 2)
+ *  - `SESSIONS_MS_RETRY_BACKOFF_MS` → first retry delay, doubled each time
+ *    (default 100)
+ *  - `SESSIONS_MS_BREAKER_FAILURES` → consecutive failed calls that open the
+ *    breaker (default 5)
+ *  - `SESSIONS_MS_BREAKER_COOLDOWN_SECS` → how long it stays open before one
+ *    probe call is let through (default 30)
+ *  - `SESSIONS_MS_RUNTIME_CACHE_SECS` → web runtime lookup cache TTL
+ *    (default 5, `0` disables)
+ *
+ * Key characteristics:
+ *
+ *  - Only successful lookups are cached
+ *  - 4xx answers are passed through and do not count against the breaker
+ *  - An open breaker answers `503 Service Unavailable` without calling out
+ *
+ * @packageDocumentation
+ */
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use axum::http::StatusCode;
+use reqwest::Url;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tracing::warn;
+use uuid::Uuid;
+
+const DEFAULT_SESSIONS_MS_URL: &str = "http://localhost:3003";
+const DEFAULT_TIMEOUT_MS: u64 = 2000;
+const DEFAULT_MAX_RETRIES: u32 = 2;
+const DEFAULT_RETRY_BACKOFF_MS: u64 = 100;
+const DEFAULT_BREAKER_FAILURES: u32 = 5;
+const DEFAULT_BREAKER_COOLDOWN_SECS: u64 = 30;
+const DEFAULT_RUNTIME_CACHE_SECS: u64 = 5;
+const POOL_IDLE_TIMEOUT_SECS: u64 = 90;
+
+#[derive(Deserialize)]
+struct SessionsApiResponse<T> {
+    data: T,
+}
+
+/// Runtime backing a web session, as reported by sessions-ms.
+#[derive(Clone, Debug, Deserialize)]
+pub struct WebRuntimeLookup {
+    pub user_id: Uuid,
+    pub runtime_kind: String,
+    pub container_id: String,
+    pub status: String,
+}
+
+#[derive(Clone, Debug)]
+struct SessionsMsClientConfig {
+    timeout: Duration,
+    max_retries: u32,
+    retry_backoff: Duration,
+    breaker_failures: u32,
+    breaker_cooldown: Duration,
+    runtime_cache_ttl: Duration,
+}
+
+impl SessionsMsClientConfig {
+    fn from_env() -> Self {
+        Self {
+            timeout: Duration::from_millis(env_number(
+                "SESSIONS_MS_TIMEOUT_MS",
+                DEFAULT_TIMEOUT_MS,
+            )),
+            max_retries: env_number("SESSIONS_MS_MAX_RETRIES", DEFAULT_MAX_RETRIES),
+            retry_backoff: Duration::from_millis(env_number(
+                "SESSIONS_MS_RETRY_BACKOFF_MS",
+                DEFAULT_RETRY_BACKOFF_MS,
+            )),
+            breaker_failures: env_number("SESSIONS_MS_BREAKER_FAILURES", DEFAULT_BREAKER_FAILURES)
+                .max(1),
+            breaker_cooldown: Duration::from_secs(env_number(
+                "SESSIONS_MS_BREAKER_COOLDOWN_SECS",
+                DEFAULT_BREAKER_COOLDOWN_SECS,
+            )),
+            runtime_cache_ttl: Duration::from_secs(env_number(
+                "SESSIONS_MS_RUNTIME_CACHE_SECS",
+                DEFAULT_RUNTIME_CACHE_SECS,
+            )),
+        }
+    }
+}
+
+/// Opens after `breaker_failures` consecutive failed calls. Once the cooldown
+/// has passed, one call at a time is let through per cooldown as a probe; a
+/// success closes the breaker again.
+#[derive(Debug, Default)]
+struct CircuitBreaker {
+    consecutive_failures: u32,
+    open_until: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    fn try_acquire(&mut self, now: Instant, cooldown: Duration) -> bool {
+        match self.open_until {
+            Some(until) if now < until => false,
+            Some(_) => {
+                self.open_until = Some(now + cooldown);
+                true
+            }
+            None => true,
+        }
+    }
+
+    fn record_success(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Returns whether this failure opened the breaker.
+    fn record_failure(&mut self, now: Instant, threshold: u32, cooldown: Duration) -> bool {
+        self.consecutive_failures = self.consecutive_failures.saturating_add(1);
+        if self.consecutive_failures < threshold {
+            return false;
+        }
+        let opened = self.open_until.is_none();
+        self.open_until = Some(now + cooldown);
+        opened
+    }
+}
+
+/// Pooled, bounded client for sessions-ms internal endpoints.
+pub struct SessionsMsClient {
+    base_url: Result<Url, StatusCode>,
+    http: reqwest::Client,
+    config: SessionsMsClientConfig,
+    breaker: Mutex<CircuitBreaker>,
+    runtimes: Mutex<HashMap<Uuid, (WebRuntimeLookup, Instant)>>,
+}
+
+impl SessionsMsClient {
+    pub fn from_env() -> Self {
+        let base_url = std::env::var("SESSIONS_MS_URL")
+            .unwrap_or_else(|_| DEFAULT_SESSIONS_MS_URL.to_string());
+        Self::new(&base_url, SessionsMsClientConfig::from_env())
+    }
+
+    fn new(base_url: &str, config: SessionsMsClientConfig) -> Self {
+        let base_url = Url::parse(base_url).map_err(|_| StatusCode::BAD_GATEWAY);
+        match &base_url {
+            Err(_) => warn!("SESSIONS_MS_URL is not a valid URL; sessions-ms calls will fail"),
+            Ok(url) if validate_sensitive_internal_url(url).is_err() => warn!(
+                "SESSIONS_MS_URL must be https, or http on a loopback host; web runtime lookups will fail"
+            ),
+            Ok(_) => {}
+        }
+        let http = reqwest::Client::builder()
+            .timeout(config.timeout)
+            .connect_timeout(config.timeout)
+            .pool_idle_timeout(Duration::from_secs(POOL_IDLE_TIMEOUT_SECS))
+            .build()
+            .unwrap_or_default();
+
+        Self {
+            base_url,
+            http,
+            config,
+            breaker: Mutex::default(),
+            runtimes: Mutex::default(),
+        }
+    }
+
+    /// Looks up the runtime behind `session_id`, from cache when fresh.
+    pub async fn web_runtime(&self, session_id: Uuid) -> Result<WebRuntimeLookup, StatusCode> {
+        if let Some(runtime) = self.cached_runtime(session_id) {
+            return Ok(runtime);
+        }
+
+        let base_url = self.base_url.clone()?;
+        validate_sensitive_internal_url(&base_url)?;
+        let url = build_sessions_ms_url(
+            base_url,
+            &format!("/internal/sessions/{session_id}/web-runtime"),
+        );
+        let runtime = self.get_json::<WebRuntimeLookup>(url).await?;
+
+        if !self.config.runtime_cache_ttl.is_zero() {
+            let mut runtimes = self.runtimes.lock().unwrap_or_else(|e| e.into_inner());
+            let now = Instant::now();
+            runtimes.retain(|_, (_, cached_at)| now - *cached_at < self.config.runtime_cache_ttl);
+            runtimes.insert(session_id, (runtime.clone(), now));
+        }
+
+        Ok(runtime)
+    }
+
+    /// Posts one terminal event batch; `batch_id` lets sessions-ms drop redeliveries.
+    pub async fn post_terminal_events(
+        &self,
+        batch_id: &str,
+        body: Vec<u8>,
+    ) -> Result<(), StatusCode> {
+        let url = build_sessions_ms_url(self.base_url.clone()?, "/internal/terminal-events");
+        let request = self
+            .http
+            .post(url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header("Idempotency-Key", batch_id)
+            .body(body);
+        send_once(request).await
+    }
+
+    /// Posts a runtime lifecycle event such as `runtime_idle`.
+    pub async fn post_runtime_event<T: Serialize>(&self, event: &T) -> Result<(), StatusCode> {
+        let url = build_sessions_ms_url(self.base_url.clone()?, "/internal/runtime-events");
+        send_once(self.http.post(url).json(event)).await
+    }
+
+    fn cached_runtime(&self, session_id: Uuid) -> Option<WebRuntimeLookup> {
+        let runtimes = self.runtimes.lock().unwrap_or_else(|e| e.into_inner());
+        runtimes
+            .get(&session_id)
+            .filter(|(_, cached_at)| cached_at.elapsed() < self.config.runtime_cache_ttl)
+            .map(|(runtime, _)| runtime.clone())
+    }
+
+    async fn get_json<T: DeserializeOwned>(&self, url: Url) -> Result<T, StatusCode> {
+        let acquired = self
+            .breaker
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .try_acquire(Instant::now(), self.config.breaker_cooldown);
+        if !acquired {
+            return Err(StatusCode::SERVICE_UNAVAILABLE);
+        }
+
+        let result = self.get_with_retries(&url).await;
+
+        let mut breaker = self.breaker.lock().unwrap_or_else(|e| e.into_inner());
+        match &result {
+            Err(status) if status.is_server_error() => {
+                let opened = breaker.record_failure(
+                    Instant::now(),
+                    self.config.breaker_failures,
+                    self.config.breaker_cooldown,
+                );
+                if opened {
+                    warn!(
+                        path = %url.path(),
+                        cooldown_secs = self.config.breaker_cooldown.as_secs(),
+                        action = "sessions_ms_breaker",
+                        "sessions-ms keeps failing; pausing lookups"
+                    );
+                }
+            }
+            _ => breaker.record_success(),
+        }
+        drop(breaker);
+
+        let body = result?;
+        serde_json::from_slice::<SessionsApiResponse<T>>(&body)
+            .map(|payload| payload.data)
+            .map_err(|_| StatusCode::BAD_GATEWAY)
+    }
+
+    async fn get_with_retries(&self, url: &Url) -> Result<axum::body::Bytes, StatusCode> {
+        let mut attempt = 0;
+        loop {
+            let status = match self.http.get(url.clone()).send().await {
+                Ok(response) if response.status().is_success() => {
+                    return response.bytes().await.map_err(|_| StatusCode::BAD_GATEWAY);
+                }
+                Ok(response) => StatusCode::from_u16(response.status().as_u16())
+                    .unwrap_or(StatusCode::BAD_GATEWAY),
+                Err(error) if error.is_timeout() => StatusCode::GATEWAY_TIMEOUT,
+                Err(_) => StatusCode::BAD_GATEWAY,
+            };
+
+            let retryable = matches!(
+                status,
+                StatusCode::BAD_GATEWAY
+                    | StatusCode::SERVICE_UNAVAILABLE
+                    | StatusCode::GATEWAY_TIMEOUT
+            );
+            if !retryable || attempt >= self.config.max_retries {
+                return Err(status);
+            }
+            tokio::time::sleep(self.config.retry_backoff * 2u32.saturating_pow(attempt)).await;
+            attempt += 1;
+        }
+    }
+}
+
+async fn send_once(request: reqwest::RequestBuilder) -> Result<(), StatusCode> {
+    match request.send().await {
+        Ok(response) if response.status().is_success() => Ok(()),
+        Ok(response) => {
+            Err(StatusCode::from_u16(response.status().as_u16()).unwrap_or(StatusCode::BAD_GATEWAY))
+        }
+        Err(error) if error.is_timeout() => Err(StatusCode::GATEWAY_TIMEOUT),
+        Err(_) => Err(StatusCode::BAD_GATEWAY),
+    }
+}
+
+fn build_sessions_ms_url(mut url: Url, path: &str) -> Url {
+    url.set_path(path);
+    url.set_query(None);
+    url
+}
+
+fn validate_sensitive_internal_url(url: &Url) -> Result<(), StatusCode> {
+    match url.scheme() {
+        "https" => Ok(()),
+        "http" if is_loopback_host(url) => Ok(()),
+        _ => Err(StatusCode::BAD_GATEWAY),
+    }
+}
+
+fn is_loopback_host(url: &Url) -> bool {
+    matches!(url.host_str(), Some("localhost" | "127.0.0.1" | "::1"))
+}
+
+fn env_number<T: std::str::FromStr>(key: &str, default: T) -> T {
+    std::env::var(key)
+        .ok()
+        .and_then(|value| value.trim().parse().ok())
+        .unwrap_or(default)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+    use std::time::Duration;
+
+    use axum::{
+        extract::Path,
+        http::{HeaderMap, StatusCode},
+        routing::{get, post},
+        Json, Router,
+    };
+    use reqwest::Url;
+    use serde_json::json;
+    use uuid::Uuid;
+
+    use super::{
+        build_sessions_ms_url, is_loopback_host, SessionsMsClient, SessionsMsClientConfig,
+    };
+
+    fn config() -> SessionsMsClientConfig {
+        SessionsMsClientConfig {
+            timeout: Duration::from_millis(200),
+            max_retries: 2,
+            retry_backoff: Duration::from_millis(1),
+            breaker_failures: 2,
+            breaker_cooldown: Duration::from_secs(60),
+            runtime_cache_ttl: Duration::from_secs(60),
+        }
+    }
+
+    /// Serves the web-runtime lookup on a loopback port. The first
+    /// `failures` calls answer `failure_status`; every call is counted.
+    async fn sessions_ms_stub(
+        failures: usize,
+        failure_status: StatusCode,
+        delay: Duration,
+    ) -> (String, Arc<AtomicUsize>) {
+        let hits = Arc::new(AtomicUsize::new(0));
+        let counter = hits.clone();
+        let app = Router::new().route(
+            "/internal/sessions/{session_id}/web-runtime",
+            get(move |Path(_): Path<Uuid>| {
+                let hit = counter.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    tokio::time::sleep(delay).await;
+                    if hit < failures {
+                        return Err(failure_status);
+                    }
+                    Ok(Json(json!({
+                        "data": {
+                            "user_id": "9bc97880-f720-41c1-9e8a-a2010e2f02c2",
+                            "runtime_kind": "web",
+                            "container_id": "ctf-runtime-42",
+                            "status": "running"
+                        }
+                    })))
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        (format!("http://{address}"), hits)
+    }
+
+    #[tokio::test]
+    async fn runtime_lookups_are_cached_per_session() {
+        let (url, hits) = sessions_ms_stub(0, StatusCode::OK, Duration::ZERO).await;
+        let client = SessionsMsClient::new(&url, config());
+        let session_id = Uuid::new_v4();
+
+        let runtime = client.web_runtime(session_id).await.unwrap();
+        client.web_runtime(session_id).await.unwrap();
+        client.web_runtime(Uuid::new_v4()).await.unwrap();
+
+        assert_eq!(runtime.container_id, "ctf-runtime-42");
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn unavailable_sessions_ms_is_retried() {
+        let (url, hits) =
+            sessions_ms_stub(2, StatusCode::SERVICE_UNAVAILABLE, Duration::ZERO).await;
+        let client = SessionsMsClient::new(&url, config());
+
+        assert!(client.web_runtime(Uuid::new_v4()).await.is_ok());
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn not_found_is_passed_through_without_retrying() {
+        let (url, hits) = sessions_ms_stub(usize::MAX, StatusCode::NOT_FOUND, Duration::ZERO).await;
+        let client = SessionsMsClient::new(&url, config());
+
+        for _ in 0..3 {
+            assert_eq!(
+                client.web_runtime(Uuid::new_v4()).await.unwrap_err(),
+                StatusCode::NOT_FOUND
+            );
+        }
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn breaker_opens_after_consecutive_failures() {
+        let (url, hits) = sessions_ms_stub(
+            usize::MAX,
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Duration::ZERO,
+        )
+        .await;
+        let client = SessionsMsClient::new(&url, config());
+
+        for _ in 0..2 {
+            assert_eq!(
+                client.web_runtime(Uuid::new_v4()).await.unwrap_err(),
+                StatusCode::INTERNAL_SERVER_ERROR
+            );
+        }
+        assert_eq!(
+            client.web_runtime(Uuid::new_v4()).await.unwrap_err(),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn slow_sessions_ms_times_out() {
+        let (url, hits) = sessions_ms_stub(0, StatusCode::OK, Duration::from_secs(5)).await;
+        let client = SessionsMsClient::new(
+            &url,
+            SessionsMsClientConfig {
+                max_retries: 1,
+                ..config()
+            },
+        );
+
+        let started = std::time::Instant::now();
+        assert_eq!(
+            client.web_runtime(Uuid::new_v4()).await.unwrap_err(),
+            StatusCode::GATEWAY_TIMEOUT
+        );
+        assert!(started.elapsed() < Duration::from_secs(2));
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn terminal_event_batches_are_posted_once_with_their_batch_id() {
+        let hits = Arc::new(AtomicUsize::new(0));
+        let counter = hits.clone();
+        let app = Router::new().route(
+            "/internal/terminal-events",
+            post(move |headers: HeaderMap, body: String| {
+                counter.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    match headers.get("idempotency-key") {
+                        Some(key) if key == "batch-1" && body == "[]" => StatusCode::ACCEPTED,
+                        _ => StatusCode::BAD_REQUEST,
+                    }
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        let client = SessionsMsClient::new(&format!("http://{address}"), config());
+
+        assert!(client
+            .post_terminal_events("batch-1", b"[]".to_vec())
+            .await
+            .is_ok());
+        assert_eq!(
+            client
+                .post_terminal_events("batch-2", b"[]".to_vec())
+                .await
+                .unwrap_err(),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn sessions_ms_lookup_url_keeps_trusted_host() {
+        let session_id = Uuid::parse_str("9bc97880-f720-41c1-9e8a-a2010e2f02c2").unwrap();
+        let url = build_sessions_ms_url(
+            Url::parse("https://sessions.example.test/base?x=1").unwrap(),
+            &format!("/internal/sessions/{session_id}/web-runtime"),
+        );
+
+        assert_eq!(
+            url,
+            Url::parse(
+                "https://sessions.example.test/internal/sessions/9bc97880-f720-41c1-9e8a-a2010e2f02c2/web-runtime"
+            )
+            .unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn runtime_lookups_accept_local_http_only() {
+        let local = SessionsMsClient::new("http://localhost:3003", config());
+        let remote = SessionsMsClient::new("http://sessions.example.test", config());
+
+        assert!(local.base_url.is_ok());
+        // Events still go to in-cluster http URLs; only lookups are refused.
+        assert!(remote.base_url.is_ok());
+        assert_eq!(
+            remote.web_runtime(Uuid::new_v4()).await.unwrap_err(),
+            StatusCode::BAD_GATEWAY
+        );
+    }
+
+    #[test]
+    fn loopback_detection_accepts_local_targets_only() {
+        assert!(is_loopback_host(
+            &Url::parse("http://localhost:3003").unwrap()
+        ));
+        assert!(is_loopback_host(
+            &Url::parse("http://127.0.0.1:3003").unwrap()
+        ));
+        assert!(!is_loopback_host(
+            &Url::parse("https://sessions.example.test").unwrap()
+        ));
+    }
+}
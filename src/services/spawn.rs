@@ -8,16 +8,19 @@
  * Responsibilities:
  *
  *  - Validate spawn requests and lab delivery modes
+ *  - Record per-lab web shell launch settings as Pod annotations
  *  - Create Kubernetes Pods for lab runtimes
  *  - Create image pull secrets for private registries
- *  - Create ClusterIP Services for web-based labs
+ *  - Create ClusterIP Services for web, desktop and IDE labs
+ *  - Start browser IDE sidecars with a per-runtime token Secret
  *  - Wait for Pods to become ready
  *  - Delete runtime resources when sessions stop
  *  - Retrieve runtime status from Kubernetes
+ *  - Locate runtime Pods across namespaces for the web shell
  *
  * Key characteristics:
  *
- *  - Supports terminal and web lab delivery modes
+ *  - Supports terminal, web, desktop (VNC) and browser IDE lab delivery modes
  *  - Splits runtimes across dedicated namespaces
  *  - Supports local mode by skipping GCP image pull secret creation
  *  - Enforces resource limits and runtime deadlines
@@ -48,8 +51,22 @@ use kube::{
 };
 use tokio::time::timeout;
 use tracing::{error, info, warn};
-
-use crate::models::{SpawnRequest, State};
+use uuid::Uuid;
+
+use crate::{
+    models::{SpawnRequest, State},
+    services::{
+        lab_ide::{
+            build_ide_container, build_ide_secret, build_ide_secret_name,
+            build_workspace_seed_container, new_ide_token, workspace_mount, workspace_volume,
+            IDE_LABEL, IDE_PORT,
+        },
+        web_response_rewriting::WEB_REWRITE_LABEL,
+        web_shell::{
+            validate_webshell_settings, TERMINAL_CAPTURE_LABEL, WEBSHELL_SETTINGS_ANNOTATION,
+        },
+    },
+};
 
 const GCP_SCOPE: &[&str] = &["https://www.googleapis.com/auth/cloud-platform"];
 const DEFAULT_NAMESPACE: &str = "default";
@@ -57,6 +74,7 @@ const WEB_NAMESPACE: &str = "labs-web";
 const POD_TIMEOUT_SECS: u64 = 30;
 const POD_DEADLINE_SECS: i64 = 7200;
 const WEB_SERVICE_PORT: i32 = 80;
+pub(crate) const DESKTOP_VNC_PORT: i32 = 5900;
 const LAB_CONTAINER_NAME: &str = "lab-container";
 const TERMINAL_KEEPALIVE_SCRIPT: &str = r#"
 if [ -x /opt/altair/startup.sh ]; then
@@ -89,6 +107,18 @@ pub async fn spawn_lab(state: State, payload: SpawnRequest) -> Result<String, St
     if !is_valid_spawn_payload(&payload) {
         return Err(StatusCode::BAD_REQUEST);
     }
+    if let Some(settings) = &payload.webshell {
+        if let Err(reason) = validate_webshell_settings(settings) {
+            warn!(
+                session_id = %payload.session_id,
+                runtime_id = %payload.runtime_id,
+                reason = %reason,
+                action = "validate_webshell",
+                "rejecting spawn request with invalid web shell settings"
+            );
+            return Err(StatusCode::BAD_REQUEST);
+        }
+    }
 
     let client = &state.kube_client;
     let namespace = namespace_for_delivery(&payload.lab_delivery);
@@ -123,6 +153,9 @@ pub async fn spawn_lab(state: State, payload: SpawnRequest) -> Result<String, St
     } else {
         create_image_pull_secret(&state, &secrets, &secret_name, &payload.template_path).await?;
     }
+    if payload.lab_delivery == "ide" {
+        create_ide_secret(&secrets, &pod_name, &namespace).await?;
+    }
 
     let pod = build_pod(&pod_name, &secret_name, &payload, use_image_pull_secret);
     pods.create(&PostParams::default(), &pod)
@@ -139,10 +172,15 @@ pub async fn spawn_lab(state: State, payload: SpawnRequest) -> Result<String, St
             StatusCode::INTERNAL_SERVER_ERROR
         })?;
 
-    // Web labs need a stable in-cluster Service before the future web proxy can
-    // forward requests to the Pod without depending on an ephemeral Pod IP.
-    if payload.lab_delivery == "web" {
-        create_web_session_service(&services, &pod_name, &payload, &namespace).await?;
+    // Web, desktop and IDE labs need a stable in-cluster Service before the proxies
+    // can forward to the Pod without depending on an ephemeral Pod IP.
+    let service = match payload.lab_delivery.as_str() {
+        "web" | "ide" => Some(build_web_service(&pod_name, &payload)),
+        "desktop" => Some(build_desktop_service(&pod_name, &payload)),
+        _ => None,
+    };
+    if let Some(service) = service {
+        create_session_service(&services, service, &pod_name, &namespace).await?;
     }
 
     wait_for_pod_ready(&pods, &pod_name, &payload, &namespace).await
@@ -162,7 +200,11 @@ fn is_valid_spawn_payload(payload: &SpawnRequest) -> bool {
         // Web sessions need an explicit container port so lab-api can create the
         // matching Kubernetes Service with a deterministic targetPort.
         "web" => payload.app_port.is_some_and(is_valid_app_port),
-        "terminal" => payload.app_port.is_none() || payload.app_port.is_some_and(is_valid_app_port),
+        // Desktop images run a VNC server, on `DESKTOP_VNC_PORT` unless `app_port` says otherwise.
+        // IDE sidecars always listen on `IDE_PORT`.
+        "terminal" | "desktop" | "ide" => {
+            payload.app_port.is_none() || payload.app_port.is_some_and(is_valid_app_port)
+        }
         _ => false,
     }
 }
@@ -171,18 +213,23 @@ fn is_valid_app_port(app_port: i32) -> bool {
     (1..=65535).contains(&app_port)
 }
 
-fn namespace_for_delivery(lab_delivery: &str) -> String {
-    if lab_delivery == "web" {
+/// Desktop and IDE runtimes share the web namespace: all are reached through lab-api proxies.
+pub(crate) fn namespace_for_delivery(lab_delivery: &str) -> String {
+    if matches!(lab_delivery, "web" | "desktop" | "ide") {
         std::env::var("LAB_WEB_NAMESPACE").unwrap_or_else(|_| WEB_NAMESPACE.to_string())
     } else {
         std::env::var("LAB_TERMINAL_NAMESPACE").unwrap_or_else(|_| DEFAULT_NAMESPACE.to_string())
     }
 }
 
-fn build_web_service_name(pod_name: &str) -> String {
+pub(crate) fn build_web_service_name(pod_name: &str) -> String {
     format!("{pod_name}-web")
 }
 
+pub(crate) fn build_desktop_service_name(pod_name: &str) -> String {
+    format!("{pod_name}-desktop")
+}
+
 async fn create_image_pull_secret(
     state: &State,
     secrets: &Api<Secret>,
@@ -231,6 +278,34 @@ async fn create_image_pull_secret(
     Ok(())
 }
 
+/// The IDE reads its token from this Secret, so it exists before the Pod starts.
+async fn create_ide_secret(
+    secrets: &Api<Secret>,
+    pod_name: &str,
+    namespace: &str,
+) -> Result<(), StatusCode> {
+    let secret_name = build_ide_secret_name(pod_name);
+    let secret = build_ide_secret(pod_name, &new_ide_token());
+
+    let _ = secrets.delete(&secret_name, &DeleteParams::default()).await;
+    secrets
+        .create(&PostParams::default(), &secret)
+        .await
+        .map_err(|e| {
+            error!(
+                namespace = %namespace,
+                pod_name = %pod_name,
+                secret_name = %secret_name,
+                error = ?e,
+                action = "create_ide_secret",
+                "failed to create IDE token secret"
+            );
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(())
+}
+
 fn build_pod(
     pod_name: &str,
     secret_name: &str,
@@ -252,6 +327,20 @@ fn build_pod(
     if let Some(lab_id) = payload.lab_id {
         labels.insert("lab_id".to_string(), lab_id.to_string());
     }
+    if let Some(policy) = payload.terminal_capture {
+        labels.insert(
+            TERMINAL_CAPTURE_LABEL.to_string(),
+            policy.as_str().to_string(),
+        );
+    }
+    if let Some(policy) = payload.web_rewrite {
+        labels.insert(WEB_REWRITE_LABEL.to_string(), policy.as_str().to_string());
+    }
+    let is_ide = payload.lab_delivery == "ide";
+    let ide = payload.ide.unwrap_or_default();
+    if is_ide {
+        labels.insert(IDE_LABEL.to_string(), ide.as_str().to_string());
+    }
 
     let limits = BTreeMap::from([
         ("memory".to_string(), Quantity("512Mi".into())),
@@ -263,12 +352,64 @@ fn build_pod(
         ("cpu".to_string(), Quantity("250m".into())),
     ]);
 
-    let is_terminal = payload.lab_delivery == "terminal";
+    // The web shell reads its launch settings back from the Pod, so they travel
+    // with the runtime instead of living in lab-api memory.
+    let mut webshell = payload.webshell.clone();
+    if is_ide {
+        // With a sidecar next to it, exec must name the lab container explicitly.
+        webshell
+            .get_or_insert_default()
+            .container
+            .get_or_insert_with(|| LAB_CONTAINER_NAME.to_string());
+    }
+    let annotations = webshell.as_ref().and_then(|settings| {
+        serde_json::to_string(settings)
+            .ok()
+            .map(|raw| BTreeMap::from([(WEBSHELL_SETTINGS_ANNOTATION.to_string(), raw)]))
+    });
+
+    // IDE labs keep the lab container alive like a terminal; the IDE is the app.
+    let is_terminal = matches!(payload.lab_delivery.as_str(), "terminal" | "ide");
+
+    let mut volume_mounts = vec![VolumeMount {
+        name: "var-log".into(),
+        mount_path: "/var/log/altair".into(),
+        ..Default::default()
+    }];
+    let mut volumes = vec![Volume {
+        name: "var-log".into(),
+        empty_dir: Some(EmptyDirVolumeSource::default()),
+        ..Default::default()
+    }];
+    if is_ide {
+        volume_mounts.push(workspace_mount());
+        volumes.push(workspace_volume());
+    }
+
+    let mut containers = vec![Container {
+        name: LAB_CONTAINER_NAME.into(),
+        image: Some(payload.template_path.clone()),
+        image_pull_policy: Some("Always".into()),
+        command: is_terminal.then(|| vec!["/bin/sh".to_string(), "-lc".to_string()]),
+        args: is_terminal.then(|| vec![TERMINAL_KEEPALIVE_SCRIPT.to_string()]),
+        env: Some(build_session_flag_env(payload)),
+        resources: Some(ResourceRequirements {
+            limits: Some(limits),
+            requests: Some(requests),
+            claims: None,
+        }),
+        volume_mounts: Some(volume_mounts),
+        ..Default::default()
+    }];
+    if is_ide {
+        containers.push(build_ide_container(ide, pod_name));
+    }
 
     Pod {
         metadata: kube::core::ObjectMeta {
             name: Some(pod_name.to_string()),
             labels: Some(labels),
+            annotations,
             ..Default::default()
         },
         spec: Some(PodSpec {
@@ -279,30 +420,10 @@ fn build_pod(
             } else {
                 None
             },
-            containers: vec![Container {
-                name: LAB_CONTAINER_NAME.into(),
-                image: Some(payload.template_path.clone()),
-                image_pull_policy: Some("Always".into()),
-                command: is_terminal.then(|| vec!["/bin/sh".to_string(), "-lc".to_string()]),
-                args: is_terminal.then(|| vec![TERMINAL_KEEPALIVE_SCRIPT.to_string()]),
-                env: Some(build_session_flag_env(payload)),
-                resources: Some(ResourceRequirements {
-                    limits: Some(limits),
-                    requests: Some(requests),
-                    claims: None,
-                }),
-                volume_mounts: Some(vec![VolumeMount {
-                    name: "var-log".into(),
-                    mount_path: "/var/log/altair".into(),
-                    ..Default::default()
-                }]),
-                ..Default::default()
-            }],
-            volumes: Some(vec![Volume {
-                name: "var-log".into(),
-                empty_dir: Some(EmptyDirVolumeSource::default()),
-                ..Default::default()
-            }]),
+            init_containers: is_ide
+                .then(|| vec![build_workspace_seed_container(&payload.template_path)]),
+            containers,
+            volumes: Some(volumes),
             restart_policy: Some("Never".into()),
             active_deadline_seconds: Some(POD_DEADLINE_SECS),
             ..Default::default()
@@ -330,9 +451,40 @@ fn build_session_flag_env(payload: &SpawnRequest) -> Vec<EnvVar> {
     env
 }
 
+/// IDE runtimes expose the IDE sidecar under the same Service name as web apps.
 fn build_web_service(pod_name: &str, payload: &SpawnRequest) -> Service {
-    let service_name = build_web_service_name(pod_name);
+    let target_port = if payload.lab_delivery == "ide" {
+        Some(IDE_PORT)
+    } else {
+        payload.app_port
+    };
+
+    build_runtime_service(
+        build_web_service_name(pod_name),
+        payload,
+        &payload.lab_delivery,
+        WEB_SERVICE_PORT,
+        target_port,
+    )
+}
 
+fn build_desktop_service(pod_name: &str, payload: &SpawnRequest) -> Service {
+    build_runtime_service(
+        build_desktop_service_name(pod_name),
+        payload,
+        "desktop",
+        DESKTOP_VNC_PORT,
+        Some(payload.app_port.unwrap_or(DESKTOP_VNC_PORT)),
+    )
+}
+
+fn build_runtime_service(
+    service_name: String,
+    payload: &SpawnRequest,
+    runtime_kind: &str,
+    port: i32,
+    target_port: Option<i32>,
+) -> Service {
     Service {
         metadata: kube::core::ObjectMeta {
             name: Some(service_name),
@@ -343,11 +495,11 @@ fn build_web_service(pod_name: &str, payload: &SpawnRequest) -> Service {
             selector: Some(BTreeMap::from([
                 ("app".to_string(), "altair-lab".to_string()),
                 ("runtime_id".to_string(), payload.runtime_id.to_string()),
-                ("runtime_kind".to_string(), "web".to_string()),
+                ("runtime_kind".to_string(), runtime_kind.to_string()),
             ])),
             ports: Some(vec![ServicePort {
-                port: WEB_SERVICE_PORT,
-                target_port: payload.app_port.map(IntOrString::Int),
+                port,
+                target_port: target_port.map(IntOrString::Int),
                 protocol: Some("TCP".to_string()),
                 ..Default::default()
             }]),
@@ -357,14 +509,13 @@ fn build_web_service(pod_name: &str, payload: &SpawnRequest) -> Service {
     }
 }
 
-async fn create_web_session_service(
+async fn create_session_service(
     services: &Api<Service>,
+    service: Service,
     pod_name: &str,
-    payload: &SpawnRequest,
     namespace: &str,
 ) -> Result<(), StatusCode> {
-    let service_name = build_web_service_name(pod_name);
-    let service = build_web_service(pod_name, payload);
+    let service_name = service.metadata.name.clone().unwrap_or_default();
 
     let _ = services
         .delete(&service_name, &DeleteParams::default())
@@ -378,8 +529,8 @@ async fn create_web_session_service(
                 pod_name = %pod_name,
                 service_name = %service_name,
                 error = ?e,
-                action = "create_web_service",
-                "failed to create web session service"
+                action = "create_session_service",
+                "failed to create session service"
             );
             StatusCode::INTERNAL_SERVER_ERROR
         })?;
@@ -639,21 +790,32 @@ fn is_pod_completed(pod: &Pod) -> bool {
 
 pub async fn delete_lab(state: State, pod_name: String) {
     // Stop requests only carry the container_id, so deletion checks both runtime
-    // namespaces and always cleans the derived web Service name as well.
+    // namespaces and always cleans the derived web and desktop Services and the
+    // IDE token Secret as well.
     let terminal_namespace = namespace_for_delivery("terminal");
     let web_namespace = namespace_for_delivery("web");
     let terminal_pods: Api<Pod> = Api::namespaced(state.kube_client.clone(), &terminal_namespace);
     let web_pods: Api<Pod> = Api::namespaced(state.kube_client.clone(), &web_namespace);
     let web_services: Api<Service> = Api::namespaced(state.kube_client.clone(), &web_namespace);
+    let web_secrets: Api<Secret> = Api::namespaced(state.kube_client.clone(), &web_namespace);
+
+    // Web cookies and SSH logins for this runtime must stop working even if
+    // Pod deletion fails.
+    state.web_cookie_revocations.revoke_runtime(&pod_name);
+    state.ide_tokens.forget(&pod_name);
+    state.ssh_credentials.revoke_runtime(&pod_name);
 
     let _ = delete_pod_if_exists(&terminal_pods, &pod_name, &terminal_namespace).await;
     let _ = delete_pod_if_exists(&web_pods, &pod_name, &web_namespace).await;
-    let _ = delete_service_if_exists(
-        &web_services,
-        &build_web_service_name(&pod_name),
-        &web_namespace,
-    )
-    .await;
+    for service_name in [
+        build_web_service_name(&pod_name),
+        build_desktop_service_name(&pod_name),
+    ] {
+        let _ = delete_service_if_exists(&web_services, &service_name, &web_namespace).await;
+    }
+    let _ = web_secrets
+        .delete(&build_ide_secret_name(&pod_name), &DeleteParams::default())
+        .await;
 }
 
 pub async fn status_lab(state: State, pod_name: String) -> String {
@@ -673,6 +835,65 @@ pub async fn status_lab(state: State, pod_name: String) -> String {
         .unwrap_or_else(|| "unknown".to_string())
 }
 
+/// Finds a runtime Pod in the terminal namespace first, then the web namespace,
+/// following the same split as `status_lab`.
+pub(crate) async fn find_runtime_pod(state: &State, pod_name: &str) -> Option<(String, Pod)> {
+    for lab_delivery in ["terminal", "web"] {
+        let namespace = namespace_for_delivery(lab_delivery);
+        let pods: Api<Pod> = Api::namespaced(state.kube_client.clone(), &namespace);
+
+        match pods.get(pod_name).await {
+            Ok(pod) => return Some((namespace, pod)),
+            Err(kube::Error::Api(api_error)) if api_error.code == 404 => continue,
+            Err(error) => {
+                error!(namespace = %namespace, pod_name = %pod_name, error = ?error, "Failed to get runtime pod");
+                return None;
+            }
+        }
+    }
+
+    None
+}
+
+/// Only the user a runtime was spawned for may reach it directly. Runtimes
+/// spawned without a `user_id` (anonymous previews) have no owner to match.
+pub(crate) fn ensure_runtime_owner(pod: &Pod, user_id: Uuid) -> Result<(), StatusCode> {
+    let owner = pod
+        .metadata
+        .labels
+        .as_ref()
+        .and_then(|labels| labels.get("user_id"))
+        .and_then(|value| Uuid::parse_str(value).ok());
+    if owner == Some(user_id) {
+        return Ok(());
+    }
+
+    warn!(
+        pod_name = ?pod.metadata.name,
+        user_id = %user_id,
+        action = "runtime_owner",
+        "caller does not own the runtime"
+    );
+    Err(StatusCode::FORBIDDEN)
+}
+
+/// Unix time at which `activeDeadlineSeconds` stops the runtime, counted from
+/// the Pod's start like the kubelet does.
+pub(crate) fn runtime_deadline(pod: &Pod) -> Option<i64> {
+    let started = pod
+        .status
+        .as_ref()
+        .and_then(|status| status.start_time.as_ref())
+        .or(pod.metadata.creation_timestamp.as_ref())?;
+    let deadline_secs = pod
+        .spec
+        .as_ref()
+        .and_then(|spec| spec.active_deadline_seconds)
+        .unwrap_or(POD_DEADLINE_SECS);
+
+    Some(started.0.as_second().saturating_add(deadline_secs))
+}
+
 async fn delete_pod_if_exists(pods: &Api<Pod>, pod_name: &str, namespace: &str) -> bool {
     match pods.delete(pod_name, &DeleteParams::default()).await {
         Ok(_) => true,
@@ -737,8 +958,14 @@ async fn get_pod_status(pods: &Api<Pod>, pod_name: &str, namespace: &str) -> Opt
 
 #[cfg(test)]
 mod tests {
-    use super::{build_pod, normalize_pod_phase, TERMINAL_KEEPALIVE_SCRIPT};
-    use crate::models::SpawnRequest;
+    use super::{
+        build_desktop_service, build_pod, build_web_service, ensure_runtime_owner,
+        is_valid_spawn_payload, namespace_for_delivery, normalize_pod_phase, runtime_deadline,
+        DESKTOP_VNC_PORT, IDE_PORT, LAB_CONTAINER_NAME, TERMINAL_KEEPALIVE_SCRIPT,
+    };
+    use crate::models::{IdeKind, SpawnRequest};
+    use crate::services::lab_ide::{resolve_ide_kind, WORKSPACE_PATH};
+    use k8s_openapi::apimachinery::pkg::util::intstr::IntOrString;
     use uuid::Uuid;
 
     fn terminal_spawn_request() -> SpawnRequest {
@@ -752,6 +979,10 @@ mod tests {
             lab_delivery: "terminal".to_string(),
             app_port: None,
             session_flags: serde_json::json!({}),
+            webshell: None,
+            terminal_capture: None,
+            web_rewrite: None,
+            ide: None,
         }
     }
 
@@ -783,6 +1014,134 @@ mod tests {
         assert!(container.args.is_none());
     }
 
+    #[test]
+    fn desktop_service_targets_the_vnc_port() {
+        let mut payload = terminal_spawn_request();
+        payload.lab_delivery = "desktop".to_string();
+        assert!(is_valid_spawn_payload(&payload));
+
+        let service = build_desktop_service("ctf-runtime-42", &payload);
+        let spec = service.spec.unwrap();
+        let port = &spec.ports.unwrap()[0];
+
+        assert_eq!(
+            service.metadata.name.as_deref(),
+            Some("ctf-runtime-42-desktop")
+        );
+        assert_eq!(spec.selector.unwrap()["runtime_kind"], "desktop");
+        assert_eq!(port.port, DESKTOP_VNC_PORT);
+        assert_eq!(port.target_port, Some(IntOrString::Int(DESKTOP_VNC_PORT)));
+
+        payload.app_port = Some(5901);
+        let service = build_desktop_service("ctf-runtime-42", &payload);
+        assert_eq!(
+            service.spec.unwrap().ports.unwrap()[0].target_port,
+            Some(IntOrString::Int(5901))
+        );
+    }
+
+    #[test]
+    fn ide_pod_runs_the_ide_next_to_the_lab_container_on_a_shared_workspace() {
+        let mut payload = terminal_spawn_request();
+        payload.lab_delivery = "ide".to_string();
+        payload.ide = Some(IdeKind::Jupyter);
+        assert!(is_valid_spawn_payload(&payload));
+        assert_eq!(namespace_for_delivery("ide"), namespace_for_delivery("web"));
+
+        let pod = build_pod("ctf-runtime-42", "test-secret", &payload, true);
+        assert_eq!(resolve_ide_kind(&pod), Some(IdeKind::Jupyter));
+        assert_eq!(
+            pod.metadata.annotations.as_ref().unwrap()[super::WEBSHELL_SETTINGS_ANNOTATION],
+            format!(r#"{{"container":"{LAB_CONTAINER_NAME}"}}"#)
+        );
+        let spec = pod.spec.unwrap();
+        let [lab, ide] = spec.containers.as_slice() else {
+            panic!("expected the lab and IDE containers");
+        };
+        assert_eq!(lab.args, Some(vec![TERMINAL_KEEPALIVE_SCRIPT.to_string()]));
+        for container in [lab, ide] {
+            assert!(container
+                .volume_mounts
+                .as_ref()
+                .unwrap()
+                .iter()
+                .any(|mount| mount.mount_path == WORKSPACE_PATH));
+        }
+        let token = &ide.env.as_ref().unwrap()[0];
+        assert_eq!(token.name, "JUPYTER_TOKEN");
+        assert_eq!(
+            token
+                .value_from
+                .as_ref()
+                .and_then(|source| source.secret_key_ref.as_ref())
+                .map(|secret| secret.name.as_str()),
+            Some("ctf-runtime-42-ide")
+        );
+        assert_eq!(
+            spec.init_containers.unwrap()[0].image.as_deref(),
+            Some("example.test/lab:latest")
+        );
+
+        let service = build_web_service("ctf-runtime-42", &payload).spec.unwrap();
+        assert_eq!(service.selector.unwrap()["runtime_kind"], "ide");
+        assert_eq!(
+            service.ports.unwrap()[0].target_port,
+            Some(IntOrString::Int(IDE_PORT))
+        );
+    }
+
+    #[test]
+    fn webshell_settings_are_stored_as_pod_annotation() {
+        let mut payload = terminal_spawn_request();
+        payload.webshell = Some(crate::models::WebShellSettings {
+            user: Some("student".to_string()),
+            ..Default::default()
+        });
+        let pod = build_pod("test-pod", "test-secret", &payload, true);
+
+        assert_eq!(
+            pod.metadata
+                .annotations
+                .unwrap()
+                .get(super::WEBSHELL_SETTINGS_ANNOTATION)
+                .map(String::as_str),
+            Some(r#"{"user":"student"}"#)
+        );
+    }
+
+    #[test]
+    fn terminal_capture_policy_is_stored_as_pod_label() {
+        let mut payload = terminal_spawn_request();
+        payload.terminal_capture = Some(crate::models::TerminalCapturePolicy::Off);
+        let pod = build_pod("test-pod", "test-secret", &payload, true);
+
+        assert_eq!(
+            pod.metadata
+                .labels
+                .unwrap()
+                .get(super::TERMINAL_CAPTURE_LABEL)
+                .map(String::as_str),
+            Some("off")
+        );
+    }
+
+    #[test]
+    fn web_rewrite_policy_is_stored_as_pod_label() {
+        let mut payload = terminal_spawn_request();
+        payload.lab_delivery = "web".to_string();
+        payload.web_rewrite = Some(crate::models::WebRewritePolicy::HeadersAndContent);
+        let pod = build_pod("test-pod", "test-secret", &payload, true);
+
+        assert_eq!(
+            pod.metadata
+                .labels
+                .unwrap()
+                .get(crate::services::web_response_rewriting::WEB_REWRITE_LABEL)
+                .map(String::as_str),
+            Some("headers-and-content")
+        );
+    }
+
     #[test]
     fn local_mode_does_not_reference_image_pull_secret() {
         let payload = terminal_spawn_request();
@@ -791,6 +1150,39 @@ mod tests {
         assert!(pod.spec.unwrap().image_pull_secrets.is_none());
     }
 
+    #[test]
+    fn runtime_deadline_counts_from_pod_start() {
+        let mut pod = build_pod("test-pod", "test-secret", &terminal_spawn_request(), false);
+        assert_eq!(runtime_deadline(&pod), None);
+
+        pod.status = Some(k8s_openapi::api::core::v1::PodStatus {
+            start_time: Some(k8s_openapi::apimachinery::pkg::apis::meta::v1::Time(
+                k8s_openapi::jiff::Timestamp::from_second(1_000).unwrap(),
+            )),
+            ..Default::default()
+        });
+        assert_eq!(runtime_deadline(&pod), Some(1_000 + 7_200));
+    }
+
+    #[test]
+    fn only_the_spawning_user_owns_a_runtime() {
+        let owner = Uuid::new_v4();
+        let pod = build_pod(
+            "test-pod",
+            "test-secret",
+            &SpawnRequest {
+                user_id: Some(owner),
+                ..terminal_spawn_request()
+            },
+            false,
+        );
+        assert!(ensure_runtime_owner(&pod, owner).is_ok());
+        assert!(ensure_runtime_owner(&pod, Uuid::new_v4()).is_err());
+
+        let anonymous = build_pod("test-pod", "test-secret", &terminal_spawn_request(), false);
+        assert!(ensure_runtime_owner(&anonymous, owner).is_err());
+    }
+
     #[test]
     fn pod_phase_is_normalized_for_public_status() {
         assert_eq!(normalize_pod_phase(Some("Pending")), "starting");
@@ -0,0 +1,768 @@
+/**
+ * @file ssh_gateway — SSH entry point to lab runtimes.
+ *
+ * @remarks
+ * Runs an optional SSH server inside lab-api so learners can reach their
+ * runtime with their own terminal, `scp` and local port forwarding.
+ *
+ * Responsibilities:
+ *
+ *  - Issue short-lived per-runtime SSH credentials (password and optional key)
+ *  - Authenticate SSH logins and map the username to a runtime Pod
+ *  - Bridge interactive shells to the same Kubernetes exec path as the web shell
+ *  - Run one-off commands (`ssh host cmd`, `scp -O`) through exec without a TTY
+ *  - Serve the `sftp` subsystem (`sftp`, and `scp` on OpenSSH 9+) by running
+ *    the runtime's own `sftp-server` through exec
+ *  - Tunnel `direct-tcpip` channels to Pod ports on localhost
+ *
+ * Key characteristics:
+ *
+ *  - Disabled unless `LAB_SSH_GATEWAY_ENABLED` is set
+ *  - Credentials live in memory, expire after `LAB_SSH_CREDENTIAL_TTL_SECS`
+ *    and are revoked when the runtime is stopped
+ *  - Shell and command sessions follow the web shell idle and maximum
+ *    duration limits (`WEBSHELL_*`), with the same warning notices on stderr
+ *  - Window-change requests are forwarded as `TerminalSize` updates
+ *  - A dropped client connection closes its runtime exec instead of waiting
+ *    for the shell to exit
+ *  - Uses a configured host key, or an ephemeral one for local development
+ *  - `LAB_SSH_SFTP_SERVER` overrides where `sftp-server` is looked up; runtimes
+ *    without one answer SFTP with an error, and learners fall back to `scp -O`
+ *  - SSH sessions do not go through the web shell capture pipeline: no command,
+ *    engagement or terminal events are recorded for them, whatever the
+ *    runtime's terminal capture policy
+ *
+ * This module lets advanced learners use native SSH tooling while keeping
+ * runtime access scoped to credentials issued by lab-api.
+ *
+ * @packageDocumentation
+ */
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use k8s_openapi::api::core::v1::Pod;
+use kube::{
+    api::{AttachParams, TerminalSize},
+    Api,
+};
+use rand::{distr::Alphanumeric, RngExt};
+use russh::{
+    keys::{Algorithm, PrivateKey, PublicKey},
+    server::{Auth, ChannelOpenHandle, Config, Handler, Msg, Server, Session},
+    Channel, ChannelId, ChannelMsg, ChannelOpenFailure,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+    sync::mpsc,
+    time::Instant,
+};
+use tracing::{error, info, warn};
+
+use crate::{
+    models::{SshCredentialsResponseData, State},
+    services::web_shell::{
+        enforce_terminal_session_limits, exec_exit_status, resolve_terminal_runtime,
+        TerminalActivity, TerminalSessionLimits,
+    },
+};
+
+const DEFAULT_LISTEN_ADDR: &str = "0.0.0.0:2222";
+const DEFAULT_PUBLIC_PORT: u16 = 2222;
+const DEFAULT_CREDENTIAL_TTL_SECS: u64 = 3600;
+const PASSWORD_CHARS: usize = 32;
+const BUFFER_SIZE: usize = 4096;
+const NOTICE_QUEUE_SIZE: usize = 4;
+const SFTP_SUBSYSTEM: &str = "sftp";
+/// Where OpenSSH installs `sftp-server` on Debian/Ubuntu, RHEL/Fedora and Alpine/Arch.
+const DEFAULT_SFTP_SERVER_PATHS: &str =
+    "/usr/lib/openssh/sftp-server,/usr/libexec/openssh/sftp-server,/usr/lib/ssh/sftp-server,/usr/libexec/sftp-server";
+
+#[derive(Clone)]
+struct SshCredential {
+    pod_name: String,
+    password: String,
+    public_key: Option<PublicKey>,
+    expires_at: Instant,
+}
+
+/// In-memory SSH credentials issued by lab-api, keyed by login name.
+#[derive(Default)]
+pub struct SshCredentialRegistry {
+    credentials: Mutex<HashMap<String, SshCredential>>,
+}
+
+impl SshCredentialRegistry {
+    /// Issues a fresh login for `pod_name`, optionally bound to an OpenSSH public key.
+    pub fn issue(
+        &self,
+        pod_name: &str,
+        public_key: Option<&str>,
+    ) -> Result<SshCredentialsResponseData, String> {
+        let public_key = public_key
+            .map(|key| {
+                PublicKey::from_openssh(key.trim())
+                    .map_err(|error| format!("invalid OpenSSH public key: {error}"))
+            })
+            .transpose()?;
+        let ttl = Duration::from_secs(credential_ttl_secs());
+        let username = format!("lab-{}", random_token(12).to_ascii_lowercase());
+        let password = random_token(PASSWORD_CHARS);
+
+        let mut credentials = self.credentials.lock().unwrap_or_else(|e| e.into_inner());
+        credentials.retain(|_, credential| credential.expires_at > Instant::now());
+        credentials.insert(
+            username.clone(),
+            SshCredential {
+                pod_name: pod_name.to_string(),
+                password: password.clone(),
+                public_key,
+                expires_at: Instant::now() + ttl,
+            },
+        );
+
+        Ok(SshCredentialsResponseData {
+            username,
+            password,
+            host: std::env::var("LAB_SSH_PUBLIC_HOST").unwrap_or_else(|_| "localhost".to_string()),
+            port: std::env::var("LAB_SSH_PUBLIC_PORT")
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(DEFAULT_PUBLIC_PORT),
+            expires_in_secs: ttl.as_secs(),
+        })
+    }
+
+    /// Revokes every login issued for `pod_name`.
+    pub fn revoke_runtime(&self, pod_name: &str) {
+        let mut credentials = self.credentials.lock().unwrap_or_else(|e| e.into_inner());
+        credentials.retain(|_, credential| credential.pod_name != pod_name);
+    }
+
+    fn authenticate_password(&self, username: &str, password: &str) -> Option<String> {
+        self.lookup(username)
+            .filter(|credential| constant_time_eq(&credential.password, password))
+            .map(|credential| credential.pod_name)
+    }
+
+    fn authenticate_key(&self, username: &str, public_key: &PublicKey) -> Option<String> {
+        self.lookup(username)
+            .filter(|credential| {
+                credential
+                    .public_key
+                    .as_ref()
+                    .is_some_and(|key| key.key_data() == public_key.key_data())
+            })
+            .map(|credential| credential.pod_name)
+    }
+
+    fn lookup(&self, username: &str) -> Option<SshCredential> {
+        let credentials = self.credentials.lock().unwrap_or_else(|e| e.into_inner());
+        credentials
+            .get(username)
+            .filter(|credential| credential.expires_at > Instant::now())
+            .cloned()
+    }
+}
+
+struct SshGateway {
+    state: State,
+}
+
+struct SshGatewaySession {
+    state: State,
+    pod_name: Option<String>,
+}
+
+/// Starts the SSH gateway when `LAB_SSH_GATEWAY_ENABLED` is set.
+pub async fn start_ssh_gateway(state: State) -> Result<(), String> {
+    if !crate::parse_bool_env("LAB_SSH_GATEWAY_ENABLED", false) {
+        return Ok(());
+    }
+
+    let addr =
+        std::env::var("LAB_SSH_LISTEN_ADDR").unwrap_or_else(|_| DEFAULT_LISTEN_ADDR.to_string());
+    let config = Arc::new(Config {
+        keys: vec![load_host_key()?],
+        inactivity_timeout: TerminalSessionLimits::from_env().idle_timeout(),
+        auth_rejection_time: Duration::from_secs(1),
+        auth_rejection_time_initial: Some(Duration::ZERO),
+        ..Default::default()
+    });
+    let listener = TcpListener::bind(&addr)
+        .await
+        .map_err(|e| format!("Failed to bind SSH gateway to {}: {}", addr, e))?;
+
+    info!("SSH gateway listening on {}", addr);
+    tokio::spawn(async move {
+        let mut gateway = SshGateway { state };
+        if let Err(error) = gateway.run_on_socket(config, &listener).await {
+            error!(error = ?error, "SSH gateway stopped");
+        }
+    });
+
+    Ok(())
+}
+
+fn load_host_key() -> Result<PrivateKey, String> {
+    match std::env::var("LAB_SSH_HOST_KEY_FILE") {
+        Ok(path) => russh::keys::load_secret_key(&path, None)
+            .map_err(|e| format!("Failed to load SSH host key {}: {}", path, e)),
+        Err(_) => {
+            warn!("LAB_SSH_HOST_KEY_FILE is not set; using an ephemeral SSH host key");
+            PrivateKey::random(&mut rand::rng(), Algorithm::Ed25519)
+                .map_err(|e| format!("Failed to generate SSH host key: {}", e))
+        }
+    }
+}
+
+impl Server for SshGateway {
+    type Handler = SshGatewaySession;
+
+    fn new_client(&mut self, _peer_addr: Option<std::net::SocketAddr>) -> SshGatewaySession {
+        SshGatewaySession {
+            state: self.state.clone(),
+            pod_name: None,
+        }
+    }
+}
+
+impl Handler for SshGatewaySession {
+    type Error = russh::Error;
+
+    async fn auth_password(&mut self, user: &str, password: &str) -> Result<Auth, Self::Error> {
+        Ok(self.accept_login(
+            user,
+            self.state
+                .ssh_credentials
+                .authenticate_password(user, password),
+        ))
+    }
+
+    async fn auth_publickey(
+        &mut self,
+        user: &str,
+        public_key: &PublicKey,
+    ) -> Result<Auth, Self::Error> {
+        Ok(self.accept_login(
+            user,
+            self.state
+                .ssh_credentials
+                .authenticate_key(user, public_key),
+        ))
+    }
+
+    async fn channel_open_session(
+        &mut self,
+        channel: Channel<Msg>,
+        reply: ChannelOpenHandle,
+        _session: &mut Session,
+    ) -> Result<(), Self::Error> {
+        let Some(pod_name) = self.pod_name.clone() else {
+            reply
+                .reject(ChannelOpenFailure::AdministrativelyProhibited)
+                .await;
+            return Ok(());
+        };
+
+        reply.accept().await;
+        tokio::spawn(run_session_channel(self.state.clone(), pod_name, channel));
+        Ok(())
+    }
+
+    async fn channel_open_direct_tcpip(
+        &mut self,
+        channel: Channel<Msg>,
+        host_to_connect: &str,
+        port_to_connect: u32,
+        _originator_address: &str,
+        _originator_port: u32,
+        reply: ChannelOpenHandle,
+        _session: &mut Session,
+    ) -> Result<(), Self::Error> {
+        // Forwarding is limited to ports of the learner's own Pod.
+        let port = u16::try_from(port_to_connect).ok().filter(|port| *port > 0);
+        let (Some(pod_name), Some(port), true) = (
+            self.pod_name.clone(),
+            port,
+            matches!(host_to_connect, "localhost" | "127.0.0.1" | "::1"),
+        ) else {
+            reply
+                .reject(ChannelOpenFailure::AdministrativelyProhibited)
+                .await;
+            return Ok(());
+        };
+
+        reply.accept().await;
+        tokio::spawn(run_port_forward(
+            self.state.clone(),
+            pod_name,
+            port,
+            channel,
+        ));
+        Ok(())
+    }
+
+    async fn pty_request(
+        &mut self,
+        channel: ChannelId,
+        _term: &str,
+        _col_width: u32,
+        _row_height: u32,
+        _pix_width: u32,
+        _pix_height: u32,
+        _modes: &[(russh::Pty, u32)],
+        session: &mut Session,
+    ) -> Result<(), Self::Error> {
+        session.channel_success(channel)
+    }
+
+    async fn shell_request(
+        &mut self,
+        channel: ChannelId,
+        session: &mut Session,
+    ) -> Result<(), Self::Error> {
+        session.channel_success(channel)
+    }
+
+    async fn exec_request(
+        &mut self,
+        channel: ChannelId,
+        _data: &[u8],
+        session: &mut Session,
+    ) -> Result<(), Self::Error> {
+        session.channel_success(channel)
+    }
+
+    async fn subsystem_request(
+        &mut self,
+        channel: ChannelId,
+        name: &str,
+        session: &mut Session,
+    ) -> Result<(), Self::Error> {
+        if name == SFTP_SUBSYSTEM {
+            session.channel_success(channel)
+        } else {
+            warn!(subsystem = %name, action = "ssh_exec", "Rejected SSH subsystem request");
+            session.channel_failure(channel)
+        }
+    }
+
+    async fn window_change_request(
+        &mut self,
+        channel: ChannelId,
+        _col_width: u32,
+        _row_height: u32,
+        _pix_width: u32,
+        _pix_height: u32,
+        session: &mut Session,
+    ) -> Result<(), Self::Error> {
+        session.channel_success(channel)
+    }
+}
+
+impl SshGatewaySession {
+    fn accept_login(&mut self, user: &str, pod_name: Option<String>) -> Auth {
+        match pod_name {
+            Some(pod_name) => {
+                info!(
+                    username = %user,
+                    pod_name = %pod_name,
+                    action = "ssh_login",
+                    "SSH gateway login accepted"
+                );
+                self.pod_name = Some(pod_name);
+                Auth::Accept
+            }
+            None => {
+                warn!(username = %user, action = "ssh_login", "SSH gateway login rejected");
+                Auth::reject()
+            }
+        }
+    }
+}
+
+async fn run_session_channel(state: State, pod_name: String, mut channel: Channel<Msg>) {
+    let mut terminal_size = None;
+
+    // The shell or command starts only once the client has sent its requests;
+    // the handler callbacks above acknowledge them.
+    let command = loop {
+        match channel.wait().await {
+            Some(ChannelMsg::RequestPty {
+                col_width,
+                row_height,
+                ..
+            }) => terminal_size = to_terminal_size(col_width, row_height),
+            Some(ChannelMsg::RequestShell { .. }) => break None,
+            Some(ChannelMsg::Exec { command, .. }) => {
+                break Some(String::from_utf8_lossy(&command).into_owned())
+            }
+            Some(ChannelMsg::RequestSubsystem { name, .. }) if name == SFTP_SUBSYSTEM => {
+                break Some(sftp_server_command())
+            }
+            Some(_) => continue,
+            None => return,
+        }
+    };
+
+    if let Err(reason) = bridge_exec(&state, &pod_name, channel, command, terminal_size).await {
+        warn!(
+            pod_name = %pod_name,
+            reason = %reason,
+            action = "ssh_exec",
+            "SSH gateway session failed"
+        );
+    }
+}
+
+async fn bridge_exec(
+    state: &State,
+    pod_name: &str,
+    channel: Channel<Msg>,
+    command: Option<String>,
+    terminal_size: Option<TerminalSize>,
+) -> Result<(), String> {
+    let runtime = match resolve_terminal_runtime(state, pod_name).await {
+        Ok(runtime) => runtime,
+        Err(reason) => {
+            let mut stderr = channel.make_writer_ext(Some(1));
+            let _ = stderr
+                .write_all(format!("[altair] SSH unavailable: {reason}\r\n").as_bytes())
+                .await;
+            let _ = channel.exit_status(1).await;
+            let _ = channel.close().await;
+            return Err(reason);
+        }
+    };
+    let pods: Api<Pod> = Api::namespaced(state.kube_client.clone(), &runtime.namespace);
+    let tty = command.is_none() && terminal_size.is_some();
+    let argv = match &command {
+        Some(command) => runtime.launch.exec_command(command),
+        None => runtime.launch.command.clone(),
+    };
+    let attach_params = AttachParams {
+        stdin: true,
+        stdout: true,
+        stderr: !tty,
+        tty,
+        container: runtime.launch.container.clone(),
+        ..Default::default()
+    };
+
+    info!(
+        namespace = %runtime.namespace,
+        pod_name = %pod_name,
+        tty,
+        exec = command.is_some(),
+        action = "ssh_exec",
+        "opening SSH gateway exec"
+    );
+
+    let mut exec = pods
+        .exec(pod_name, argv, &attach_params)
+        .await
+        .map_err(|e| format!("failed to start exec: {e}"))?;
+    let mut stdin = exec.stdin().ok_or("exec did not provide stdin")?;
+    let mut stdout = exec.stdout().ok_or("exec did not provide stdout")?;
+    let mut terminal_size_tx = exec.terminal_size();
+    if let (Some(tx), Some(size)) = (terminal_size_tx.as_mut(), terminal_size) {
+        let _ = futures::SinkExt::send(tx, size).await;
+    }
+
+    let limits = TerminalSessionLimits::from_env();
+    let activity = TerminalActivity::new();
+    let (notice_tx, mut notice_rx) = mpsc::channel::<String>(NOTICE_QUEUE_SIZE);
+    let (mut channel_rx, channel_tx) = channel.split();
+    if let Some(mut stderr) = exec.stderr() {
+        let mut stderr_writer = channel_tx.make_writer_ext(Some(1));
+        tokio::spawn(async move {
+            let _ = tokio::io::copy(&mut stderr, &mut stderr_writer).await;
+        });
+    }
+
+    let from_client = async {
+        while let Some(msg) = channel_rx.wait().await {
+            let forwarded = match msg {
+                ChannelMsg::Data { data } => {
+                    activity.touch();
+                    stdin.write_all(&data).await.is_ok()
+                }
+                ChannelMsg::Eof => stdin.shutdown().await.is_ok(),
+                ChannelMsg::WindowChange {
+                    col_width,
+                    row_height,
+                    ..
+                } => {
+                    if let (Some(tx), Some(size)) = (
+                        terminal_size_tx.as_mut(),
+                        to_terminal_size(col_width, row_height),
+                    ) {
+                        let _ = futures::SinkExt::send(tx, size).await;
+                    }
+                    true
+                }
+                _ => true,
+            };
+            if !forwarded {
+                return false;
+            }
+        }
+        // The client went away without waiting for the command to finish.
+        true
+    };
+    let from_pod = async {
+        let mut buf = [0u8; BUFFER_SIZE];
+        loop {
+            // Limit notices go to stderr so they never corrupt scp or sftp streams.
+            let sent = tokio::select! {
+                read = stdout.read(&mut buf) => match read {
+                    Ok(n) if n > 0 => {
+                        activity.touch();
+                        channel_tx.data_bytes(buf[..n].to_vec()).await
+                    }
+                    _ => break,
+                },
+                Some(notice) = notice_rx.recv() => {
+                    channel_tx.extended_data_bytes(1, notice.into_bytes()).await
+                }
+            };
+            if sent.is_err() {
+                break;
+            }
+        }
+    };
+
+    let (client_gone, limit_reached) = tokio::select! {
+        client_gone = from_client => (client_gone, None),
+        _ = from_pod => (false, None),
+        reason = enforce_terminal_session_limits(&limits, &activity, notice_tx) => {
+            (false, Some(reason))
+        }
+    };
+
+    // An interactive shell never exits on its own once the client is gone, so
+    // its exec stream is torn down instead of waiting for a status.
+    let _ = stdin.shutdown().await;
+    drop(stdin);
+    if let Some(reason) = limit_reached {
+        exec.abort();
+        info!(
+            pod_name = %pod_name,
+            reason = reason.as_str(),
+            elapsed_secs = activity.elapsed().as_secs(),
+            action = "ssh_limit",
+            "closing SSH session after session limit"
+        );
+        let _ = channel_tx
+            .extended_data_bytes(1, reason.closing_notice().as_bytes().to_vec())
+            .await;
+        let _ = channel_tx.exit_status(1).await;
+        let _ = channel_tx.eof().await;
+        let _ = channel_tx.close().await;
+        return Ok(());
+    }
+    if client_gone {
+        exec.abort();
+        info!(
+            pod_name = %pod_name,
+            action = "ssh_exec",
+            "SSH client disconnected; closed runtime exec"
+        );
+        return Ok(());
+    }
+
+    let exit_status = exec_exit_status(&mut exec).await;
+    let _ = channel_tx.exit_status(exit_status).await;
+    let _ = channel_tx.eof().await;
+    let _ = channel_tx.close().await;
+    Ok(())
+}
+
+async fn run_port_forward(state: State, pod_name: String, port: u16, channel: Channel<Msg>) {
+    let Ok(runtime) = resolve_terminal_runtime(&state, &pod_name).await else {
+        let _ = channel.close().await;
+        return;
+    };
+    let pods: Api<Pod> = Api::namespaced(state.kube_client.clone(), &runtime.namespace);
+
+    info!(
+        namespace = %runtime.namespace,
+        pod_name = %pod_name,
+        port,
+        action = "ssh_port_forward",
+        "opening SSH gateway port forward"
+    );
+
+    let mut forwarder = match pods.portforward(&pod_name, &[port]).await {
+        Ok(forwarder) => forwarder,
+        Err(error) => {
+            warn!(pod_name = %pod_name, port, error = ?error, "Failed to open port forward");
+            let _ = channel.close().await;
+            return;
+        }
+    };
+    let Some(mut upstream) = forwarder.take_stream(port) else {
+        let _ = channel.close().await;
+        return;
+    };
+
+    let mut downstream = channel.into_stream();
+    let _ = tokio::io::copy_bidirectional(&mut upstream, &mut downstream).await;
+    drop(upstream);
+    let _ = forwarder.join().await;
+}
+
+/// Runs the first `sftp-server` found in the runtime, or explains the `scp -O` fallback.
+fn sftp_server_command() -> String {
+    let paths = std::env::var("LAB_SSH_SFTP_SERVER")
+        .unwrap_or_else(|_| DEFAULT_SFTP_SERVER_PATHS.to_string());
+    let candidates = paths
+        .split(',')
+        .map(str::trim)
+        .filter(|path| !path.is_empty())
+        .map(|path| format!("'{}'", path.replace('\'', r"'\''")))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    format!(
+        "for p in {candidates}; do [ -x \"$p\" ] && exec \"$p\"; done; \
+         echo '[altair] SFTP is not available in this lab; use scp -O' >&2; exit 127"
+    )
+}
+
+fn to_terminal_size(col_width: u32, row_height: u32) -> Option<TerminalSize> {
+    let width = u16::try_from(col_width).ok().filter(|w| *w > 0)?;
+    let height = u16::try_from(row_height).ok().filter(|h| *h > 0)?;
+    Some(TerminalSize { width, height })
+}
+
+fn credential_ttl_secs() -> u64 {
+    std::env::var("LAB_SSH_CREDENTIAL_TTL_SECS")
+        .ok()
+        .and_then(|value| value.parse::<u64>().ok())
+        .filter(|value| *value > 0)
+        .unwrap_or(DEFAULT_CREDENTIAL_TTL_SECS)
+}
+
+fn random_token(len: usize) -> String {
+    rand::rng()
+        .sample_iter(Alphanumeric)
+        .take(len)
+        .map(char::from)
+        .collect()
+}
+
+fn constant_time_eq(expected: &str, provided: &str) -> bool {
+    let expected = expected.as_bytes();
+    let provided = provided.as_bytes();
+    expected.len() == provided.len()
+        && expected
+            .iter()
+            .zip(provided)
+            .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{constant_time_eq, sftp_server_command, to_terminal_size, SshCredentialRegistry};
+    use russh::keys::{Algorithm, PrivateKey};
+
+    #[test]
+    fn issued_password_maps_username_to_runtime() {
+        let registry = SshCredentialRegistry::default();
+        let issued = registry.issue("ctf-runtime-1", None).unwrap();
+
+        assert!(issued.username.starts_with("lab-"));
+        assert_eq!(
+            registry.authenticate_password(&issued.username, &issued.password),
+            Some("ctf-runtime-1".to_string())
+        );
+        assert_eq!(
+            registry.authenticate_password(&issued.username, "wrong"),
+            None
+        );
+        assert_eq!(
+            registry.authenticate_password("lab-unknown", &issued.password),
+            None
+        );
+    }
+
+    #[test]
+    fn stopping_a_runtime_revokes_its_logins() {
+        let registry = SshCredentialRegistry::default();
+        let stopped = registry.issue("ctf-runtime-1", None).unwrap();
+        let running = registry.issue("ctf-runtime-2", None).unwrap();
+
+        registry.revoke_runtime("ctf-runtime-1");
+
+        assert_eq!(
+            registry.authenticate_password(&stopped.username, &stopped.password),
+            None
+        );
+        assert_eq!(
+            registry.authenticate_password(&running.username, &running.password),
+            Some("ctf-runtime-2".to_string())
+        );
+    }
+
+    #[test]
+    fn issued_key_authenticates_only_its_holder() {
+        let registry = SshCredentialRegistry::default();
+        let key = PrivateKey::random(&mut rand::rng(), Algorithm::Ed25519).unwrap();
+        let other = PrivateKey::random(&mut rand::rng(), Algorithm::Ed25519).unwrap();
+        let issued = registry
+            .issue(
+                "ctf-runtime-2",
+                Some(&key.public_key().to_openssh().unwrap()),
+            )
+            .unwrap();
+
+        assert_eq!(
+            registry.authenticate_key(&issued.username, key.public_key()),
+            Some("ctf-runtime-2".to_string())
+        );
+        assert_eq!(
+            registry.authenticate_key(&issued.username, other.public_key()),
+            None
+        );
+    }
+
+    #[test]
+    fn invalid_public_key_is_rejected() {
+        let registry = SshCredentialRegistry::default();
+
+        assert!(registry
+            .issue("ctf-runtime-3", Some("ssh-ed25519 not-base64"))
+            .is_err());
+    }
+
+    #[test]
+    fn sftp_runs_the_first_server_found_in_the_runtime() {
+        let command = sftp_server_command();
+
+        assert!(command.starts_with("for p in '/usr/lib/openssh/sftp-server' "));
+        assert!(command.contains("exec \"$p\""));
+        assert!(command.ends_with("use scp -O' >&2; exit 127"));
+    }
+
+    #[test]
+    fn window_changes_map_to_terminal_size() {
+        let size = to_terminal_size(120, 40).unwrap();
+
+        assert_eq!((size.width, size.height), (120, 40));
+        assert!(to_terminal_size(0, 40).is_none());
+        assert!(to_terminal_size(70_000, 40).is_none());
+    }
+
+    #[test]
+    fn password_comparison_requires_exact_match() {
+        assert!(constant_time_eq("secret", "secret"));
+        assert!(!constant_time_eq("secret", "secreT"));
+        assert!(!constant_time_eq("secret", "secret2"));
+    }
+}
@@ -0,0 +1,520 @@
+/**
+ * @file terminal_event_outbox — durable delivery of terminal analytics events.
+ *
+ * @remarks
+ * Spools batches of terminal command events to disk and delivers them to
+ * sessions-ms in the background, so analytics survive sessions-ms outages
+ * and Lab API restarts.
+ *
+ * Responsibilities:
+ *
+ *  - Persist each event batch as one file in the spool directory
+ *  - Bound the spool by batch count, dropping new batches when full; the
+ *    count is kept in memory and only read from disk at startup
+ *  - Deliver spooled batches oldest first with exponential backoff, through
+ *    the shared sessions-ms client
+ *  - Remove batches once delivered or permanently rejected
+ *  - Count enqueued, delivered and dropped events for `/metrics`
+ *
+ * Key characteristics:
+ *
+ *  - Event ids are assigned before spooling, so retries are idempotent
+ *  - Files are written to a temporary name and renamed into place
+ *  - `TERMINAL_EVENTS_SPOOL_DIR` should point at a persistent volume in
+ *    production; without it batches are spooled to the temp directory, are lost
+ *    with the container, and a warning is logged at startup
+ *
+ * @packageDocumentation
+ */
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use axum::http::StatusCode;
+use tokio::sync::Notify;
+use tokio::time::{sleep, Duration};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::services::sessions_ms_client::SessionsMsClient;
+
+const DEFAULT_SPOOL_DIR: &str = "altair-terminal-events";
+const DEFAULT_MAX_SPOOLED_BATCHES: usize = 10_000;
+const DEFAULT_BACKOFF_BASE_MS: u64 = 1_000;
+const DEFAULT_BACKOFF_MAX_MS: u64 = 300_000;
+const BATCH_FILE_EXTENSION: &str = "json";
+
+/// Why an event never reached sessions-ms.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TerminalEventDropReason {
+    QueueFull,
+    SpoolFull,
+    SpoolError,
+    Rejected,
+}
+
+impl TerminalEventDropReason {
+    const ALL: [TerminalEventDropReason; 4] = [
+        TerminalEventDropReason::QueueFull,
+        TerminalEventDropReason::SpoolFull,
+        TerminalEventDropReason::SpoolError,
+        TerminalEventDropReason::Rejected,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TerminalEventDropReason::QueueFull => "queue_full",
+            TerminalEventDropReason::SpoolFull => "spool_full",
+            TerminalEventDropReason::SpoolError => "spool_error",
+            TerminalEventDropReason::Rejected => "rejected",
+        }
+    }
+}
+
+#[derive(Default)]
+struct TerminalEventOutboxMetrics {
+    enqueued: AtomicU64,
+    delivered: AtomicU64,
+    delivery_failures: AtomicU64,
+    dropped: [AtomicU64; 4],
+    spooled_batches: AtomicU64,
+}
+
+pub struct TerminalEventOutbox {
+    spool_dir: PathBuf,
+    max_spooled_batches: usize,
+    backoff_base: Duration,
+    backoff_max: Duration,
+    wake: Notify,
+    sequence: AtomicU64,
+    metrics: TerminalEventOutboxMetrics,
+}
+
+impl TerminalEventOutbox {
+    pub fn from_env() -> Self {
+        let spool_dir = match std::env::var("TERMINAL_EVENTS_SPOOL_DIR") {
+            Ok(dir) => PathBuf::from(dir),
+            Err(_) => {
+                let dir = std::env::temp_dir().join(DEFAULT_SPOOL_DIR);
+                warn!(
+                    spool_dir = %dir.display(),
+                    action = "terminal_event_outbox",
+                    "TERMINAL_EVENTS_SPOOL_DIR is not set; terminal events waiting for \
+                     sessions-ms are spooled to the temp directory and lost on restart. \
+                     Mount a persistent volume and point TERMINAL_EVENTS_SPOOL_DIR at it"
+                );
+                dir
+            }
+        };
+
+        Self::new(
+            spool_dir,
+            env_number(
+                "TERMINAL_EVENTS_SPOOL_MAX_BATCHES",
+                DEFAULT_MAX_SPOOLED_BATCHES,
+            ),
+            Duration::from_millis(env_number(
+                "TERMINAL_EVENTS_RETRY_BASE_MS",
+                DEFAULT_BACKOFF_BASE_MS,
+            )),
+            Duration::from_millis(env_number(
+                "TERMINAL_EVENTS_RETRY_MAX_MS",
+                DEFAULT_BACKOFF_MAX_MS,
+            )),
+        )
+    }
+
+    fn new(
+        spool_dir: PathBuf,
+        max_spooled_batches: usize,
+        backoff_base: Duration,
+        backoff_max: Duration,
+    ) -> Self {
+        let metrics = TerminalEventOutboxMetrics::default();
+        metrics
+            .spooled_batches
+            .store(count_spooled_batches(&spool_dir), Ordering::Relaxed);
+
+        Self {
+            spool_dir,
+            max_spooled_batches,
+            backoff_base,
+            backoff_max,
+            wake: Notify::new(),
+            sequence: AtomicU64::new(0),
+            metrics,
+        }
+    }
+
+    /// Persists one serialized batch; the delivery loop picks it up from disk.
+    pub async fn enqueue(&self, body: Vec<u8>, event_count: usize) {
+        let event_count = event_count as u64;
+
+        // Reserve a spool slot up front so concurrent enqueues cannot overshoot the cap.
+        let max_batches = self.max_spooled_batches as u64;
+        let reserved = self.metrics.spooled_batches.fetch_update(
+            Ordering::Relaxed,
+            Ordering::Relaxed,
+            |count| (count < max_batches).then_some(count + 1),
+        );
+        if reserved.is_err() {
+            warn!(
+                spool_dir = %self.spool_dir.display(),
+                max_batches = self.max_spooled_batches,
+                "Dropped terminal event batch because the spool is full"
+            );
+            self.record_dropped(TerminalEventDropReason::SpoolFull, event_count);
+            return;
+        }
+
+        // Timestamp and sequence prefixes keep lexical order equal to enqueue order.
+        let name = format!(
+            "{:020}-{:012}-{}-{}.{}",
+            chrono::Utc::now().timestamp_millis(),
+            self.sequence.fetch_add(1, Ordering::Relaxed),
+            event_count,
+            Uuid::new_v4(),
+            BATCH_FILE_EXTENSION
+        );
+        let path = self.spool_dir.join(&name);
+        let tmp_path = self.spool_dir.join(format!(".{name}.tmp"));
+
+        let written = async {
+            tokio::fs::create_dir_all(&self.spool_dir).await?;
+            tokio::fs::write(&tmp_path, &body).await?;
+            tokio::fs::rename(&tmp_path, &path).await
+        }
+        .await;
+
+        if let Err(error) = written {
+            warn!(
+                spool_dir = %self.spool_dir.display(),
+                error = %error,
+                "Dropped terminal event batch because it could not be spooled"
+            );
+            self.metrics.spooled_batches.fetch_sub(1, Ordering::Relaxed);
+            self.record_dropped(TerminalEventDropReason::SpoolError, event_count);
+            return;
+        }
+
+        self.metrics
+            .enqueued
+            .fetch_add(event_count, Ordering::Relaxed);
+        self.wake.notify_one();
+    }
+
+    pub fn record_dropped(&self, reason: TerminalEventDropReason, event_count: u64) {
+        let index = TerminalEventDropReason::ALL
+            .iter()
+            .position(|candidate| *candidate == reason)
+            .unwrap_or_default();
+        self.metrics.dropped[index].fetch_add(event_count, Ordering::Relaxed);
+    }
+
+    /// Prometheus text exposition of the outbox counters.
+    pub fn render_metrics(&self) -> String {
+        let metrics = &self.metrics;
+        let mut output = String::new();
+
+        output.push_str(
+            "# HELP terminal_events_enqueued_total Terminal events written to the outbox spool.\n",
+        );
+        output.push_str("# TYPE terminal_events_enqueued_total counter\n");
+        output.push_str(&format!(
+            "terminal_events_enqueued_total {}\n",
+            metrics.enqueued.load(Ordering::Relaxed)
+        ));
+        output.push_str(
+            "# HELP terminal_events_delivered_total Terminal events accepted by sessions-ms.\n",
+        );
+        output.push_str("# TYPE terminal_events_delivered_total counter\n");
+        output.push_str(&format!(
+            "terminal_events_delivered_total {}\n",
+            metrics.delivered.load(Ordering::Relaxed)
+        ));
+        output.push_str(
+            "# HELP terminal_events_dropped_total Terminal events that will never be delivered.\n",
+        );
+        output.push_str("# TYPE terminal_events_dropped_total counter\n");
+        for (reason, counter) in TerminalEventDropReason::ALL.iter().zip(&metrics.dropped) {
+            output.push_str(&format!(
+                "terminal_events_dropped_total{{reason=\"{}\"}} {}\n",
+                reason.as_str(),
+                counter.load(Ordering::Relaxed)
+            ));
+        }
+        output.push_str("# HELP terminal_event_delivery_failures_total Failed delivery attempts that will be retried.\n");
+        output.push_str("# TYPE terminal_event_delivery_failures_total counter\n");
+        output.push_str(&format!(
+            "terminal_event_delivery_failures_total {}\n",
+            metrics.delivery_failures.load(Ordering::Relaxed)
+        ));
+        output.push_str(
+            "# HELP terminal_event_spooled_batches Batches waiting in the outbox spool.\n",
+        );
+        output.push_str("# TYPE terminal_event_spooled_batches gauge\n");
+        output.push_str(&format!(
+            "terminal_event_spooled_batches {}\n",
+            metrics.spooled_batches.load(Ordering::Relaxed)
+        ));
+
+        output
+    }
+
+    async fn spooled_batch_files(&self) -> std::io::Result<Vec<PathBuf>> {
+        let mut entries = match tokio::fs::read_dir(&self.spool_dir).await {
+            Ok(entries) => entries,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(error) => return Err(error),
+        };
+
+        let mut files = Vec::new();
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if is_batch_file(&path) {
+                files.push(path);
+            }
+        }
+        files.sort();
+
+        Ok(files)
+    }
+}
+
+/// Starts the background loop delivering spooled batches to sessions-ms.
+pub fn start_terminal_event_delivery(
+    outbox: Arc<TerminalEventOutbox>,
+    sessions_ms: Arc<SessionsMsClient>,
+) {
+    tokio::spawn(async move {
+        let mut failures: u32 = 0;
+
+        match outbox.spooled_batch_files().await {
+            Ok(files) if !files.is_empty() => {
+                info!(
+                    batches = files.len(),
+                    spool_dir = %outbox.spool_dir.display(),
+                    "Resuming delivery of spooled terminal events"
+                );
+            }
+            Ok(_) => {}
+            Err(error) => {
+                error!(error = %error, "Failed to read terminal event spool");
+            }
+        }
+
+        loop {
+            let delivered_all = deliver_spooled_batches(&outbox, &sessions_ms).await;
+
+            if delivered_all {
+                failures = 0;
+                outbox.wake.notified().await;
+                continue;
+            }
+
+            failures = failures.saturating_add(1);
+            let delay = backoff_delay(outbox.backoff_base, outbox.backoff_max, failures);
+            warn!(
+                retry_in_ms = delay.as_millis() as u64,
+                attempt = failures,
+                "Terminal event delivery failed; retrying with backoff"
+            );
+            sleep(delay).await;
+        }
+    });
+}
+
+/// Sends every spooled batch in order; returns false as soon as one must be retried.
+async fn deliver_spooled_batches(
+    outbox: &TerminalEventOutbox,
+    sessions_ms: &SessionsMsClient,
+) -> bool {
+    let files = match outbox.spooled_batch_files().await {
+        Ok(files) => files,
+        Err(error) => {
+            warn!(error = %error, "Failed to list terminal event spool");
+            return false;
+        }
+    };
+
+    for path in files {
+        let body = match tokio::fs::read(&path).await {
+            Ok(body) => body,
+            Err(error) => {
+                warn!(path = %path.display(), error = %error, "Failed to read spooled batch");
+                continue;
+            }
+        };
+        let event_count = batch_event_count(&path);
+        let batch_id = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or_default()
+            .to_string();
+
+        match sessions_ms.post_terminal_events(&batch_id, body).await {
+            Ok(()) => {
+                outbox
+                    .metrics
+                    .delivered
+                    .fetch_add(event_count, Ordering::Relaxed);
+            }
+            Err(status) if is_permanent_rejection(status) => {
+                warn!(
+                    status = %status,
+                    events = event_count,
+                    "sessions-ms permanently rejected terminal events; dropping batch"
+                );
+                outbox.record_dropped(TerminalEventDropReason::Rejected, event_count);
+            }
+            Err(status) => {
+                warn!(
+                    status = %status,
+                    "Failed to forward terminal events to sessions-ms"
+                );
+                outbox
+                    .metrics
+                    .delivery_failures
+                    .fetch_add(1, Ordering::Relaxed);
+                return false;
+            }
+        }
+
+        if let Err(error) = tokio::fs::remove_file(&path).await {
+            warn!(path = %path.display(), error = %error, "Failed to remove delivered batch");
+        }
+        let _ = outbox.metrics.spooled_batches.fetch_update(
+            Ordering::Relaxed,
+            Ordering::Relaxed,
+            |count| Some(count.saturating_sub(1)),
+        );
+    }
+
+    true
+}
+
+fn is_permanent_rejection(status: StatusCode) -> bool {
+    status.is_client_error()
+        && status != StatusCode::REQUEST_TIMEOUT
+        && status != StatusCode::TOO_MANY_REQUESTS
+}
+
+fn backoff_delay(base: Duration, max: Duration, failures: u32) -> Duration {
+    let exponent = failures.saturating_sub(1).min(20);
+    base.saturating_mul(1u32 << exponent).min(max)
+}
+
+fn is_batch_file(path: &std::path::Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| !name.starts_with('.') && name.ends_with(BATCH_FILE_EXTENSION))
+}
+
+/// Batches left in the spool by a previous run.
+fn count_spooled_batches(spool_dir: &std::path::Path) -> u64 {
+    std::fs::read_dir(spool_dir)
+        .map(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .filter(|entry| is_batch_file(&entry.path()))
+                .count() as u64
+        })
+        .unwrap_or(0)
+}
+
+fn batch_event_count(path: &std::path::Path) -> u64 {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .and_then(|name| name.split('-').nth(2))
+        .and_then(|count| count.parse().ok())
+        .unwrap_or(0)
+}
+
+fn env_number<T: std::str::FromStr>(key: &str, default: T) -> T {
+    std::env::var(key)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use tokio::time::Duration;
+
+    use super::{backoff_delay, batch_event_count, TerminalEventDropReason, TerminalEventOutbox};
+
+    fn test_outbox(max_batches: usize) -> TerminalEventOutbox {
+        let spool_dir =
+            std::env::temp_dir().join(format!("altair-outbox-test-{}", uuid::Uuid::new_v4()));
+        TerminalEventOutbox::new(
+            spool_dir,
+            max_batches,
+            Duration::from_millis(10),
+            Duration::from_millis(100),
+        )
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        let base = Duration::from_secs(1);
+        let max = Duration::from_secs(60);
+
+        assert_eq!(backoff_delay(base, max, 1), Duration::from_secs(1));
+        assert_eq!(backoff_delay(base, max, 3), Duration::from_secs(4));
+        assert_eq!(backoff_delay(base, max, 30), max);
+    }
+
+    #[tokio::test]
+    async fn batches_are_spooled_in_order_and_bounded() {
+        let outbox = test_outbox(2);
+
+        outbox.enqueue(b"[1]".to_vec(), 3).await;
+        outbox.enqueue(b"[2]".to_vec(), 1).await;
+        outbox.enqueue(b"[3]".to_vec(), 5).await;
+
+        let files = outbox.spooled_batch_files().await.unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(tokio::fs::read(&files[0]).await.unwrap(), b"[1]");
+        assert_eq!(batch_event_count(&files[0]), 3);
+
+        let metrics = outbox.render_metrics();
+        assert!(metrics.contains("terminal_events_enqueued_total 4\n"));
+        assert!(metrics.contains("terminal_events_dropped_total{reason=\"spool_full\"} 5\n"));
+
+        let _ = tokio::fs::remove_dir_all(&outbox.spool_dir).await;
+    }
+
+    #[tokio::test]
+    async fn batches_left_by_a_previous_run_count_against_the_cap() {
+        let previous = test_outbox(2);
+        previous.enqueue(b"[1]".to_vec(), 1).await;
+        previous.enqueue(b"[2]".to_vec(), 1).await;
+
+        let restarted = TerminalEventOutbox::new(
+            previous.spool_dir.clone(),
+            2,
+            Duration::from_millis(10),
+            Duration::from_millis(100),
+        );
+        restarted.enqueue(b"[3]".to_vec(), 4).await;
+
+        let metrics = restarted.render_metrics();
+        assert!(metrics.contains("terminal_event_spooled_batches 2\n"));
+        assert!(metrics.contains("terminal_events_dropped_total{reason=\"spool_full\"} 4\n"));
+
+        let _ = tokio::fs::remove_dir_all(&previous.spool_dir).await;
+    }
+
+    #[test]
+    fn drop_counters_are_labelled_by_reason() {
+        let outbox = test_outbox(1);
+        outbox.record_dropped(TerminalEventDropReason::QueueFull, 2);
+
+        assert!(outbox
+            .render_metrics()
+            .contains("terminal_events_dropped_total{reason=\"queue_full\"} 2\n"));
+        assert!(!PathBuf::from(&outbox.spool_dir).exists());
+    }
+}
@@ -0,0 +1,422 @@
+/**
+ * @file terminal_event_sinks — pluggable destinations for terminal telemetry.
+ *
+ * @remarks
+ * Publishes batches of terminal command events, and the web proxy's
+ * request events, to one or more sinks, selected with `TERMINAL_EVENT_SINKS`
+ * (comma-separated, default `http`).
+ *
+ * Available sinks:
+ *
+ *  - `http` → durable outbox delivering to sessions-ms
+ *  - `file` → newline-delimited JSON appended to `TERMINAL_EVENTS_JSONL_PATH`
+ *  - `stdout` → newline-delimited JSON on standard output
+ *
+ * Key characteristics:
+ *
+ *  - Every configured sink receives every batch (fan-out)
+ *  - JSONL sinks write one self-describing event per line
+ *  - Events carry an explicit `type`; payloads carry `schema_version`
+ *  - `schema_version` only changes on breaking changes (2 tagged events with
+ *    `type`, 3 made `user_id` and `lab_id` nullable); new event types and
+ *    new optional fields keep it, so consumers skip what they do not know
+ *  - New destinations (e.g. a message broker) implement `TerminalEventSink`
+ *
+ * @packageDocumentation
+ */
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use futures::future::{join_all, BoxFuture};
+use serde::Serialize;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::services::terminal_event_outbox::{TerminalEventDropReason, TerminalEventOutbox};
+
+/// Version of the batch and event payload published to every sink.
+pub const TERMINAL_EVENT_SCHEMA_VERSION: u32 = 3;
+
+const DEFAULT_SINKS: &str = "http";
+const DEFAULT_JSONL_PATH: &str = "terminal-events.jsonl";
+
+#[derive(Clone, Debug, Serialize)]
+pub struct TerminalEvent {
+    pub event_id: Uuid,
+    pub occurred_at: DateTime<Utc>,
+    #[serde(flatten)]
+    pub kind: TerminalEventKind,
+}
+
+/// Event payloads, tagged with an explicit `type` field.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TerminalEventKind {
+    Command {
+        command_redacted: String,
+        exit_status: Option<i32>,
+        duration_ms: Option<u64>,
+        /// Redacted output excerpt, only under the `commands-with-output` policy.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        output_excerpt: Option<String>,
+    },
+    TerminalOpened,
+    TerminalClosed {
+        duration_ms: u64,
+        bytes_in: u64,
+        bytes_out: u64,
+    },
+    Resize {
+        cols: u16,
+        rows: u16,
+    },
+    IdleStarted {
+        idle_after_secs: u64,
+    },
+    IdleEnded {
+        idle_secs: u64,
+    },
+    PasteDetected {
+        bytes: u64,
+        lines: u64,
+    },
+    DisconnectReason {
+        reason: String,
+    },
+    FlagRevealed {
+        step: u32,
+    },
+    /// A request proxied to a web lab runtime.
+    HttpRequest {
+        method: String,
+        path_redacted: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        query_redacted: Option<String>,
+        status: u16,
+        duration_ms: u64,
+        /// Redacted request body excerpt, only under the `commands-with-output` policy.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        body_excerpt: Option<String>,
+    },
+    /// First request of a session carrying a known attack payload category.
+    PayloadDetected {
+        category: String,
+        location: String,
+    },
+}
+
+impl TerminalEvent {
+    pub fn new(kind: TerminalEventKind) -> Self {
+        Self {
+            event_id: Uuid::new_v4(),
+            occurred_at: Utc::now(),
+            kind,
+        }
+    }
+}
+
+/// Events from one terminal session, with the identifiers they belong to.
+/// `user_id` and `lab_id` are `null` for anonymous or preview sessions.
+#[derive(Clone, Debug, Serialize)]
+pub struct TerminalEventBatch {
+    pub schema_version: u32,
+    pub session_id: Uuid,
+    pub runtime_id: Uuid,
+    pub user_id: Option<Uuid>,
+    pub lab_id: Option<Uuid>,
+    pub events: Vec<TerminalEvent>,
+}
+
+#[derive(Serialize)]
+struct TerminalEventLine<'a> {
+    schema_version: u32,
+    session_id: Uuid,
+    runtime_id: Uuid,
+    user_id: Option<Uuid>,
+    lab_id: Option<Uuid>,
+    #[serde(flatten)]
+    event: &'a TerminalEvent,
+}
+
+impl TerminalEventBatch {
+    /// One JSON object per event, each carrying the batch identifiers.
+    fn to_jsonl(&self) -> Vec<u8> {
+        let mut output = Vec::new();
+        for event in &self.events {
+            let line = TerminalEventLine {
+                schema_version: self.schema_version,
+                session_id: self.session_id,
+                runtime_id: self.runtime_id,
+                user_id: self.user_id,
+                lab_id: self.lab_id,
+                event,
+            };
+            if serde_json::to_writer(&mut output, &line).is_ok() {
+                output.push(b'\n');
+            }
+        }
+        output
+    }
+}
+
+/// A destination for terminal event batches.
+pub trait TerminalEventSink: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn publish<'a>(&'a self, batch: &'a TerminalEventBatch) -> BoxFuture<'a, ()>;
+}
+
+/// Delivers batches to sessions-ms through the durable outbox.
+pub struct HttpOutboxSink {
+    outbox: Arc<TerminalEventOutbox>,
+}
+
+impl TerminalEventSink for HttpOutboxSink {
+    fn name(&self) -> &'static str {
+        "http"
+    }
+
+    fn publish<'a>(&'a self, batch: &'a TerminalEventBatch) -> BoxFuture<'a, ()> {
+        Box::pin(async move {
+            let event_count = batch.events.len();
+            match serde_json::to_vec(batch) {
+                Ok(body) => self.outbox.enqueue(body, event_count).await,
+                Err(error) => {
+                    warn!("Failed to serialize terminal events: {}", error);
+                    self.outbox
+                        .record_dropped(TerminalEventDropReason::SpoolError, event_count as u64);
+                }
+            }
+        })
+    }
+}
+
+/// Appends events as newline-delimited JSON to a local file.
+pub struct JsonlFileSink {
+    path: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl TerminalEventSink for JsonlFileSink {
+    fn name(&self) -> &'static str {
+        "file"
+    }
+
+    fn publish<'a>(&'a self, batch: &'a TerminalEventBatch) -> BoxFuture<'a, ()> {
+        Box::pin(async move {
+            let lines = batch.to_jsonl();
+            let _guard = self.lock.lock().await;
+            let written = async {
+                let mut file = tokio::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&self.path)
+                    .await?;
+                file.write_all(&lines).await?;
+                file.flush().await
+            }
+            .await;
+
+            if let Err(error) = written {
+                warn!(
+                    path = %self.path.display(),
+                    error = %error,
+                    "Failed to append terminal events to JSONL file"
+                );
+            }
+        })
+    }
+}
+
+/// Writes events as newline-delimited JSON to standard output.
+pub struct StdoutSink;
+
+impl TerminalEventSink for StdoutSink {
+    fn name(&self) -> &'static str {
+        "stdout"
+    }
+
+    fn publish<'a>(&'a self, batch: &'a TerminalEventBatch) -> BoxFuture<'a, ()> {
+        Box::pin(async move {
+            let lines = batch.to_jsonl();
+            let mut stdout = tokio::io::stdout();
+            if stdout.write_all(&lines).await.is_err() || stdout.flush().await.is_err() {
+                warn!("Failed to write terminal events to stdout");
+            }
+        })
+    }
+}
+
+/// Fans every batch out to all configured sinks.
+pub struct TerminalEventSinks {
+    sinks: Vec<Arc<dyn TerminalEventSink>>,
+}
+
+impl TerminalEventSinks {
+    pub fn new(sinks: Vec<Arc<dyn TerminalEventSink>>) -> Self {
+        Self { sinks }
+    }
+
+    pub fn from_env(outbox: Arc<TerminalEventOutbox>) -> Self {
+        let configured =
+            std::env::var("TERMINAL_EVENT_SINKS").unwrap_or_else(|_| DEFAULT_SINKS.to_string());
+        let jsonl_path = std::env::var("TERMINAL_EVENTS_JSONL_PATH")
+            .unwrap_or_else(|_| DEFAULT_JSONL_PATH.to_string());
+
+        let mut sinks: Vec<Arc<dyn TerminalEventSink>> = Vec::new();
+        for name in configured
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+        {
+            if sinks.iter().any(|sink| sink.name() == name) {
+                continue;
+            }
+            match name {
+                "http" => sinks.push(Arc::new(HttpOutboxSink {
+                    outbox: outbox.clone(),
+                })),
+                "file" => sinks.push(Arc::new(JsonlFileSink {
+                    path: PathBuf::from(&jsonl_path),
+                    lock: Mutex::new(()),
+                })),
+                "stdout" => sinks.push(Arc::new(StdoutSink)),
+                other => warn!(sink = %other, "Ignoring unknown terminal event sink"),
+            }
+        }
+
+        Self::new(sinks)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sinks.is_empty()
+    }
+
+    pub async fn publish(&self, batch: &TerminalEventBatch) {
+        join_all(self.sinks.iter().map(|sink| sink.publish(batch))).await;
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use futures::future::BoxFuture;
+    use uuid::Uuid;
+
+    use super::{
+        JsonlFileSink, TerminalEvent, TerminalEventBatch, TerminalEventKind, TerminalEventSink,
+        TerminalEventSinks, TERMINAL_EVENT_SCHEMA_VERSION,
+    };
+
+    /// Collects published batches in memory.
+    #[derive(Default)]
+    pub(crate) struct InMemorySink {
+        pub(crate) batches: Mutex<Vec<TerminalEventBatch>>,
+    }
+
+    impl TerminalEventSink for InMemorySink {
+        fn name(&self) -> &'static str {
+            "memory"
+        }
+
+        fn publish<'a>(&'a self, batch: &'a TerminalEventBatch) -> BoxFuture<'a, ()> {
+            self.batches.lock().unwrap().push(batch.clone());
+            Box::pin(async {})
+        }
+    }
+
+    fn batch(commands: &[&str]) -> TerminalEventBatch {
+        TerminalEventBatch {
+            schema_version: TERMINAL_EVENT_SCHEMA_VERSION,
+            session_id: Uuid::new_v4(),
+            runtime_id: Uuid::new_v4(),
+            user_id: Some(Uuid::new_v4()),
+            lab_id: None,
+            events: commands
+                .iter()
+                .map(|command| {
+                    TerminalEvent::new(TerminalEventKind::Command {
+                        command_redacted: command.to_string(),
+                        exit_status: Some(0),
+                        duration_ms: None,
+                        output_excerpt: None,
+                    })
+                })
+                .collect(),
+        }
+    }
+
+    #[tokio::test]
+    async fn batches_fan_out_to_every_sink() {
+        let first = Arc::new(InMemorySink::default());
+        let second = Arc::new(InMemorySink::default());
+        let sinks = TerminalEventSinks::new(vec![first.clone(), second.clone()]);
+
+        sinks.publish(&batch(&["ls"])).await;
+
+        assert_eq!(first.batches.lock().unwrap().len(), 1);
+        assert_eq!(
+            second.batches.lock().unwrap()[0].events[0].kind,
+            TerminalEventKind::Command {
+                command_redacted: "ls".to_string(),
+                exit_status: Some(0),
+                duration_ms: None,
+                output_excerpt: None,
+            }
+        );
+    }
+
+    #[test]
+    fn jsonl_lines_carry_batch_identifiers() {
+        let batch = batch(&["id", "whoami"]);
+        let output = String::from_utf8(batch.to_jsonl()).unwrap();
+        let lines: Vec<serde_json::Value> = output
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1]["type"], "command");
+        assert_eq!(lines[1]["command_redacted"], "whoami");
+        assert_eq!(lines[1]["schema_version"], TERMINAL_EVENT_SCHEMA_VERSION);
+        assert_eq!(lines[1]["session_id"], batch.session_id.to_string());
+        assert!(lines[1]["lab_id"].is_null());
+    }
+
+    #[test]
+    fn event_types_serialize_with_a_type_tag() {
+        let event = TerminalEvent::new(TerminalEventKind::TerminalClosed {
+            duration_ms: 1500,
+            bytes_in: 10,
+            bytes_out: 2048,
+        });
+        let value = serde_json::to_value(&event).unwrap();
+
+        assert_eq!(value["type"], "terminal_closed");
+        assert_eq!(value["bytes_out"], 2048);
+        assert!(value["event_id"].is_string());
+
+        let opened = serde_json::to_value(TerminalEvent::new(TerminalEventKind::TerminalOpened));
+        assert_eq!(opened.unwrap()["type"], "terminal_opened");
+    }
+
+    #[tokio::test]
+    async fn file_sink_appends_jsonl() {
+        let path = std::env::temp_dir().join(format!("altair-events-{}.jsonl", Uuid::new_v4()));
+        let sink = JsonlFileSink {
+            path: path.clone(),
+            lock: tokio::sync::Mutex::new(()),
+        };
+
+        sink.publish(&batch(&["pwd"])).await;
+        sink.publish(&batch(&["ls", "id"])).await;
+
+        let content = tokio::fs::read_to_string(&path).await.unwrap();
+        assert_eq!(content.lines().count(), 3);
+        let _ = tokio::fs::remove_file(&path).await;
+    }
+}
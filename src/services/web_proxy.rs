@@ -0,0 +1,515 @@
+/**
+ * @file web_proxy — HTTP forwarding to web lab runtimes.
+ *
+ * @remarks
+ * Forwards learner requests under `/web/{container_id}/` to the runtime's
+ * in-cluster Service (`{container_id}-web`) once the route has verified the
+ * lab web session cookie. WebSocket upgrades are bridged to a WebSocket on
+ * the same Service.
+ *
+ * Responsibilities:
+ *
+ *  - Resolve the upstream Service URL for a runtime
+ *  - Forward method, path, query, headers and body
+ *  - Drop hop-by-hop headers and the lab web session cookie
+ *  - Apply the lab's prefix rewriting to headers and HTML/CSS bodies
+ *  - Record each forwarded request for web traffic capture
+ *  - Sign requests in to browser IDE runtimes and read commands typed in
+ *    IDE terminals whose protocol allows it
+ *  - Stream upstream responses back without buffering them
+ *
+ * Key characteristics:
+ *
+ *  - Upstream URLs come from `LAB_WEB_UPSTREAM_URL_TEMPLATE`
+ *    (default `http://{service}.{namespace}.svc.cluster.local`)
+ *  - Request bodies are capped by `LAB_WEB_PROXY_MAX_BODY_BYTES` (10 MiB)
+ *  - Upstream redirects are returned to the browser, never followed
+ *  - The upstream WebSocket is opened before the upgrade, so an unreachable
+ *    runtime fails the handshake with `502`
+ *
+ * @packageDocumentation
+ */
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+
+use axum::{
+    body::{Body, Bytes},
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        FromRequestParts, Request,
+    },
+    http::{header, HeaderMap, HeaderName, HeaderValue, Response, StatusCode},
+};
+use chrono::Utc;
+use futures::{stream, SinkExt, StreamExt};
+use reqwest::Url;
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+use tokio_tungstenite::{
+    tungstenite::{self, client::IntoClientRequest},
+    MaybeTlsStream, WebSocketStream,
+};
+use tracing::{info, warn};
+
+use crate::services::{
+    lab_ide::{terminal_stdin, IdeAccess},
+    spawn::{build_web_service_name, namespace_for_delivery},
+    web_response_rewriting::PrefixRewrite,
+    web_shell::TerminalCommandInputCapture,
+    web_traffic_capture::{WebExchange, WebTrafficCapture},
+};
+
+type UpstreamSocket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+const DEFAULT_UPSTREAM_URL_TEMPLATE: &str = "http://{service}.{namespace}.svc.cluster.local";
+const DEFAULT_MAX_BODY_BYTES: usize = 10 * 1024 * 1024;
+const CONNECT_TIMEOUT_SECS: u64 = 5;
+const MAX_RUNTIME_NAME_CHARS: usize = 59;
+
+const HOP_BY_HOP_HEADERS: [HeaderName; 8] = [
+    header::CONNECTION,
+    header::HOST,
+    header::PROXY_AUTHORIZATION,
+    header::TE,
+    header::TRAILER,
+    header::TRANSFER_ENCODING,
+    header::UPGRADE,
+    header::CONTENT_LENGTH,
+];
+
+fn upstream_client() -> &'static reqwest::Client {
+    static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+    CLIENT.get_or_init(|| {
+        reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .connect_timeout(Duration::from_secs(CONNECT_TIMEOUT_SECS))
+            .build()
+            .unwrap_or_default()
+    })
+}
+
+/// Forwards `request` to `path` on the runtime's web Service. `ide` signs
+/// the request in to the runtime's browser IDE.
+pub async fn forward_web_request(
+    container_id: &str,
+    path: &str,
+    session_cookie: &str,
+    rewrite: Option<&PrefixRewrite>,
+    ide: Option<&IdeAccess>,
+    capture: &WebTrafficCapture,
+    request: Request,
+) -> Result<Response<Body>, StatusCode> {
+    let url = build_upstream_url(container_id, path, request.uri().query())?;
+    let occurred_at = Utc::now();
+    let (parts, body) = request.into_parts();
+    let mut headers = forwarded_headers(&parts.headers, session_cookie);
+    if let Some(ide) = ide {
+        ide.authorize(&mut headers);
+    }
+    // Compressed bodies cannot be rewritten, so ask the lab app for plain ones.
+    if rewrite.is_some_and(PrefixRewrite::rewrites_content) {
+        headers.remove(header::ACCEPT_ENCODING);
+    }
+    let max_body_bytes = std::env::var("LAB_WEB_PROXY_MAX_BODY_BYTES")
+        .ok()
+        .and_then(|value| value.trim().parse().ok())
+        .unwrap_or(DEFAULT_MAX_BODY_BYTES);
+    let body = axum::body::to_bytes(body, max_body_bytes)
+        .await
+        .map_err(|_| StatusCode::PAYLOAD_TOO_LARGE)?;
+    let body_prefix = capture.body_prefix(&body);
+    let content_type = parts
+        .headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+
+    let started = Instant::now();
+    let upstream = upstream_client()
+        .request(parts.method.clone(), url.clone())
+        .headers(headers)
+        .body(body)
+        .send()
+        .await
+        .map_err(|error| {
+            warn!(
+                container_id = %container_id,
+                error = %error,
+                action = "web_proxy",
+                "web runtime did not answer"
+            );
+            StatusCode::BAD_GATEWAY
+        })?;
+    capture.record(WebExchange {
+        container_id: container_id.to_string(),
+        occurred_at,
+        method: parts.method.to_string(),
+        path: url.path().to_string(),
+        query: url.query().map(str::to_string),
+        content_type,
+        body: body_prefix,
+        status: upstream.status().as_u16(),
+        duration_ms: started.elapsed().as_millis() as u64,
+    });
+
+    let mut response = Response::builder().status(upstream.status());
+    for (name, value) in upstream.headers() {
+        if HOP_BY_HOP_HEADERS.contains(name) {
+            continue;
+        }
+        let rewritten = rewrite
+            .zip(value.to_str().ok())
+            .and_then(|(rewrite, value)| {
+                if name == header::LOCATION {
+                    rewrite.location(value, &url)
+                } else if name == header::SET_COOKIE {
+                    Some(rewrite.set_cookie(value))
+                } else {
+                    None
+                }
+            });
+        response = match rewritten {
+            Some(rewritten) => response.header(name, rewritten),
+            None => response.header(name, value),
+        };
+    }
+
+    let rewriter = rewrite.and_then(|rewrite| rewrite.content_rewriter(upstream.headers()));
+    let body = stream::unfold(
+        (Some(upstream), rewriter),
+        |(upstream, mut rewriter)| async move {
+            let mut upstream = upstream?;
+            match upstream.chunk().await {
+                Ok(Some(chunk)) => {
+                    let chunk = match rewriter.as_mut() {
+                        Some(rewriter) => rewriter.push(&chunk),
+                        None => chunk,
+                    };
+                    Some((Ok::<_, reqwest::Error>(chunk), (Some(upstream), rewriter)))
+                }
+                // Whatever the rewriter still holds back goes out last.
+                Ok(None) => Some((Ok(rewriter?.finish()), (None, None))),
+                Err(error) => Some((Err(error), (Some(upstream), rewriter))),
+            }
+        },
+    );
+
+    response
+        .body(Body::from_stream(body))
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+pub fn is_websocket_upgrade(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::UPGRADE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.eq_ignore_ascii_case("websocket"))
+}
+
+/// Bridges a WebSocket `request` to `path` on the runtime's web Service.
+/// `ide` signs the handshake in to the runtime's browser IDE; commands typed
+/// in its terminal are recorded when the IDE's protocol can be read.
+pub async fn forward_web_socket(
+    container_id: &str,
+    path: &str,
+    session_cookie: &str,
+    ide: Option<&IdeAccess>,
+    capture: Arc<WebTrafficCapture>,
+    request: Request,
+) -> Result<Response<Body>, StatusCode> {
+    let url = build_upstream_url(container_id, path, request.uri().query())?;
+    let occurred_at = Utc::now();
+    let (mut parts, _) = request.into_parts();
+    let ws = WebSocketUpgrade::from_request_parts(&mut parts, &())
+        .await
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    let mut upstream_request = build_upstream_socket_url(&url)
+        .as_str()
+        .into_client_request()
+        .map_err(|_| StatusCode::BAD_GATEWAY)?;
+    let headers = upstream_request.headers_mut();
+    for (name, value) in upstream_socket_headers(&parts.headers, session_cookie, &url).iter() {
+        headers.append(name, value.clone());
+    }
+    if let Some(ide) = ide {
+        ide.authorize(headers);
+    }
+
+    let started = Instant::now();
+    let (upstream, handshake) = match timeout(
+        Duration::from_secs(CONNECT_TIMEOUT_SECS),
+        tokio_tungstenite::connect_async(upstream_request),
+    )
+    .await
+    {
+        Ok(Ok(connected)) => connected,
+        Ok(Err(error)) => {
+            warn!(
+                container_id = %container_id,
+                error = %error,
+                action = "web_proxy",
+                "web runtime refused the WebSocket"
+            );
+            return Err(StatusCode::BAD_GATEWAY);
+        }
+        Err(_) => {
+            warn!(
+                container_id = %container_id,
+                action = "web_proxy",
+                "timed out opening WebSocket to web runtime"
+            );
+            return Err(StatusCode::GATEWAY_TIMEOUT);
+        }
+    };
+    capture.record(WebExchange {
+        container_id: container_id.to_string(),
+        occurred_at,
+        method: parts.method.to_string(),
+        path: url.path().to_string(),
+        query: url.query().map(str::to_string),
+        content_type: None,
+        body: Bytes::new(),
+        status: StatusCode::SWITCHING_PROTOCOLS.as_u16(),
+        duration_ms: started.elapsed().as_millis() as u64,
+    });
+
+    // The browser must agree on whatever subprotocol the runtime picked.
+    let protocol = handshake
+        .headers()
+        .get(header::SEC_WEBSOCKET_PROTOCOL)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    let ws = match protocol {
+        Some(protocol) => ws.protocols([protocol]),
+        None => ws,
+    };
+    let terminal_capture = ide
+        .is_some_and(|ide| ide.is_terminal_socket(path))
+        .then_some(capture);
+    let container_id = container_id.to_string();
+
+    Ok(ws.on_upgrade(move |socket| {
+        bridge_web_socket(socket, upstream, container_id, terminal_capture)
+    }))
+}
+
+/// Copies frames between the browser and the runtime until either side closes.
+async fn bridge_web_socket(
+    socket: WebSocket,
+    upstream: UpstreamSocket,
+    container_id: String,
+    terminal_capture: Option<Arc<WebTrafficCapture>>,
+) {
+    let (mut browser_tx, mut browser_rx) = socket.split();
+    let (mut upstream_tx, mut upstream_rx) = upstream.split();
+
+    let to_runtime = async {
+        let mut input = TerminalCommandInputCapture::default();
+        while let Some(Ok(message)) = browser_rx.next().await {
+            let message = match message {
+                Message::Text(text) => {
+                    if let Some(capture) = &terminal_capture {
+                        for command in terminal_stdin(&text)
+                            .map(|stdin| input.extract_commands(stdin.as_bytes()))
+                            .unwrap_or_default()
+                        {
+                            capture.record_terminal_command(&container_id, command);
+                        }
+                    }
+                    tungstenite::Message::text(text.as_str())
+                }
+                Message::Binary(data) => tungstenite::Message::Binary(data),
+                Message::Close(_) => break,
+                // Each side answers its own pings.
+                Message::Ping(_) | Message::Pong(_) => continue,
+            };
+            if upstream_tx.send(message).await.is_err() {
+                break;
+            }
+        }
+        let _ = upstream_tx.close().await;
+    };
+
+    let to_browser = async {
+        while let Some(Ok(message)) = upstream_rx.next().await {
+            let message = match message {
+                tungstenite::Message::Text(text) => Message::Text(text.as_str().into()),
+                tungstenite::Message::Binary(data) => Message::Binary(data),
+                tungstenite::Message::Close(_) => break,
+                _ => continue,
+            };
+            if browser_tx.send(message).await.is_err() {
+                return;
+            }
+        }
+        let _ = browser_tx.send(Message::Close(None)).await;
+    };
+
+    tokio::select! {
+        _ = to_runtime => {}
+        _ = to_browser => {}
+    }
+
+    info!(
+        container_id = %container_id,
+        action = "web_proxy",
+        "web socket closed"
+    );
+}
+
+fn build_upstream_socket_url(url: &Url) -> Url {
+    let mut socket_url = url.clone();
+    let scheme = if url.scheme() == "https" { "wss" } else { "ws" };
+    let _ = socket_url.set_scheme(scheme);
+
+    socket_url
+}
+
+/// Forwarded headers for the upstream handshake. The client library writes
+/// its own handshake headers, and `Origin` names the runtime so IDEs that
+/// check it accept the proxied socket.
+fn upstream_socket_headers(headers: &HeaderMap, session_cookie: &str, url: &Url) -> HeaderMap {
+    let mut forwarded = forwarded_headers(headers, session_cookie);
+    for name in [
+        header::SEC_WEBSOCKET_KEY,
+        header::SEC_WEBSOCKET_VERSION,
+        header::SEC_WEBSOCKET_EXTENSIONS,
+        header::SEC_WEBSOCKET_ACCEPT,
+    ] {
+        forwarded.remove(name);
+    }
+    if let Ok(origin) = HeaderValue::from_str(&url.origin().ascii_serialization()) {
+        forwarded.insert(header::ORIGIN, origin);
+    }
+
+    forwarded
+}
+
+fn build_upstream_url(
+    container_id: &str,
+    path: &str,
+    query: Option<&str>,
+) -> Result<Url, StatusCode> {
+    if !is_runtime_name(container_id) {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let template = std::env::var("LAB_WEB_UPSTREAM_URL_TEMPLATE")
+        .unwrap_or_else(|_| DEFAULT_UPSTREAM_URL_TEMPLATE.to_string());
+    let base = template
+        .replace("{service}", &build_web_service_name(container_id))
+        .replace("{namespace}", &namespace_for_delivery("web"));
+    let mut url = Url::parse(&base).map_err(|_| StatusCode::BAD_GATEWAY)?;
+    url.set_path(&format!("/{}", path.trim_start_matches('/')));
+    url.set_query(query);
+
+    Ok(url)
+}
+
+/// Request headers minus hop-by-hop ones and the lab web session cookie,
+/// which is a credential for lab-api and must not reach the lab app.
+fn forwarded_headers(headers: &HeaderMap, session_cookie: &str) -> HeaderMap {
+    let mut forwarded = HeaderMap::new();
+
+    for (name, value) in headers {
+        if HOP_BY_HOP_HEADERS.contains(name) {
+            continue;
+        }
+        if name == header::COOKIE {
+            let Ok(cookies) = value.to_str() else {
+                continue;
+            };
+            let kept = cookies
+                .split(';')
+                .map(str::trim)
+                .filter(|cookie| {
+                    cookie.split_once('=').map(|(name, _)| name) != Some(session_cookie)
+                })
+                .collect::<Vec<_>>()
+                .join("; ");
+            if !kept.is_empty() {
+                if let Ok(value) = kept.parse() {
+                    forwarded.append(header::COOKIE, value);
+                }
+            }
+            continue;
+        }
+        forwarded.append(name, value.clone());
+    }
+
+    forwarded
+}
+
+pub(crate) fn is_runtime_name(value: &str) -> bool {
+    !value.is_empty()
+        && value.len() <= MAX_RUNTIME_NAME_CHARS
+        && value
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+        && !value.starts_with('-')
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{HeaderMap, StatusCode};
+
+    use super::{
+        build_upstream_socket_url, build_upstream_url, forwarded_headers, upstream_socket_headers,
+    };
+
+    #[test]
+    fn upstream_url_targets_the_runtime_web_service() {
+        let url = build_upstream_url("ctf-runtime-42", "/static/app.js", Some("v=1")).unwrap();
+
+        assert_eq!(
+            url.as_str(),
+            "http://ctf-runtime-42-web.labs-web.svc.cluster.local/static/app.js?v=1"
+        );
+        assert_eq!(
+            build_upstream_url("../kube-system", "/", None).unwrap_err(),
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    #[test]
+    fn session_cookie_and_hop_by_hop_headers_are_not_forwarded() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "cookie",
+            "altair_web_session=jwt; PHPSESSID=abc".parse().unwrap(),
+        );
+        headers.insert("connection", "keep-alive".parse().unwrap());
+        headers.insert("accept", "text/html".parse().unwrap());
+
+        let forwarded = forwarded_headers(&headers, "altair_web_session");
+
+        assert_eq!(forwarded.get("cookie").unwrap(), "PHPSESSID=abc");
+        assert!(forwarded.get("connection").is_none());
+        assert_eq!(forwarded.get("accept").unwrap(), "text/html");
+    }
+
+    #[test]
+    fn websocket_handshake_targets_the_runtime_origin() {
+        let url = build_upstream_url("ctf-runtime-42", "/terminals/websocket/1", None).unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert("origin", "https://labs.example".parse().unwrap());
+        headers.insert("sec-websocket-key", "abc==".parse().unwrap());
+        headers.insert("sec-websocket-protocol", "binary".parse().unwrap());
+        headers.insert("upgrade", "websocket".parse().unwrap());
+
+        let forwarded = upstream_socket_headers(&headers, "altair_web_session", &url);
+
+        assert_eq!(
+            build_upstream_socket_url(&url).as_str(),
+            "ws://ctf-runtime-42-web.labs-web.svc.cluster.local/terminals/websocket/1"
+        );
+        assert_eq!(
+            forwarded.get("origin").unwrap(),
+            "http://ctf-runtime-42-web.labs-web.svc.cluster.local"
+        );
+        assert_eq!(forwarded.get("sec-websocket-protocol").unwrap(), "binary");
+        assert!(forwarded.get("sec-websocket-key").is_none());
+        assert!(forwarded.get("upgrade").is_none());
+    }
+}
@@ -0,0 +1,336 @@
+/**
+ * @file web_response_rewriting — keep proxied web labs under their path prefix.
+ *
+ * @remarks
+ * Lab apps served under `/web/{container_id}/` usually assume they own the
+ * origin root. This module rewrites what they send back so the browser stays
+ * inside the runtime's prefix.
+ *
+ * Rewrites, depending on the lab's `WebRewritePolicy`:
+ *
+ *  - `Location` → root-relative targets and absolute URLs pointing at the
+ *    upstream Service get the prefix
+ *  - `Set-Cookie` → `Path` is moved under the prefix and `Domain` dropped
+ *  - HTML and CSS bodies → root-relative `href`, `src`, `action`,
+ *    `formaction`, `poster` and `url(...)` references get the prefix
+ *
+ * Key characteristics:
+ *
+ *  - The policy is read from the Pod's `web_rewrite` label at spawn time
+ *  - Bodies are rewritten while streaming, holding back only enough bytes to
+ *    match a reference split across chunks
+ *  - Compressed bodies are never rewritten; the proxy asks for identity
+ *    encoding when content rewriting is on
+ *  - URLs built by JavaScript at runtime are out of reach
+ *
+ * @packageDocumentation
+ */
+use std::sync::OnceLock;
+
+use axum::{
+    body::Bytes,
+    http::{header, HeaderMap},
+};
+use k8s_openapi::api::core::v1::Pod;
+use regex::bytes::Regex;
+use reqwest::Url;
+use tracing::warn;
+
+use crate::models::WebRewritePolicy;
+
+pub const WEB_REWRITE_LABEL: &str = "web_rewrite";
+
+// Longest reference the pattern can match, e.g. `formaction    =    "/`.
+const MAX_REFERENCE_BYTES: usize = 24;
+
+fn root_relative_reference() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| {
+        Regex::new(
+            r#"(?i)(?:\b(?:href|src|action|formaction|poster)\s{0,4}=\s{0,4}["']?|\burl\(\s{0,4}["']?)/"#,
+        )
+        .expect("static pattern")
+    })
+}
+
+/// Runtimes without the label keep the default; an unreadable value turns
+/// rewriting off rather than guessing what the lab expects.
+pub fn resolve_web_rewrite_policy(pod: &Pod) -> WebRewritePolicy {
+    let Some(raw) = pod
+        .metadata
+        .labels
+        .as_ref()
+        .and_then(|labels| labels.get(WEB_REWRITE_LABEL))
+    else {
+        return WebRewritePolicy::default();
+    };
+
+    serde_json::from_value(serde_json::Value::String(raw.clone())).unwrap_or_else(|_| {
+        warn!(
+            pod_name = ?pod.metadata.name,
+            value = %raw,
+            action = "web_proxy",
+            "unknown web rewrite policy; disabling rewriting"
+        );
+        WebRewritePolicy::Off
+    })
+}
+
+/// Rewrites upstream responses for one runtime prefix such as `/web/ctf-runtime-42`.
+pub struct PrefixRewrite {
+    prefix: String,
+    content: bool,
+}
+
+impl PrefixRewrite {
+    pub fn new(policy: WebRewritePolicy, prefix: &str) -> Option<Self> {
+        let prefix = prefix.trim_end_matches('/');
+        match policy {
+            WebRewritePolicy::Off => None,
+            _ if prefix.is_empty() => None,
+            WebRewritePolicy::Headers => Some(Self {
+                prefix: prefix.to_string(),
+                content: false,
+            }),
+            WebRewritePolicy::HeadersAndContent => Some(Self {
+                prefix: prefix.to_string(),
+                content: true,
+            }),
+        }
+    }
+
+    pub fn rewrites_content(&self) -> bool {
+        self.content
+    }
+
+    /// `None` when the redirect already leaves the runtime or needs no change.
+    pub fn location(&self, location: &str, upstream: &Url) -> Option<String> {
+        if location.starts_with('/') && !location.starts_with("//") {
+            return Some(format!("{}{location}", self.prefix));
+        }
+
+        let target = Url::parse(location).ok()?;
+        if target.origin() != upstream.origin() {
+            return None;
+        }
+        let mut rewritten = format!("{}{}", self.prefix, target.path());
+        if let Some(query) = target.query() {
+            rewritten.push('?');
+            rewritten.push_str(query);
+        }
+        if let Some(fragment) = target.fragment() {
+            rewritten.push('#');
+            rewritten.push_str(fragment);
+        }
+
+        Some(rewritten)
+    }
+
+    pub fn set_cookie(&self, cookie: &str) -> String {
+        let mut parts = cookie.split(';').map(str::trim);
+        let mut rewritten = vec![parts.next().unwrap_or_default().to_string()];
+        let mut has_path = false;
+
+        for attribute in parts {
+            let name = attribute
+                .split_once('=')
+                .map_or(attribute, |(name, _)| name)
+                .trim();
+            if name.eq_ignore_ascii_case("domain") {
+                continue;
+            }
+            if name.eq_ignore_ascii_case("path") {
+                has_path = true;
+                let path = attribute
+                    .split_once('=')
+                    .map_or("", |(_, path)| path.trim());
+                let path = if path.starts_with('/') { path } else { "/" };
+                rewritten.push(format!("Path={}{path}", self.prefix));
+                continue;
+            }
+            rewritten.push(attribute.to_string());
+        }
+        if !has_path {
+            rewritten.push(format!("Path={}/", self.prefix));
+        }
+
+        rewritten.join("; ")
+    }
+
+    /// Body rewriter for this response, if it is uncompressed HTML or CSS.
+    pub fn content_rewriter(&self, headers: &HeaderMap) -> Option<ContentRewriter> {
+        if !self.content {
+            return None;
+        }
+        let content_type = headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        let identity = headers
+            .get(header::CONTENT_ENCODING)
+            .and_then(|value| value.to_str().ok())
+            .is_none_or(|encoding| encoding.trim().eq_ignore_ascii_case("identity"));
+
+        (identity
+            && (content_type.starts_with("text/html") || content_type.starts_with("text/css")))
+        .then(|| ContentRewriter::new(&self.prefix))
+    }
+}
+
+/// Streaming rewriter for root-relative references in HTML and CSS.
+pub struct ContentRewriter {
+    prefix: Vec<u8>,
+    /// `web/ctf-runtime-42/`: a reference that already starts with it is left alone.
+    prefixed: Vec<u8>,
+    pending: Vec<u8>,
+    /// Bytes of `pending` before this offset were already emitted; they only
+    /// give the pattern's word boundary its left context.
+    emitted: usize,
+}
+
+impl ContentRewriter {
+    fn new(prefix: &str) -> Self {
+        Self {
+            prefix: prefix.as_bytes().to_vec(),
+            prefixed: format!("{}/", prefix.trim_start_matches('/')).into_bytes(),
+            pending: Vec::new(),
+            emitted: 0,
+        }
+    }
+
+    pub fn push(&mut self, chunk: &[u8]) -> Bytes {
+        self.pending.extend_from_slice(chunk);
+        self.rewrite(false)
+    }
+
+    pub fn finish(&mut self) -> Bytes {
+        self.rewrite(true)
+    }
+
+    fn rewrite(&mut self, last: bool) -> Bytes {
+        let lookahead = MAX_REFERENCE_BYTES + self.prefixed.len();
+        let cut = if last {
+            self.pending.len()
+        } else {
+            self.pending.len().saturating_sub(lookahead)
+        };
+
+        let mut output = Vec::with_capacity(self.pending.len());
+        let mut copied = self.emitted;
+        while let Some(found) = root_relative_reference().find_at(&self.pending, copied) {
+            if found.start() >= cut {
+                break;
+            }
+            let rest = &self.pending[found.end()..];
+            output.extend_from_slice(&self.pending[copied..found.end() - 1]);
+            if rest.first() != Some(&b'/') && !rest.starts_with(&self.prefixed) {
+                output.extend_from_slice(&self.prefix);
+            }
+            output.push(b'/');
+            copied = found.end();
+        }
+
+        let end = cut.max(copied);
+        output.extend_from_slice(&self.pending[copied..end]);
+        let keep_from = end.saturating_sub(1);
+        self.pending.drain(..keep_from);
+        self.emitted = end - keep_from;
+
+        Bytes::from(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderMap;
+    use reqwest::Url;
+
+    use super::PrefixRewrite;
+    use crate::models::WebRewritePolicy;
+
+    fn rewrite(policy: WebRewritePolicy) -> PrefixRewrite {
+        PrefixRewrite::new(policy, "/web/ctf-runtime-42/").unwrap()
+    }
+
+    #[test]
+    fn redirects_stay_under_the_runtime_prefix() {
+        let upstream = Url::parse("http://ctf-runtime-42-web.labs-web.svc.cluster.local/").unwrap();
+        let rewrite = rewrite(WebRewritePolicy::Headers);
+
+        assert_eq!(
+            rewrite.location("/login?next=%2F", &upstream).as_deref(),
+            Some("/web/ctf-runtime-42/login?next=%2F")
+        );
+        assert_eq!(
+            rewrite
+                .location(
+                    "http://ctf-runtime-42-web.labs-web.svc.cluster.local/admin#top",
+                    &upstream
+                )
+                .as_deref(),
+            Some("/web/ctf-runtime-42/admin#top")
+        );
+        assert_eq!(rewrite.location("https://example.com/", &upstream), None);
+        assert_eq!(rewrite.location("//cdn.example.com/x", &upstream), None);
+        assert!(PrefixRewrite::new(WebRewritePolicy::Off, "/web/ctf-runtime-42").is_none());
+    }
+
+    #[test]
+    fn cookies_are_scoped_to_the_runtime_prefix() {
+        let rewrite = rewrite(WebRewritePolicy::Headers);
+
+        assert_eq!(
+            rewrite.set_cookie("sid=abc; Path=/; Domain=internal; HttpOnly"),
+            "sid=abc; Path=/web/ctf-runtime-42/; HttpOnly"
+        );
+        assert_eq!(
+            rewrite.set_cookie("theme=dark; Max-Age=60"),
+            "theme=dark; Max-Age=60; Path=/web/ctf-runtime-42/"
+        );
+    }
+
+    #[test]
+    fn html_references_are_rewritten_across_chunk_boundaries() {
+        let mut headers = HeaderMap::new();
+        headers.insert("content-type", "text/html; charset=utf-8".parse().unwrap());
+        assert!(rewrite(WebRewritePolicy::Headers)
+            .content_rewriter(&headers)
+            .is_none());
+        let mut rewriter = rewrite(WebRewritePolicy::HeadersAndContent)
+            .content_rewriter(&headers)
+            .unwrap();
+
+        let html = concat!(
+            r#"<a href="/login">in</a><img SRC='/logo.png'><a href="//cdn.example/x">cdn</a>"#,
+            r#"<a href="https://example.com/">out</a><style>body{background:url(/bg.png)}</style>"#,
+            r#"<form action="/web/ctf-runtime-42/post"><a data-href="/x">"#
+        );
+        let mut output = Vec::new();
+        for chunk in html.as_bytes().chunks(7) {
+            output.extend_from_slice(&rewriter.push(chunk));
+        }
+        output.extend_from_slice(&rewriter.finish());
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            concat!(
+                r#"<a href="/web/ctf-runtime-42/login">in</a><img SRC='/web/ctf-runtime-42/logo.png'>"#,
+                r#"<a href="//cdn.example/x">cdn</a><a href="https://example.com/">out</a>"#,
+                r#"<style>body{background:url(/web/ctf-runtime-42/bg.png)}</style>"#,
+                r#"<form action="/web/ctf-runtime-42/post"><a data-href="/web/ctf-runtime-42/x">"#
+            )
+        );
+    }
+
+    #[test]
+    fn compressed_bodies_are_not_rewritten() {
+        let mut headers = HeaderMap::new();
+        headers.insert("content-type", "text/css".parse().unwrap());
+        headers.insert("content-encoding", "gzip".parse().unwrap());
+
+        assert!(rewrite(WebRewritePolicy::HeadersAndContent)
+            .content_rewriter(&headers)
+            .is_none());
+    }
+}
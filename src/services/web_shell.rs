@@ -16,9 +16,21 @@
  *
  *  - Uses a TTY-enabled exec session with the container's current user
  *  - Does not force `su - student` or any fixed user
+ *  - Honors per-runtime launch settings (container, argv, env, dir, user)
+ *  - Reaches terminal and opted-in web runtimes in their own namespaces
  *  - Sets a stable prompt displaying the real current user as user@altair:cwd
+ *  - Reports command exit status and duration through OSC 133 prompt hooks (bash,
+ *    and POSIX sh whose prompt expands `$(...)`, such as dash 0.5.11+ or busybox ash)
+ *  - Takes executed command lines from bash history; sh has no history hook, so its
+ *    lines still come from keystrokes and are paired with the status it reports
+ *  - Redacts secrets, including the runtime's own flag values, before forwarding commands
+ *  - Enforces the runtime's capture policy and announces it to the client on connect
+ *  - Attaches redacted output excerpts to commands when the policy includes output
  *  - Binary WebSocket messages for efficient terminal I/O
  *  - Non-blocking I/O with async streams
+ *  - Closes idle or over-long sessions after a warning notice
+ *  - Emits session, resize, idle, paste and disconnect events for analytics
+ *  - Reports when a step's flag value appears in terminal output, never the value itself
  *  - Graceful shutdown on connection close or errors
  *
  * This module enables interactive terminal access for lab sessions,
@@ -31,23 +43,60 @@ use axum::extract::ws::{Message, WebSocket};
 use futures::{SinkExt, StreamExt};
 use k8s_openapi::api::core::v1::Pod;
 use kube::{
-    api::{AttachParams, TerminalSize},
+    api::{AttachParams, AttachedProcess, TerminalSize},
     Api,
 };
 use serde::Deserialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::mpsc;
 use tracing::{error, info, warn};
 
-use crate::models::State;
+use crate::{
+    models::{State, TerminalCapturePolicy},
+    services::{spawn::find_runtime_pod, terminal_event_sinks::TerminalEventKind},
+};
 
+mod terminal_capture_privacy_policy;
 mod terminal_command_event_forwarding_to_sessions_ms;
 mod terminal_command_input_capture_and_redaction;
+mod terminal_command_output_excerpts;
+mod terminal_command_secret_redaction_rules;
+mod terminal_engagement_events;
+mod terminal_launch_settings;
+mod terminal_output_flag_reveal_detection;
+mod terminal_session_idle_and_duration_limits;
+pub(crate) mod terminal_shell_integration_exit_status_markers;
+
+pub(crate) use terminal_capture_privacy_policy::{
+    resolve_terminal_capture_policy, TERMINAL_CAPTURE_LABEL,
+};
+pub(crate) use terminal_command_event_forwarding_to_sessions_ms::{
+    load_terminal_event_context, publish_terminal_events, TerminalEventContext,
+};
+pub(crate) use terminal_command_input_capture_and_redaction::TerminalCommandInputCapture;
+pub(crate) use terminal_command_secret_redaction_rules::TerminalCommandRedactor;
+pub(crate) use terminal_launch_settings::{
+    validate_webshell_settings, TerminalLaunch, WEBSHELL_SETTINGS_ANNOTATION,
+};
+pub(crate) use terminal_session_idle_and_duration_limits::{
+    enforce_terminal_session_limits, TerminalActivity, TerminalLimitReason, TerminalSessionLimits,
+};
 
+use terminal_capture_privacy_policy::TerminalCapturePolicyMessage;
 use terminal_command_event_forwarding_to_sessions_ms::start_terminal_command_event_forwarder;
-use terminal_command_input_capture_and_redaction::TerminalCommandInputCapture;
+use terminal_engagement_events::{track_idle_periods, PasteDetector};
+use terminal_launch_settings::resolve_terminal_launch;
+use terminal_output_flag_reveal_detection::TerminalFlagRevealScanner;
+use terminal_session_idle_and_duration_limits::notify_runtime_idle;
+use terminal_shell_integration_exit_status_markers::{
+    CompletedTerminalCommand, ShellIntegrationMarkerParser, ShellIntegrationSegment,
+    TerminalCommandExitTracker,
+};
 
-const DEFAULT_NAMESPACE: &str = "default";
 const BUFFER_SIZE: usize = 4096;
+const NOTICE_QUEUE_SIZE: usize = 4;
 const WEBSHELL_COMMAND: &str = r##"
 USER_NAME="$(id -un 2>/dev/null || echo uid-$(id -u 2>/dev/null || echo unknown))"
 
@@ -61,13 +110,37 @@ export TERM="${TERM:-xterm-256color}"
 
 if command -v bash >/dev/null 2>&1; then
   export PS1="${USER_NAME}@altair:\w${PROMPT_CHAR} "
+  export HISTCONTROL=
+  export HISTIGNORE=
+  export PS0='\e]133;C\a'
+  export PROMPT_COMMAND='__altair_status=$?
+__altair_entry="$(HISTTIMEFORMAT= builtin history 1)"
+if [[ $__altair_entry =~ ^[[:space:]]*([0-9]+)[*]?[[:space:]]+(.*)$ ]]; then
+  if [ -n "${__altair_hist:-}" ] && [ "${BASH_REMATCH[1]}" != "$__altair_hist" ]; then
+    __altair_line="${BASH_REMATCH[2]//[[:cntrl:]]/ }"
+    printf "\033]633;E;%s\007" "${__altair_line:0:2048}"
+  fi
+  __altair_hist="${BASH_REMATCH[1]}"
+else
+  __altair_hist="${__altair_hist:-0}"
+fi
+printf "\033]133;D;%s\007\033]133;A\007" "$__altair_status"'
   exec bash --noprofile --norc -i
 fi
 
-export PS1="${USER_NAME}@altair:\${PWD}${PROMPT_CHAR} "
+# POSIX sh has no pre-exec hook or history, so only the exit status is
+# reported, from the prompt; shells that do not expand `$(...)` in PS1 show it as is.
+export PS1='$(printf "\033]133;D;%s\007\033]133;A\007" "$?")'"${USER_NAME}@altair:\${PWD}${PROMPT_CHAR} "
 exec sh -i
 "##;
 
+/// A runtime Pod located for terminal access, with its resolved launch settings.
+pub(crate) struct TerminalRuntime {
+    pub(crate) namespace: String,
+    pub(crate) pod: Pod,
+    pub(crate) launch: TerminalLaunch,
+}
+
 #[derive(Debug, Deserialize)]
 struct TerminalResizeMessage {
     #[serde(rename = "type")]
@@ -76,34 +149,84 @@ struct TerminalResizeMessage {
     rows: u16,
 }
 
-pub async fn handle_terminal(socket: WebSocket, pod_name: String, state: State) {
-    let namespace = terminal_namespace();
+/// Locates a runtime and resolves how its shell is launched, for any terminal entry point.
+pub(crate) async fn resolve_terminal_runtime(
+    state: &State,
+    pod_name: &str,
+) -> Result<TerminalRuntime, String> {
+    let Some((namespace, pod)) = find_runtime_pod(state, pod_name).await else {
+        warn!(
+            pod_name = %pod_name,
+            action = "webshell_exec",
+            "web shell runtime not found"
+        );
+        return Err("runtime not found".to_string());
+    };
+
+    match resolve_terminal_launch(&pod) {
+        Ok(launch) => Ok(TerminalRuntime {
+            namespace,
+            pod,
+            launch,
+        }),
+        Err(reason) => {
+            warn!(
+                namespace = %namespace,
+                pod_name = %pod_name,
+                reason = %reason,
+                action = "webshell_exec",
+                "rejecting invalid web shell settings"
+            );
+            Err(reason)
+        }
+    }
+}
+
+pub async fn handle_terminal(mut socket: WebSocket, pod_name: String, state: State) {
+    let TerminalRuntime {
+        namespace,
+        pod,
+        launch,
+    } = match resolve_terminal_runtime(&state, &pod_name).await {
+        Ok(runtime) => runtime,
+        Err(reason) => {
+            let notice = format!("\r\n[altair] Web shell unavailable: {reason}\r\n");
+            let _ = socket
+                .send(Message::Binary(notice.into_bytes().into()))
+                .await;
+            let _ = socket.send(Message::Close(None)).await;
+            return;
+        }
+    };
     let pods: Api<Pod> = Api::namespaced(state.kube_client.clone(), &namespace);
-    let event_forwarder = start_terminal_command_event_forwarder(&pods, &pod_name).await;
+
+    let capture = resolve_terminal_capture_policy(&pod);
+    let event_forwarder = start_terminal_command_event_forwarder(
+        pods.clone(),
+        &pod,
+        capture,
+        state.terminal_event_sinks.clone(),
+        state.terminal_outbox.clone(),
+    );
 
     let attach_params = AttachParams {
         stdin: true,
         stdout: true,
         stderr: false,
         tty: true,
+        container: launch.container.clone(),
         ..Default::default()
     };
 
     info!(
         namespace = %namespace,
         pod_name = %pod_name,
+        container = ?launch.container,
         action = "webshell_exec",
-        "opening web shell with current container user"
+        "opening web shell with runtime launch settings"
     );
 
-    let mut exec = match pods
-        .exec(
-            &pod_name,
-            vec!["/bin/sh", "-lc", WEBSHELL_COMMAND],
-            &attach_params,
-        )
-        .await
-    {
+    let mut exec = match pods.exec(&pod_name, launch.command, &attach_params).await {
         Ok(e) => e,
         Err(error) => {
             error!(
@@ -139,6 +262,10 @@ pub async fn handle_terminal(socket: WebSocket, pod_name: String, state: State)
 
     let mut terminal_size_tx = exec.terminal_size();
 
+    if let Some(forwarder) = &event_forwarder {
+        forwarder.send_event(TerminalEventKind::TerminalOpened);
+    }
+
     if let Some(mut stderr) = exec.stderr() {
         let stderr_namespace = namespace.clone();
         let stderr_pod_name = pod_name.clone();
@@ -173,17 +300,52 @@ pub async fn handle_terminal(socket: WebSocket, pod_name: String, state: State)
     }
 
     let (mut ws_tx, mut ws_rx) = socket.split();
+    if let Ok(message) = serde_json::to_string(&TerminalCapturePolicyMessage::new(capture)) {
+        let _ = ws_tx.send(Message::Text(message.into())).await;
+    }
+    let limits = TerminalSessionLimits::from_env();
+    let activity = TerminalActivity::new();
+    let (notice_tx, mut notice_rx) = mpsc::channel::<String>(NOTICE_QUEUE_SIZE);
+    let redactor = TerminalCommandRedactor::for_pod(&pod);
+    let bytes_in = AtomicU64::new(0);
+    let bytes_out = AtomicU64::new(0);
+    let mut exit_tracker = TerminalCommandExitTracker::new(redactor.clone());
+    if capture == TerminalCapturePolicy::CommandsWithOutput {
+        exit_tracker = exit_tracker.with_output_excerpts(launch.output_excerpt);
+    }
+    let exit_tracker = Mutex::new(exit_tracker);
+    let forward_commands = |commands: Vec<CompletedTerminalCommand>| {
+        if !capture.captures_commands() {
+            return;
+        }
+        if let Some(forwarder) = &event_forwarder {
+            for command in commands {
+                forwarder.send_command(command);
+            }
+        }
+    };
 
     let to_pod = async {
         let mut command_capture = TerminalCommandInputCapture::default();
+        let mut paste_detector = PasteDetector::from_env();
 
         while let Some(Ok(msg)) = ws_rx.next().await {
             match msg {
                 Message::Binary(data) => {
-                    let commands = command_capture.capture_redacted_commands(data.as_ref());
-                    if let Some(forwarder) = &event_forwarder {
-                        for command in commands {
-                            forwarder.send_redacted_command(command);
+                    activity.touch();
+                    bytes_in.fetch_add(data.len() as u64, Ordering::Relaxed);
+                    // Under `off` learner input is never inspected, not even for pastes.
+                    if capture.captures_commands() {
+                        if let Some(forwarder) = &event_forwarder {
+                            for event in paste_detector.observe(data.as_ref()) {
+                                forwarder.send_event(event);
+                            }
+                        }
+                        for command in
+                            command_capture.capture_redacted_commands(data.as_ref(), &redactor)
+                        {
+                            let released = exit_tracker.lock().unwrap().command_entered(command);
+                            forward_commands(released);
                         }
                     }
 
@@ -211,6 +373,11 @@ pub async fn handle_terminal(socket: WebSocket, pod_name: String, state: State)
                     };
                     if !sent {
                         terminal_size_tx = None;
+                    } else if let Some(forwarder) = &event_forwarder {
+                        forwarder.send_event(TerminalEventKind::Resize {
+                            cols: resize.cols,
+                            rows: resize.rows,
+                        });
                     }
                 }
                 Message::Close(_) => break,
@@ -222,30 +389,132 @@ pub async fn handle_terminal(socket: WebSocket, pod_name: String, state: State)
 
     let from_pod = async {
         let mut buf = [0u8; BUFFER_SIZE];
+        let mut marker_parser = ShellIntegrationMarkerParser::default();
+        let mut flag_scanner = TerminalFlagRevealScanner::for_pod(&pod);
 
-        while let Ok(n) = stdout.read(&mut buf).await {
-            if n == 0 {
-                break;
-            }
+        loop {
+            let frame = tokio::select! {
+                read = stdout.read(&mut buf) => match read {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => {
+                        activity.touch();
+                        bytes_out.fetch_add(n as u64, Ordering::Relaxed);
+                        let mut output = Vec::with_capacity(n);
+                        for segment in marker_parser.split_markers(&buf[..n]) {
+                            match segment {
+                                ShellIntegrationSegment::Output(bytes) => {
+                                    exit_tracker.lock().unwrap().output(&bytes);
+                                    output.extend_from_slice(&bytes);
+                                }
+                                ShellIntegrationSegment::Marker(marker) => {
+                                    let released = exit_tracker.lock().unwrap().marker(marker);
+                                    forward_commands(released);
+                                }
+                            }
+                        }
+                        let revealed = if capture.captures_commands() {
+                            flag_scanner.scan(&output)
+                        } else {
+                            Vec::new()
+                        };
+                        for step in revealed {
+                            info!(pod_name = %pod_name, step, "Runtime flag revealed in terminal output");
+                            if let Some(forwarder) = &event_forwarder {
+                                forwarder.send_event(TerminalEventKind::FlagRevealed { step });
+                            }
+                        }
+                        output
+                    }
+                },
+                Some(notice) = notice_rx.recv() => notice.into_bytes(),
+            };
 
-            if ws_tx
-                .send(Message::Binary(buf[..n].to_vec().into()))
-                .await
-                .is_err()
-            {
+            if frame.is_empty() {
+                continue;
+            }
+            if ws_tx.send(Message::Binary(frame.into())).await.is_err() {
                 break;
             }
         }
     };
 
-    tokio::select! {
-        _ = to_pod => {}
-        _ = from_pod => {}
+    let (limit_reached, disconnect_reason): (Option<TerminalLimitReason>, &str) = tokio::select! {
+        _ = to_pod => (None, "client_closed"),
+        _ = from_pod => (None, "runtime_exited"),
+        reason = enforce_terminal_session_limits(&limits, &activity, notice_tx) => {
+            (Some(reason), reason.as_str())
+        }
+        // Never completes; it only reports idle periods while the session runs.
+        _ = track_idle_periods(&activity, event_forwarder.as_ref()) => (None, "client_closed"),
+    };
+
+    forward_commands(exit_tracker.lock().unwrap().finish());
+    if let Some(forwarder) = &event_forwarder {
+        forwarder.send_event(TerminalEventKind::DisconnectReason {
+            reason: disconnect_reason.to_string(),
+        });
+        forwarder.send_event(TerminalEventKind::TerminalClosed {
+            duration_ms: activity.elapsed().as_millis() as u64,
+            bytes_in: bytes_in.load(Ordering::Relaxed),
+            bytes_out: bytes_out.load(Ordering::Relaxed),
+        });
+    }
+
+    let Some(reason) = limit_reached else {
+        return;
+    };
+
+    info!(
+        namespace = %namespace,
+        pod_name = %pod_name,
+        reason = reason.as_str(),
+        elapsed_secs = activity.elapsed().as_secs(),
+        action = "webshell_limit",
+        "closing web shell after session limit"
+    );
+
+    let _ = ws_tx
+        .send(Message::Binary(
+            reason.closing_notice().as_bytes().to_vec().into(),
+        ))
+        .await;
+    let _ = ws_tx.send(Message::Close(None)).await;
+
+    if reason == TerminalLimitReason::IdleTimeout && limits.notify_runtime_idle {
+        notify_runtime_idle(
+            &state.sessions_ms,
+            &pods,
+            &pod,
+            activity.idle_for().as_secs(),
+        )
+        .await;
     }
 }
 
-fn terminal_namespace() -> String {
-    std::env::var("LAB_TERMINAL_NAMESPACE").unwrap_or_else(|_| DEFAULT_NAMESPACE.to_string())
+/// Exit code reported by Kubernetes once an exec session ends (0 when unknown).
+pub(crate) async fn exec_exit_status(exec: &mut AttachedProcess) -> u32 {
+    let Some(status) = exec.take_status() else {
+        return 0;
+    };
+    let Some(status) = status.await else {
+        return 0;
+    };
+
+    if status.status.as_deref() == Some("Success") {
+        return 0;
+    }
+
+    status
+        .details
+        .and_then(|details| details.causes)
+        .and_then(|causes| {
+            causes
+                .into_iter()
+                .find(|cause| cause.reason.as_deref() == Some("ExitCode"))
+        })
+        .and_then(|cause| cause.message)
+        .and_then(|message| message.parse().ok())
+        .unwrap_or(1)
 }
 
 #[cfg(test)]
@@ -266,6 +535,20 @@ mod tests {
         assert!(!WEBSHELL_COMMAND.contains("USER_NAME=\"student\""));
     }
 
+    #[test]
+    fn webshell_command_emits_exit_status_markers_for_bash() {
+        assert!(WEBSHELL_COMMAND.contains("PS0='\\e]133;C\\a'"));
+        assert!(WEBSHELL_COMMAND.contains("133;D;%s"));
+        assert!(WEBSHELL_COMMAND.contains("__altair_status=$?"));
+    }
+
+    #[test]
+    fn webshell_command_reports_command_lines_from_history() {
+        assert!(WEBSHELL_COMMAND.contains("builtin history 1"));
+        assert!(WEBSHELL_COMMAND.contains("633;E;%s"));
+        assert!(WEBSHELL_COMMAND.contains("export HISTCONTROL="));
+    }
+
     #[test]
     fn webshell_command_falls_back_to_sh() {
         assert!(WEBSHELL_COMMAND.contains("command -v bash"));
@@ -0,0 +1,102 @@
+//! Resolve the per-runtime terminal capture policy stored on the Pod at spawn time.
+
+use k8s_openapi::api::core::v1::Pod;
+use serde::Serialize;
+use tracing::warn;
+
+use crate::models::TerminalCapturePolicy;
+
+pub(crate) const TERMINAL_CAPTURE_LABEL: &str = "terminal_capture";
+
+/// Sent to the client as a text frame when the session opens, so the UI can
+/// tell the learner what is being captured.
+#[derive(Serialize)]
+pub(super) struct TerminalCapturePolicyMessage {
+    #[serde(rename = "type")]
+    message_type: &'static str,
+    policy: TerminalCapturePolicy,
+}
+
+impl TerminalCapturePolicyMessage {
+    pub(super) fn new(policy: TerminalCapturePolicy) -> Self {
+        Self {
+            message_type: "capture_policy",
+            policy,
+        }
+    }
+}
+
+/// Runtimes without the label keep the default; an unreadable value turns
+/// capture off rather than guessing what the lab allowed.
+pub(crate) fn resolve_terminal_capture_policy(pod: &Pod) -> TerminalCapturePolicy {
+    let Some(raw) = pod
+        .metadata
+        .labels
+        .as_ref()
+        .and_then(|labels| labels.get(TERMINAL_CAPTURE_LABEL))
+    else {
+        return TerminalCapturePolicy::default();
+    };
+
+    serde_json::from_value(serde_json::Value::String(raw.clone())).unwrap_or_else(|_| {
+        warn!(
+            pod_name = ?pod.metadata.name,
+            value = %raw,
+            action = "webshell_capture_policy",
+            "unknown terminal capture policy; disabling capture"
+        );
+        TerminalCapturePolicy::Off
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use k8s_openapi::api::core::v1::Pod;
+    use kube::api::ObjectMeta;
+
+    use super::{
+        resolve_terminal_capture_policy, TerminalCapturePolicyMessage, TERMINAL_CAPTURE_LABEL,
+    };
+    use crate::models::TerminalCapturePolicy;
+
+    fn pod_with_policy(value: &str) -> Pod {
+        Pod {
+            metadata: ObjectMeta {
+                labels: Some(BTreeMap::from([(
+                    TERMINAL_CAPTURE_LABEL.to_string(),
+                    value.to_string(),
+                )])),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn capture_policy_is_read_from_the_pod_label() {
+        assert_eq!(
+            resolve_terminal_capture_policy(&Pod::default()),
+            TerminalCapturePolicy::CommandsOnly
+        );
+        assert_eq!(
+            resolve_terminal_capture_policy(&pod_with_policy("commands-with-output")),
+            TerminalCapturePolicy::CommandsWithOutput
+        );
+        assert_eq!(
+            resolve_terminal_capture_policy(&pod_with_policy("everything")),
+            TerminalCapturePolicy::Off
+        );
+    }
+
+    #[test]
+    fn capture_policy_message_names_the_policy() {
+        let message = TerminalCapturePolicyMessage::new(TerminalCapturePolicy::Off);
+
+        assert_eq!(
+            serde_json::to_string(&message).unwrap(),
+            r#"{"type":"capture_policy","policy":"off"}"#
+        );
+    }
+}
@@ -1,86 +1,126 @@
-//! Batch and forward captured terminal command events to sessions-ms.
+//! Batch captured terminal command events and publish them to the configured event sinks.
+
+use std::future::pending;
+use std::sync::Arc;
 
-use chrono::{DateTime, Utc};
 use k8s_openapi::api::core::v1::Pod;
 use kube::Api;
-use serde::Serialize;
 use tokio::sync::mpsc;
-use tokio::time::{interval, Duration};
-use tracing::warn;
+use tokio::time::{interval, interval_at, Duration, Instant, Interval, MissedTickBehavior};
+use tracing::{info, warn};
 use uuid::Uuid;
 
+use super::terminal_session_idle_and_duration_limits::duration_env;
+use super::terminal_shell_integration_exit_status_markers::CompletedTerminalCommand;
+use crate::models::TerminalCapturePolicy;
+use crate::services::{
+    terminal_event_outbox::{TerminalEventDropReason, TerminalEventOutbox},
+    terminal_event_sinks::{
+        TerminalEvent, TerminalEventBatch, TerminalEventKind, TerminalEventSinks,
+        TERMINAL_EVENT_SCHEMA_VERSION,
+    },
+};
+
 const EVENT_BATCH_SIZE: usize = 10;
 const EVENT_FLUSH_SECS: u64 = 2;
 const EVENT_QUEUE_SIZE: usize = 256;
+const DEFAULT_CONTEXT_REFRESH_SECS: u64 = 30;
 
 #[derive(Clone)]
 pub(super) struct TerminalCommandEventForwarder {
-    tx: mpsc::Sender<TerminalCommandEvent>,
+    tx: mpsc::Sender<TerminalEvent>,
+    outbox: Arc<TerminalEventOutbox>,
 }
 
-#[derive(Clone)]
-struct TerminalEventContext {
-    session_id: Uuid,
-    runtime_id: Uuid,
-    user_id: Uuid,
-    lab_id: Uuid,
+/// Identifiers taken from the runtime Pod labels. `user_id` and `lab_id` are
+/// optional in `SpawnRequest`, so anonymous and preview sessions leave them unset.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct TerminalEventContext {
+    pub(super) session_id: Uuid,
+    pub(super) runtime_id: Uuid,
+    user_id: Option<Uuid>,
+    lab_id: Option<Uuid>,
 }
 
-#[derive(Clone, Serialize)]
-struct TerminalCommandEvent {
-    event_id: Uuid,
-    occurred_at: DateTime<Utc>,
-    command_redacted: String,
-    exit_status: Option<i32>,
-}
+/// Starts batching events for `pod`, re-reading its labels from `pods` while the
+/// session runs so that ids assigned later (e.g. a warm-pool claim) are picked up.
+/// Runtimes whose capture policy is `off` get no forwarder, so nothing is
+/// published and their labels are never re-read.
+pub(super) fn start_terminal_command_event_forwarder(
+    pods: Api<Pod>,
+    pod: &Pod,
+    policy: TerminalCapturePolicy,
+    sinks: Arc<TerminalEventSinks>,
+    outbox: Arc<TerminalEventOutbox>,
+) -> Option<TerminalCommandEventForwarder> {
+    if policy == TerminalCapturePolicy::Off {
+        return None;
+    }
+    let context = load_terminal_event_context(pod)?;
+    let pod_name = pod.metadata.name.clone()?;
+    let (tx, rx) = mpsc::channel(EVENT_QUEUE_SIZE);
+    let refresh = duration_env(
+        "TERMINAL_EVENT_CONTEXT_REFRESH_SECS",
+        DEFAULT_CONTEXT_REFRESH_SECS,
+    )
+    .map(|every| ContextRefresh {
+        pods,
+        pod_name,
+        every,
+    });
+    tokio::spawn(forward_terminal_events(context, refresh, sinks, rx));
 
-#[derive(Serialize)]
-struct TerminalEventsPayload {
-    session_id: Uuid,
-    runtime_id: Uuid,
-    user_id: Uuid,
-    lab_id: Uuid,
-    events: Vec<TerminalCommandEvent>,
+    Some(TerminalCommandEventForwarder { tx, outbox })
 }
 
-pub(super) async fn start_terminal_command_event_forwarder(
-    pods: &Api<Pod>,
-    pod_name: &str,
-) -> Option<TerminalCommandEventForwarder> {
-    let context = load_terminal_event_context(pods, pod_name).await?;
-    let (tx, rx) = mpsc::channel(EVENT_QUEUE_SIZE);
-    tokio::spawn(forward_terminal_events(context, rx));
+/// Where and how often to re-read the runtime Pod labels.
+struct ContextRefresh {
+    pods: Api<Pod>,
+    pod_name: String,
+    every: Duration,
+}
 
-    Some(TerminalCommandEventForwarder { tx })
+impl ContextRefresh {
+    async fn load(&self) -> Option<TerminalEventContext> {
+        match self.pods.get_opt(&self.pod_name).await {
+            Ok(pod) => pod.as_ref().and_then(load_terminal_event_context),
+            Err(error) => {
+                warn!(
+                    pod_name = %self.pod_name,
+                    "Failed to refresh terminal event context: {}",
+                    error
+                );
+                None
+            }
+        }
+    }
 }
 
 impl TerminalCommandEventForwarder {
-    pub(super) fn send_redacted_command(&self, command_redacted: String) {
-        if command_redacted.is_empty() {
+    pub(super) fn send_command(&self, command: CompletedTerminalCommand) {
+        if command.command_redacted.is_empty() {
             return;
         }
 
-        if self
-            .tx
-            .try_send(TerminalCommandEvent {
-                event_id: Uuid::new_v4(),
-                occurred_at: Utc::now(),
-                command_redacted,
-                exit_status: None,
-            })
-            .is_err()
-        {
-            warn!("Dropped terminal command event because the analytics queue is full");
+        self.send_event(TerminalEventKind::Command {
+            command_redacted: command.command_redacted,
+            exit_status: command.exit_status,
+            duration_ms: command.duration_ms,
+            output_excerpt: command.output_excerpt,
+        });
+    }
+
+    pub(super) fn send_event(&self, kind: TerminalEventKind) {
+        if self.tx.try_send(TerminalEvent::new(kind)).is_err() {
+            warn!("Dropped terminal event because the analytics queue is full");
+            self.outbox
+                .record_dropped(TerminalEventDropReason::QueueFull, 1);
         }
     }
 }
 
-async fn load_terminal_event_context(
-    pods: &Api<Pod>,
-    pod_name: &str,
-) -> Option<TerminalEventContext> {
-    let pod = pods.get(pod_name).await.ok()?;
-    let labels = pod.metadata.labels?;
+pub(crate) fn load_terminal_event_context(pod: &Pod) -> Option<TerminalEventContext> {
+    let labels = pod.metadata.labels.as_ref()?;
 
     Some(TerminalEventContext {
         session_id: labels
@@ -89,19 +129,24 @@ async fn load_terminal_event_context(
         runtime_id: labels
             .get("runtime_id")
             .and_then(|v| Uuid::parse_str(v).ok())?,
-        user_id: labels
-            .get("user_id")
-            .and_then(|v| Uuid::parse_str(v).ok())?,
-        lab_id: labels.get("lab_id").and_then(|v| Uuid::parse_str(v).ok())?,
+        user_id: labels.get("user_id").and_then(|v| Uuid::parse_str(v).ok()),
+        lab_id: labels.get("lab_id").and_then(|v| Uuid::parse_str(v).ok()),
     })
 }
 
 async fn forward_terminal_events(
-    context: TerminalEventContext,
-    mut rx: mpsc::Receiver<TerminalCommandEvent>,
+    mut context: TerminalEventContext,
+    refresh: Option<ContextRefresh>,
+    sinks: Arc<TerminalEventSinks>,
+    mut rx: mpsc::Receiver<TerminalEvent>,
 ) {
     let mut events = Vec::new();
     let mut ticker = interval(Duration::from_secs(EVENT_FLUSH_SECS));
+    let mut refresh_ticker = refresh.as_ref().map(|refresh| {
+        let mut ticker = interval_at(Instant::now() + refresh.every, refresh.every);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        ticker
+    });
 
     loop {
         tokio::select! {
@@ -110,7 +155,7 @@ async fn forward_terminal_events(
                     Some(event) => {
                         events.push(event);
                         if events.len() >= EVENT_BATCH_SIZE {
-                            flush_terminal_events(&context, &mut events).await;
+                            publish_terminal_events(&context, &sinks, &mut events).await;
                         }
                     }
                     None => break,
@@ -118,51 +163,231 @@ async fn forward_terminal_events(
             }
             _ = ticker.tick() => {
                 if !events.is_empty() {
-                    flush_terminal_events(&context, &mut events).await;
+                    publish_terminal_events(&context, &sinks, &mut events).await;
+                }
+            }
+            _ = tick_refresh(refresh_ticker.as_mut()) => {
+                if let Some(refresh) = &refresh {
+                    if let Some(updated) = refresh.load().await {
+                        update_terminal_event_context(&mut context, updated, &sinks, &mut events).await;
+                    }
                 }
             }
         }
     }
 
     if !events.is_empty() {
-        flush_terminal_events(&context, &mut events).await;
+        publish_terminal_events(&context, &sinks, &mut events).await;
     }
 }
 
-async fn flush_terminal_events(
-    context: &TerminalEventContext,
-    events: &mut Vec<TerminalCommandEvent>,
+async fn tick_refresh(ticker: Option<&mut Interval>) {
+    match ticker {
+        Some(ticker) => {
+            ticker.tick().await;
+        }
+        None => pending().await,
+    }
+}
+
+/// Switches to `updated` when the labels changed. Events queued so far are
+/// flushed first so they keep the identifiers they were recorded under.
+async fn update_terminal_event_context(
+    context: &mut TerminalEventContext,
+    updated: TerminalEventContext,
+    sinks: &TerminalEventSinks,
+    events: &mut Vec<TerminalEvent>,
 ) {
-    let batch = std::mem::take(events);
-    let sessions_url =
-        std::env::var("SESSIONS_MS_URL").unwrap_or_else(|_| "http://localhost:3003".to_string());
-    let url = format!(
-        "{}/internal/terminal-events",
-        sessions_url.trim_end_matches('/')
+    if *context == updated {
+        return;
+    }
+
+    if !events.is_empty() {
+        publish_terminal_events(context, sinks, events).await;
+    }
+    info!(
+        session_id = %updated.session_id,
+        runtime_id = %updated.runtime_id,
+        user_id = ?updated.user_id,
+        lab_id = ?updated.lab_id,
+        "Terminal event context changed"
     );
+    *context = updated;
+}
 
-    let payload = TerminalEventsPayload {
+pub(crate) async fn publish_terminal_events(
+    context: &TerminalEventContext,
+    sinks: &TerminalEventSinks,
+    events: &mut Vec<TerminalEvent>,
+) {
+    if sinks.is_empty() {
+        events.clear();
+        return;
+    }
+
+    let batch = TerminalEventBatch {
+        schema_version: TERMINAL_EVENT_SCHEMA_VERSION,
         session_id: context.session_id,
         runtime_id: context.runtime_id,
         user_id: context.user_id,
         lab_id: context.lab_id,
-        events: batch,
+        events: std::mem::take(events),
     };
 
-    match reqwest::Client::new().post(&url).json(&payload).send().await {
-        Ok(response) if response.status().is_success() => {}
-        Ok(response) => {
-            warn!(
-                status = %response.status(),
-                url = %url,
-                "sessions-ms rejected terminal events"
-            );
-        }
-        Err(error) => {
-            warn!(
-                "Failed to forward terminal events to sessions-ms: {}",
-                error
-            );
+    sinks.publish(&batch).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+
+    use k8s_openapi::api::core::v1::Pod;
+    use kube::{api::ObjectMeta, Api};
+    use tokio::sync::mpsc;
+    use uuid::Uuid;
+
+    use super::{
+        forward_terminal_events, load_terminal_event_context,
+        start_terminal_command_event_forwarder, update_terminal_event_context,
+        TerminalEventContext,
+    };
+    use crate::models::TerminalCapturePolicy;
+    use crate::services::{
+        terminal_event_outbox::TerminalEventOutbox,
+        terminal_event_sinks::{
+            tests::InMemorySink, TerminalEvent, TerminalEventKind, TerminalEventSinks,
+        },
+        web_shell::terminal_shell_integration_exit_status_markers::CompletedTerminalCommand,
+    };
+
+    fn anonymous_context() -> TerminalEventContext {
+        TerminalEventContext {
+            session_id: Uuid::new_v4(),
+            runtime_id: Uuid::new_v4(),
+            user_id: None,
+            lab_id: None,
         }
     }
+
+    #[tokio::test]
+    async fn queued_commands_are_published_when_the_session_ends() {
+        let sink = Arc::new(InMemorySink::default());
+        let context = TerminalEventContext {
+            user_id: Some(Uuid::new_v4()),
+            lab_id: Some(Uuid::new_v4()),
+            ..anonymous_context()
+        };
+        let (tx, rx) = mpsc::channel(8);
+        let forwarder = super::TerminalCommandEventForwarder {
+            tx,
+            outbox: Arc::new(TerminalEventOutbox::from_env()),
+        };
+
+        forwarder.send_command(CompletedTerminalCommand {
+            command_redacted: "nmap -sV 10.0.0.5".to_string(),
+            exit_status: Some(0),
+            duration_ms: Some(1200),
+            output_excerpt: None,
+        });
+        drop(forwarder);
+        forward_terminal_events(
+            context.clone(),
+            None,
+            Arc::new(TerminalEventSinks::new(vec![sink.clone()])),
+            rx,
+        )
+        .await;
+
+        let batches = sink.batches.lock().unwrap();
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0].session_id, context.session_id);
+        assert_eq!(batches[0].lab_id, context.lab_id);
+        assert_eq!(
+            batches[0].events[0].kind,
+            TerminalEventKind::Command {
+                command_redacted: "nmap -sV 10.0.0.5".to_string(),
+                exit_status: Some(0),
+                duration_ms: Some(1200),
+                output_excerpt: None,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn no_forwarder_is_started_when_capture_is_off() {
+        let context = anonymous_context();
+        let pod = Pod {
+            metadata: ObjectMeta {
+                name: Some("ctf-runtime-1".to_string()),
+                labels: Some(BTreeMap::from([
+                    ("session_id".to_string(), context.session_id.to_string()),
+                    ("runtime_id".to_string(), context.runtime_id.to_string()),
+                ])),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let config = kube::Config::new("http://127.0.0.1:9".parse().unwrap());
+        let pods: Api<Pod> = Api::default_namespaced(kube::Client::try_from(config).unwrap());
+        let sinks = Arc::new(TerminalEventSinks::new(Vec::new()));
+        let outbox = Arc::new(TerminalEventOutbox::from_env());
+
+        let start = |policy| {
+            start_terminal_command_event_forwarder(
+                pods.clone(),
+                &pod,
+                policy,
+                sinks.clone(),
+                outbox.clone(),
+            )
+        };
+
+        assert!(start(TerminalCapturePolicy::Off).is_none());
+        assert!(start(TerminalCapturePolicy::CommandsOnly).is_some());
+    }
+
+    #[test]
+    fn pods_without_user_or_lab_labels_still_have_a_context() {
+        let context = anonymous_context();
+        let pod = Pod {
+            metadata: ObjectMeta {
+                labels: Some(BTreeMap::from([
+                    ("session_id".to_string(), context.session_id.to_string()),
+                    ("runtime_id".to_string(), context.runtime_id.to_string()),
+                    ("lab_id".to_string(), "not-a-uuid".to_string()),
+                ])),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        assert_eq!(load_terminal_event_context(&pod), Some(context));
+        assert_eq!(load_terminal_event_context(&Pod::default()), None);
+    }
+
+    #[tokio::test]
+    async fn context_changes_flush_pending_events_under_the_old_ids() {
+        let sink = Arc::new(InMemorySink::default());
+        let sinks = TerminalEventSinks::new(vec![sink.clone()]);
+        let anonymous = anonymous_context();
+        let claimed = TerminalEventContext {
+            user_id: Some(Uuid::new_v4()),
+            lab_id: Some(Uuid::new_v4()),
+            ..anonymous.clone()
+        };
+        let mut context = anonymous.clone();
+        let mut events = vec![TerminalEvent::new(TerminalEventKind::TerminalOpened)];
+
+        update_terminal_event_context(&mut context, anonymous.clone(), &sinks, &mut events).await;
+        assert_eq!(events.len(), 1);
+        assert!(sink.batches.lock().unwrap().is_empty());
+
+        update_terminal_event_context(&mut context, claimed.clone(), &sinks, &mut events).await;
+        assert!(events.is_empty());
+        assert_eq!(context, claimed);
+        let batches = sink.batches.lock().unwrap();
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0].user_id, None);
+    }
 }
@@ -1,25 +1,67 @@
 //! Capture and redact commands entered through the terminal WebSocket stream.
+//!
+//! Keystroke capture is only a fallback for shells without integration hooks:
+//! history recall and line editing cannot be reconstructed from raw input.
+
+use super::terminal_command_secret_redaction_rules::TerminalCommandRedactor;
 
 const MAX_CAPTURED_COMMAND_CHARS: usize = 2048;
 
 #[derive(Default)]
-pub(super) struct TerminalCommandInputCapture {
+pub(crate) struct TerminalCommandInputCapture {
     buffer: String,
+    escape: EscapeState,
+}
+
+/// Progress through an escape sequence (arrow keys, Home/End, ...) split across reads.
+#[derive(Default, PartialEq)]
+enum EscapeState {
+    #[default]
+    None,
+    Esc,
+    Csi,
+    Ss3,
 }
 
 impl TerminalCommandInputCapture {
-    pub(super) fn capture_redacted_commands(&mut self, input: &[u8]) -> Vec<String> {
+    pub(super) fn capture_redacted_commands(
+        &mut self,
+        input: &[u8],
+        redactor: &TerminalCommandRedactor,
+    ) -> Vec<String> {
         self.extract_commands(input)
             .into_iter()
-            .map(|command| redact_command(&command))
+            .map(|command| redactor.redact(&command))
             .filter(|command| !command.is_empty())
             .collect()
     }
 
-    fn extract_commands(&mut self, input: &[u8]) -> Vec<String> {
+    pub(crate) fn extract_commands(&mut self, input: &[u8]) -> Vec<String> {
         let mut commands = Vec::new();
 
         for byte in input {
+            match self.escape {
+                EscapeState::None => {}
+                EscapeState::Esc => {
+                    self.escape = match *byte {
+                        b'[' => EscapeState::Csi,
+                        b'O' => EscapeState::Ss3,
+                        _ => EscapeState::None,
+                    };
+                    continue;
+                }
+                EscapeState::Csi => {
+                    if (0x40..=0x7e).contains(byte) {
+                        self.escape = EscapeState::None;
+                    }
+                    continue;
+                }
+                EscapeState::Ss3 => {
+                    self.escape = EscapeState::None;
+                    continue;
+                }
+            }
+
             match *byte {
                 b'\r' | b'\n' => {
                     let command = self.buffer.trim().to_string();
@@ -28,16 +70,16 @@ impl TerminalCommandInputCapture {
                         commands.push(command);
                     }
                 }
-                0x03 => self.buffer.clear(),
+                0x03 | 0x15 => self.buffer.clear(),
                 0x08 | 0x7f => {
                     self.buffer.pop();
                 }
-                0x1b => {}
+                0x1b => self.escape = EscapeState::Esc,
                 b'\t' => self.buffer.push(' '),
-                byte if byte.is_ascii_graphic() || byte == b' ' => {
-                    if self.buffer.len() < MAX_CAPTURED_COMMAND_CHARS {
-                        self.buffer.push(byte as char);
-                    }
+                byte if (byte.is_ascii_graphic() || byte == b' ')
+                    && self.buffer.len() < MAX_CAPTURED_COMMAND_CHARS =>
+                {
+                    self.buffer.push(byte as char);
                 }
                 _ => {}
             }
@@ -47,57 +89,15 @@ impl TerminalCommandInputCapture {
     }
 }
 
-fn redact_command(command: &str) -> String {
-    let trimmed = command.trim();
-    if trimmed.is_empty() {
-        return String::new();
-    }
-
-    let mut redact_next = false;
-    trimmed
-        .split_whitespace()
-        .map(|part| {
-            let lower = part.to_ascii_lowercase();
-            if redact_next {
-                redact_next = false;
-                return "[redacted]".to_string();
-            }
-
-            if matches!(
-                lower.as_str(),
-                "-p" | "--password" | "--token" | "--secret" | "--api-key" | "--key"
-            ) {
-                redact_next = true;
-                return part.to_string();
-            }
-
-            if let Some((key, _)) = part.split_once('=') {
-                let key_lower = key.to_ascii_lowercase();
-                if key_lower.contains("password")
-                    || key_lower.contains("passwd")
-                    || key_lower.contains("secret")
-                    || key_lower.contains("token")
-                    || key_lower.contains("api_key")
-                    || key_lower.contains("apikey")
-                {
-                    return format!("{key}=[redacted]");
-                }
-            }
-
-            part.to_string()
-        })
-        .collect::<Vec<_>>()
-        .join(" ")
-}
-
 #[cfg(test)]
 mod tests {
-    use super::{redact_command, TerminalCommandInputCapture, MAX_CAPTURED_COMMAND_CHARS};
+    use super::{TerminalCommandInputCapture, TerminalCommandRedactor, MAX_CAPTURED_COMMAND_CHARS};
 
     #[test]
     fn extracts_entered_commands_from_terminal_input() {
         let mut capture = TerminalCommandInputCapture::default();
-        let commands = capture.capture_redacted_commands(b"ls -la\rwhoami\n");
+        let commands = capture
+            .capture_redacted_commands(b"ls -la\rwhoami\n", &TerminalCommandRedactor::default());
 
         assert_eq!(commands, vec!["ls -la", "whoami"]);
     }
@@ -106,29 +106,42 @@ mod tests {
     fn keeps_partial_command_until_enter_is_received() {
         let mut capture = TerminalCommandInputCapture::default();
 
-        assert!(capture.capture_redacted_commands(b"ec").is_empty());
+        assert!(capture
+            .capture_redacted_commands(b"ec", &TerminalCommandRedactor::default())
+            .is_empty());
         assert_eq!(
-            capture.capture_redacted_commands(b"ho test\r"),
+            capture.capture_redacted_commands(b"ho test\r", &TerminalCommandRedactor::default()),
             vec!["echo test"]
         );
     }
 
     #[test]
-    fn limits_partial_command_capture() {
+    fn skips_cursor_and_history_escape_sequences() {
         let mut capture = TerminalCommandInputCapture::default();
-        let long_input = vec![b'a'; MAX_CAPTURED_COMMAND_CHARS + 50];
 
+        assert!(capture
+            .capture_redacted_commands(b"\x1b[", &TerminalCommandRedactor::default())
+            .is_empty());
         assert_eq!(
-            capture.capture_redacted_commands(&[long_input, vec![b'\r']].concat()),
-            vec!["a".repeat(MAX_CAPTURED_COMMAND_CHARS)]
+            capture.capture_redacted_commands(
+                b"Als\x1bOD\x1b[1;5C -l\r",
+                &TerminalCommandRedactor::default()
+            ),
+            vec!["ls -l"]
         );
     }
 
     #[test]
-    fn redacts_sensitive_assignment_values() {
+    fn limits_partial_command_capture() {
+        let mut capture = TerminalCommandInputCapture::default();
+        let long_input = vec![b'a'; MAX_CAPTURED_COMMAND_CHARS + 50];
+
         assert_eq!(
-            redact_command("curl --token abc SECRET_KEY=value"),
-            "curl --token [redacted] SECRET_KEY=[redacted]"
+            capture.capture_redacted_commands(
+                &[long_input, vec![b'\r']].concat(),
+                &TerminalCommandRedactor::default()
+            ),
+            vec!["a".repeat(MAX_CAPTURED_COMMAND_CHARS)]
         );
     }
 }
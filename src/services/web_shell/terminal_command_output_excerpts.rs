@@ -0,0 +1,166 @@
+//! Collect a bounded, ANSI-stripped and redacted excerpt of each command's output.
+
+use super::terminal_command_secret_redaction_rules::TerminalCommandRedactor;
+
+pub(super) const DEFAULT_OUTPUT_EXCERPT_MAX_BYTES: u32 = 2048;
+pub(super) const DEFAULT_OUTPUT_EXCERPT_MAX_LINES: u32 = 40;
+const TRUNCATED_SUFFIX: &str = "\n[truncated]";
+// Escape sequences are dropped after collection, so keep more raw bytes than the cap.
+const RAW_BYTES_PER_EXCERPT_BYTE: usize = 4;
+
+/// Per-lab size caps for output excerpts.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct OutputExcerptLimits {
+    pub(super) max_bytes: usize,
+    pub(super) max_lines: usize,
+}
+
+impl Default for OutputExcerptLimits {
+    fn default() -> Self {
+        Self {
+            max_bytes: DEFAULT_OUTPUT_EXCERPT_MAX_BYTES as usize,
+            max_lines: DEFAULT_OUTPUT_EXCERPT_MAX_LINES as usize,
+        }
+    }
+}
+
+/// Raw PTY output of the command currently running.
+pub(super) struct OutputExcerptBuffer {
+    limits: OutputExcerptLimits,
+    raw: Vec<u8>,
+    overflowed: bool,
+}
+
+impl OutputExcerptBuffer {
+    pub(super) fn new(limits: OutputExcerptLimits) -> Self {
+        Self {
+            limits,
+            raw: Vec::new(),
+            overflowed: false,
+        }
+    }
+
+    pub(super) fn push(&mut self, output: &[u8]) {
+        let capacity = self.limits.max_bytes * RAW_BYTES_PER_EXCERPT_BYTE;
+        let room = capacity.saturating_sub(self.raw.len());
+        if output.len() > room {
+            self.overflowed = true;
+        }
+        self.raw
+            .extend_from_slice(&output[..output.len().min(room)]);
+    }
+
+    /// Returns the redacted excerpt (if the command printed anything) and resets the buffer.
+    pub(super) fn take(&mut self, redactor: &TerminalCommandRedactor) -> Option<String> {
+        let raw = std::mem::take(&mut self.raw);
+        let mut truncated = std::mem::take(&mut self.overflowed);
+
+        let text = strip_ansi(&raw);
+        let lines: Vec<String> = text
+            .lines()
+            .map(|line| redactor.redact(line))
+            .filter(|line| !line.is_empty())
+            .collect();
+        if lines.is_empty() {
+            return None;
+        }
+        truncated |= lines.len() > self.limits.max_lines;
+
+        let mut excerpt = lines
+            .into_iter()
+            .take(self.limits.max_lines)
+            .collect::<Vec<_>>()
+            .join("\n");
+        if excerpt.len() > self.limits.max_bytes {
+            let mut end = self.limits.max_bytes;
+            while !excerpt.is_char_boundary(end) {
+                end -= 1;
+            }
+            excerpt.truncate(end);
+            truncated = true;
+        }
+        if truncated {
+            excerpt.push_str(TRUNCATED_SUFFIX);
+        }
+
+        Some(excerpt)
+    }
+}
+
+/// Drops CSI/OSC/other escape sequences and control characters, keeping text and newlines.
+fn strip_ansi(raw: &[u8]) -> String {
+    let text = String::from_utf8_lossy(raw);
+    let mut output = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\x1b' => match chars.next() {
+                Some('[') => {
+                    for c in chars.by_ref() {
+                        if ('\x40'..='\x7e').contains(&c) {
+                            break;
+                        }
+                    }
+                }
+                Some(']') => {
+                    while let Some(c) = chars.next() {
+                        if c == '\x07' || (c == '\x1b' && chars.next_if_eq(&'\\').is_some()) {
+                            break;
+                        }
+                    }
+                }
+                _ => {}
+            },
+            '\n' | '\t' => output.push(c),
+            c if c.is_control() => {}
+            c => output.push(c),
+        }
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{strip_ansi, OutputExcerptBuffer, OutputExcerptLimits};
+    use crate::services::web_shell::terminal_command_secret_redaction_rules::TerminalCommandRedactor;
+
+    #[test]
+    fn escape_sequences_and_carriage_returns_are_stripped() {
+        assert_eq!(
+            strip_ansi(b"\x1b[01;34mbin\x1b[0m  etc\r\n\x1b]0;title\x07done\r\n"),
+            "bin  etc\ndone\n"
+        );
+    }
+
+    #[test]
+    fn excerpts_are_redacted_and_capped() {
+        let mut buffer = OutputExcerptBuffer::new(OutputExcerptLimits {
+            max_bytes: 64,
+            max_lines: 2,
+        });
+        buffer.push(b"connecting with --password hunter2\r\n");
+        buffer.push(b"ok\r\nthird line\r\n");
+
+        assert_eq!(
+            buffer.take(&TerminalCommandRedactor::default()).as_deref(),
+            Some("connecting with --password [redacted]\nok\n[truncated]")
+        );
+        assert_eq!(buffer.take(&TerminalCommandRedactor::default()), None);
+    }
+
+    #[test]
+    fn long_output_is_cut_on_a_character_boundary() {
+        let mut buffer = OutputExcerptBuffer::new(OutputExcerptLimits {
+            max_bytes: 5,
+            max_lines: 10,
+        });
+        buffer.push("héllo wörld".as_bytes());
+
+        assert_eq!(
+            buffer.take(&TerminalCommandRedactor::default()).as_deref(),
+            Some("héll\n[truncated]")
+        );
+    }
+}
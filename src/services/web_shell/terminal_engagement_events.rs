@@ -0,0 +1,225 @@
+//! Derive engagement events (idle periods, large pastes) from terminal activity.
+
+use std::future::pending;
+
+use tokio::time::{sleep, Duration};
+
+use super::terminal_command_event_forwarding_to_sessions_ms::TerminalCommandEventForwarder;
+use super::terminal_session_idle_and_duration_limits::{duration_env, TerminalActivity};
+use crate::services::terminal_event_sinks::TerminalEventKind;
+
+const DEFAULT_IDLE_EVENT_SECS: u64 = 300;
+const DEFAULT_PASTE_EVENT_MIN_BYTES: u64 = 256;
+const IDLE_POLL_SECS: u64 = 5;
+const PASTE_START: &[u8] = b"\x1b[200~";
+const PASTE_END: &[u8] = b"\x1b[201~";
+
+/// Emits `idle_started` / `idle_ended` around quiet periods longer than the threshold.
+#[derive(Debug)]
+struct IdlePeriodTracker {
+    threshold: Duration,
+    idle_from: Option<Duration>,
+}
+
+impl IdlePeriodTracker {
+    /// `elapsed` is time since the session opened, `idle` time since the last activity.
+    fn observe(&mut self, elapsed: Duration, idle: Duration) -> Option<TerminalEventKind> {
+        let last_activity = elapsed.saturating_sub(idle);
+
+        match self.idle_from {
+            None if idle >= self.threshold => {
+                self.idle_from = Some(last_activity);
+                Some(TerminalEventKind::IdleStarted {
+                    idle_after_secs: self.threshold.as_secs(),
+                })
+            }
+            Some(idle_from) if last_activity > idle_from => {
+                self.idle_from = None;
+                Some(TerminalEventKind::IdleEnded {
+                    idle_secs: (last_activity - idle_from).as_secs(),
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Runs for the whole session, reporting idle periods through the forwarder.
+pub(super) async fn track_idle_periods(
+    activity: &TerminalActivity,
+    forwarder: Option<&TerminalCommandEventForwarder>,
+) {
+    let threshold = duration_env("WEBSHELL_IDLE_EVENT_SECS", DEFAULT_IDLE_EVENT_SECS);
+    let (Some(forwarder), Some(threshold)) = (forwarder, threshold) else {
+        return pending().await;
+    };
+    let poll = threshold.min(Duration::from_secs(IDLE_POLL_SECS));
+    let mut tracker = IdlePeriodTracker {
+        threshold,
+        idle_from: None,
+    };
+
+    loop {
+        sleep(poll).await;
+        if let Some(event) = tracker.observe(activity.elapsed(), activity.idle_for()) {
+            forwarder.send_event(event);
+        }
+    }
+}
+
+/// Measures bracketed-paste blocks in terminal input.
+pub(super) struct PasteDetector {
+    min_bytes: u64,
+    active: Option<(u64, u64)>,
+    /// Start of a marker that may continue in the next frame.
+    carry: Vec<u8>,
+}
+
+impl PasteDetector {
+    pub(super) fn from_env() -> Self {
+        Self {
+            min_bytes: std::env::var("WEBSHELL_PASTE_EVENT_MIN_BYTES")
+                .ok()
+                .and_then(|value| value.trim().parse().ok())
+                .unwrap_or(DEFAULT_PASTE_EVENT_MIN_BYTES),
+            active: None,
+            carry: Vec::new(),
+        }
+    }
+
+    /// Returns a `paste_detected` event for each large paste completed in `input`.
+    pub(super) fn observe(&mut self, input: &[u8]) -> Vec<TerminalEventKind> {
+        let mut events = Vec::new();
+        let mut data = std::mem::take(&mut self.carry);
+        data.extend_from_slice(input);
+        let mut rest = data.as_slice();
+
+        while !rest.is_empty() {
+            let marker = if self.active.is_some() {
+                PASTE_END
+            } else {
+                PASTE_START
+            };
+            let found = find(rest, marker);
+            let consumed = found.unwrap_or(rest.len() - partial_marker_len(rest, marker));
+            if let Some((bytes, lines)) = self.active.as_mut() {
+                let pasted = &rest[..consumed];
+                *bytes += pasted.len() as u64;
+                *lines += pasted
+                    .iter()
+                    .filter(|b| **b == b'\r' || **b == b'\n')
+                    .count() as u64;
+            }
+
+            let Some(found) = found else {
+                self.carry = rest[consumed..].to_vec();
+                break;
+            };
+            match self.active.take() {
+                None => self.active = Some((0, 0)),
+                Some((bytes, lines)) if bytes >= self.min_bytes => {
+                    events.push(TerminalEventKind::PasteDetected {
+                        bytes,
+                        lines: lines + 1,
+                    });
+                }
+                Some(_) => {}
+            }
+            rest = &rest[found + marker.len()..];
+        }
+
+        events
+    }
+}
+
+/// Length of the longest end of `data` that `marker` starts with.
+fn partial_marker_len(data: &[u8], marker: &[u8]) -> usize {
+    (1..marker.len())
+        .rev()
+        .find(|len| data.ends_with(&marker[..*len]))
+        .unwrap_or(0)
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::time::Duration;
+
+    use super::{IdlePeriodTracker, PasteDetector};
+    use crate::services::terminal_event_sinks::TerminalEventKind;
+
+    #[test]
+    fn idle_periods_start_and_end_once() {
+        let mut tracker = IdlePeriodTracker {
+            threshold: Duration::from_secs(60),
+            idle_from: None,
+        };
+        let secs = Duration::from_secs;
+
+        assert_eq!(tracker.observe(secs(30), secs(20)), None);
+        assert_eq!(
+            tracker.observe(secs(80), secs(70)),
+            Some(TerminalEventKind::IdleStarted {
+                idle_after_secs: 60
+            })
+        );
+        assert_eq!(tracker.observe(secs(90), secs(80)), None);
+        assert_eq!(
+            tracker.observe(secs(125), secs(5)),
+            Some(TerminalEventKind::IdleEnded { idle_secs: 110 })
+        );
+    }
+
+    #[test]
+    fn large_pastes_are_reported_across_frames() {
+        let mut detector = PasteDetector {
+            min_bytes: 8,
+            active: None,
+            carry: Vec::new(),
+        };
+
+        assert!(detector.observe(b"ls\x1b[200~line one\r").is_empty());
+        assert_eq!(
+            detector.observe(b"line two\x1b[201~\r"),
+            vec![TerminalEventKind::PasteDetected {
+                bytes: 17,
+                lines: 2,
+            }]
+        );
+    }
+
+    #[test]
+    fn paste_markers_split_across_frames_are_recognised() {
+        let mut detector = PasteDetector {
+            min_bytes: 8,
+            active: None,
+            carry: Vec::new(),
+        };
+
+        assert!(detector.observe(b"ls\x1b[20").is_empty());
+        assert!(detector.observe(b"0~line one\r\x1b[2").is_empty());
+        assert!(detector.observe(b"0").is_empty());
+        assert_eq!(
+            detector.observe(b"1~\r"),
+            vec![TerminalEventKind::PasteDetected { bytes: 9, lines: 2 }]
+        );
+        assert!(detector.observe(b"\x1b").is_empty());
+        assert!(detector.observe(b"[A").is_empty());
+    }
+
+    #[test]
+    fn small_pastes_are_ignored() {
+        let mut detector = PasteDetector {
+            min_bytes: 64,
+            active: None,
+            carry: Vec::new(),
+        };
+
+        assert!(detector.observe(b"\x1b[200~pwd\x1b[201~").is_empty());
+    }
+}
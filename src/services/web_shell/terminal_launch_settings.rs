@@ -0,0 +1,448 @@
+//! Resolve per-runtime web shell launch settings stored on the Pod at spawn time.
+
+use k8s_openapi::api::core::v1::Pod;
+
+use crate::models::WebShellSettings;
+
+use super::terminal_command_output_excerpts::{
+    OutputExcerptLimits, DEFAULT_OUTPUT_EXCERPT_MAX_BYTES, DEFAULT_OUTPUT_EXCERPT_MAX_LINES,
+};
+use super::WEBSHELL_COMMAND;
+
+pub(crate) const WEBSHELL_SETTINGS_ANNOTATION: &str = "altair.io/webshell";
+
+const MAX_COMMAND_ARGS: usize = 32;
+const MAX_ENV_VARS: usize = 64;
+const MAX_VALUE_CHARS: usize = 4096;
+const MAX_USER_CHARS: usize = 32;
+const RESERVED_ENV_PREFIX: &str = "ALTAIR_FLAG_STEP_";
+const MAX_OUTPUT_EXCERPT_BYTES: u32 = 16 * 1024;
+const MAX_OUTPUT_EXCERPT_LINES: u32 = 500;
+
+/// Container and argv used to open the exec session for a web shell.
+#[derive(Debug, PartialEq)]
+pub(crate) struct TerminalLaunch {
+    pub(crate) container: Option<String>,
+    pub(crate) command: Vec<String>,
+    pub(crate) output_excerpt: OutputExcerptLimits,
+    prelude: String,
+    user: Option<String>,
+}
+
+impl TerminalLaunch {
+    /// Argv running a one-off command with the same env, directory and user as the shell.
+    pub(crate) fn exec_command(&self, command: &str) -> Vec<String> {
+        wrap_for_user(format!("{}{command}", self.prelude), self.user.as_deref())
+    }
+}
+
+pub(crate) fn validate_webshell_settings(settings: &WebShellSettings) -> Result<(), String> {
+    if let Some(container) = &settings.container {
+        if !is_dns_label(container) {
+            return Err(format!(
+                "container `{container}` is not a valid Kubernetes container name"
+            ));
+        }
+    }
+
+    if let Some(command) = &settings.command {
+        if command.is_empty() || command[0].trim().is_empty() {
+            return Err("command must contain at least the program to run".to_string());
+        }
+        if command.len() > MAX_COMMAND_ARGS {
+            return Err(format!(
+                "command accepts at most {MAX_COMMAND_ARGS} arguments"
+            ));
+        }
+        if command.iter().any(|arg| !is_safe_value(arg)) {
+            return Err(format!(
+                "command arguments must be at most {MAX_VALUE_CHARS} characters without NUL bytes"
+            ));
+        }
+    }
+
+    if settings.env.len() > MAX_ENV_VARS {
+        return Err(format!("env accepts at most {MAX_ENV_VARS} variables"));
+    }
+    for (name, value) in &settings.env {
+        if !is_env_name(name) {
+            return Err(format!("env name `{name}` is not a valid variable name"));
+        }
+        if name.starts_with(RESERVED_ENV_PREFIX) {
+            return Err(format!(
+                "env name `{name}` uses the reserved {RESERVED_ENV_PREFIX} prefix"
+            ));
+        }
+        if !is_safe_value(value) {
+            return Err(format!(
+                "env value for `{name}` must be at most {MAX_VALUE_CHARS} characters without NUL bytes"
+            ));
+        }
+    }
+
+    if let Some(working_dir) = &settings.working_dir {
+        if !working_dir.starts_with('/')
+            || !is_safe_value(working_dir)
+            || working_dir.contains('\n')
+        {
+            return Err(format!(
+                "working_dir `{working_dir}` must be an absolute path"
+            ));
+        }
+    }
+
+    if let Some(user) = &settings.user {
+        if !is_user_name(user) {
+            return Err(format!("user `{user}` is not a valid login name"));
+        }
+    }
+
+    if settings
+        .output_excerpt_max_bytes
+        .is_some_and(|bytes| !(1..=MAX_OUTPUT_EXCERPT_BYTES).contains(&bytes))
+    {
+        return Err(format!(
+            "output_excerpt_max_bytes must be between 1 and {MAX_OUTPUT_EXCERPT_BYTES}"
+        ));
+    }
+    if settings
+        .output_excerpt_max_lines
+        .is_some_and(|lines| !(1..=MAX_OUTPUT_EXCERPT_LINES).contains(&lines))
+    {
+        return Err(format!(
+            "output_excerpt_max_lines must be between 1 and {MAX_OUTPUT_EXCERPT_LINES}"
+        ));
+    }
+
+    Ok(())
+}
+
+pub(super) fn resolve_terminal_launch(pod: &Pod) -> Result<TerminalLaunch, String> {
+    let settings = match pod
+        .metadata
+        .annotations
+        .as_ref()
+        .and_then(|annotations| annotations.get(WEBSHELL_SETTINGS_ANNOTATION))
+    {
+        Some(raw) => serde_json::from_str::<WebShellSettings>(raw)
+            .map_err(|error| format!("web shell settings are not valid JSON: {error}"))?,
+        None => WebShellSettings::default(),
+    };
+    validate_webshell_settings(&settings)?;
+
+    if !is_webshell_enabled(pod, &settings) {
+        return Err("web shell access is disabled for this runtime".to_string());
+    }
+
+    if let Some(container) = &settings.container {
+        let exists = pod
+            .spec
+            .as_ref()
+            .is_some_and(|spec| spec.containers.iter().any(|c| &c.name == container));
+        if !exists {
+            return Err(format!(
+                "container `{container}` does not exist in this runtime"
+            ));
+        }
+    }
+
+    Ok(build_terminal_launch(&settings))
+}
+
+fn is_webshell_enabled(pod: &Pod, settings: &WebShellSettings) -> bool {
+    let runtime_kind = pod
+        .metadata
+        .labels
+        .as_ref()
+        .and_then(|labels| labels.get("runtime_kind"))
+        .map(String::as_str);
+
+    match runtime_kind {
+        // Terminal labs are a shell, and IDE labs keep one next to the editor.
+        None | Some("terminal" | "ide") => settings.enabled != Some(false),
+        // Web and desktop labs expose their app, not a shell, unless the lab opts in.
+        Some(_) => settings.enabled == Some(true),
+    }
+}
+
+fn build_terminal_launch(settings: &WebShellSettings) -> TerminalLaunch {
+    let mut prelude = String::new();
+
+    for (name, value) in &settings.env {
+        prelude.push_str(&format!("export {name}={}\n", shell_quote(value)));
+    }
+    if let Some(working_dir) = &settings.working_dir {
+        prelude.push_str(&format!("cd {} || exit 1\n", shell_quote(working_dir)));
+    }
+
+    let shell = match &settings.command {
+        Some(command) => {
+            let argv = command
+                .iter()
+                .map(|arg| shell_quote(arg))
+                .collect::<Vec<_>>()
+                .join(" ");
+            format!("exec {argv}\n")
+        }
+        None => WEBSHELL_COMMAND.to_string(),
+    };
+
+    TerminalLaunch {
+        container: settings.container.clone(),
+        command: wrap_for_user(format!("{prelude}{shell}"), settings.user.as_deref()),
+        output_excerpt: OutputExcerptLimits {
+            max_bytes: settings.output_excerpt_max_bytes.unwrap_or_else(|| {
+                u32_env(
+                    "WEBSHELL_OUTPUT_EXCERPT_MAX_BYTES",
+                    DEFAULT_OUTPUT_EXCERPT_MAX_BYTES,
+                )
+            }) as usize,
+            max_lines: settings.output_excerpt_max_lines.unwrap_or_else(|| {
+                u32_env(
+                    "WEBSHELL_OUTPUT_EXCERPT_MAX_LINES",
+                    DEFAULT_OUTPUT_EXCERPT_MAX_LINES,
+                )
+            }) as usize,
+        },
+        prelude,
+        user: settings.user.clone(),
+    }
+}
+
+fn u32_env(key: &str, default: u32) -> u32 {
+    std::env::var(key)
+        .ok()
+        .and_then(|value| value.trim().parse().ok())
+        .filter(|value| *value > 0)
+        .unwrap_or(default)
+}
+
+fn wrap_for_user(script: String, user: Option<&str>) -> Vec<String> {
+    // `su -l` resets the environment, so the exports and cd run inside the target
+    // user's login shell rather than before the switch.
+    let script = match user {
+        Some(user) => format!("exec su -l {user} -s /bin/sh -c {}", shell_quote(&script)),
+        None => script,
+    };
+
+    vec!["/bin/sh".to_string(), "-lc".to_string(), script]
+}
+
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+fn is_dns_label(value: &str) -> bool {
+    !value.is_empty()
+        && value.len() <= 63
+        && value
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+        && !value.starts_with('-')
+        && !value.ends_with('-')
+}
+
+fn is_env_name(value: &str) -> bool {
+    let mut chars = value.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn is_user_name(value: &str) -> bool {
+    let mut chars = value.chars();
+    value.len() <= MAX_USER_CHARS
+        && chars
+            .next()
+            .is_some_and(|c| c.is_ascii_lowercase() || c == '_')
+        && chars
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '_' | '-' | '.'))
+}
+
+fn is_safe_value(value: &str) -> bool {
+    value.chars().count() <= MAX_VALUE_CHARS && !value.contains('\0')
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use k8s_openapi::api::core::v1::{Container, Pod, PodSpec};
+
+    use super::{
+        resolve_terminal_launch, validate_webshell_settings, WEBSHELL_COMMAND,
+        WEBSHELL_SETTINGS_ANNOTATION,
+    };
+    use crate::models::WebShellSettings;
+
+    fn pod_with_settings(settings: Option<&str>) -> Pod {
+        pod_with_kind_and_settings("terminal", settings)
+    }
+
+    fn pod_with_kind_and_settings(runtime_kind: &str, settings: Option<&str>) -> Pod {
+        Pod {
+            metadata: kube::core::ObjectMeta {
+                labels: Some(BTreeMap::from([(
+                    "runtime_kind".to_string(),
+                    runtime_kind.to_string(),
+                )])),
+                annotations: settings.map(|raw| {
+                    BTreeMap::from([(WEBSHELL_SETTINGS_ANNOTATION.to_string(), raw.to_string())])
+                }),
+                ..Default::default()
+            },
+            spec: Some(PodSpec {
+                containers: vec![
+                    Container {
+                        name: "lab-container".to_string(),
+                        ..Default::default()
+                    },
+                    Container {
+                        name: "tools".to_string(),
+                        ..Default::default()
+                    },
+                ],
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn runtime_without_settings_uses_default_shell() {
+        let launch = resolve_terminal_launch(&pod_with_settings(None)).unwrap();
+
+        assert!(launch.container.is_none());
+        assert_eq!(launch.command, vec!["/bin/sh", "-lc", WEBSHELL_COMMAND]);
+    }
+
+    #[test]
+    fn settings_select_container_env_dir_and_command() {
+        let launch = resolve_terminal_launch(&pod_with_settings(Some(
+            r#"{"container":"tools","command":["zsh","-i"],"env":{"LANG":"C.UTF-8"},"working_dir":"/srv/it's"}"#,
+        )))
+        .unwrap();
+
+        assert_eq!(launch.container.as_deref(), Some("tools"));
+        assert_eq!(
+            launch.command[2],
+            "export LANG='C.UTF-8'\ncd '/srv/it'\\''s' || exit 1\nexec 'zsh' '-i'\n"
+        );
+    }
+
+    #[test]
+    fn target_user_wraps_the_shell_in_su() {
+        let launch =
+            resolve_terminal_launch(&pod_with_settings(Some(r#"{"user":"student"}"#))).unwrap();
+
+        assert!(launch.command[2].starts_with("exec su -l student -s /bin/sh -c '"));
+        assert!(launch.command[2].contains("@altair"));
+    }
+
+    #[test]
+    fn web_and_desktop_runtimes_require_an_explicit_opt_in() {
+        for kind in ["web", "desktop"] {
+            assert!(resolve_terminal_launch(&pod_with_kind_and_settings(kind, None)).is_err());
+            assert!(resolve_terminal_launch(&pod_with_kind_and_settings(
+                kind,
+                Some(r#"{"enabled":true}"#)
+            ))
+            .is_ok());
+        }
+        assert!(resolve_terminal_launch(&pod_with_kind_and_settings("ide", None)).is_ok());
+    }
+
+    #[test]
+    fn terminal_runtimes_can_disable_the_web_shell() {
+        let error =
+            resolve_terminal_launch(&pod_with_settings(Some(r#"{"enabled":false}"#))).unwrap_err();
+
+        assert!(error.contains("disabled"));
+    }
+
+    #[test]
+    fn one_off_commands_reuse_env_dir_and_user() {
+        let launch = resolve_terminal_launch(&pod_with_settings(Some(
+            r#"{"user":"student","working_dir":"/home/student"}"#,
+        )))
+        .unwrap();
+
+        assert_eq!(
+            launch.exec_command("scp -t .")[2],
+            "exec su -l student -s /bin/sh -c 'cd '\\''/home/student'\\'' || exit 1\nscp -t .'"
+        );
+    }
+
+    #[test]
+    fn output_excerpt_caps_come_from_settings() {
+        let launch = resolve_terminal_launch(&pod_with_settings(Some(
+            r#"{"output_excerpt_max_bytes":512,"output_excerpt_max_lines":5}"#,
+        )))
+        .unwrap();
+
+        assert_eq!(launch.output_excerpt.max_bytes, 512);
+        assert_eq!(launch.output_excerpt.max_lines, 5);
+    }
+
+    #[test]
+    fn unknown_container_is_rejected() {
+        let error = resolve_terminal_launch(&pod_with_settings(Some(r#"{"container":"missing"}"#)))
+            .unwrap_err();
+
+        assert!(error.contains("does not exist"));
+    }
+
+    #[test]
+    fn invalid_settings_are_rejected_with_a_reason() {
+        let cases = [
+            (
+                WebShellSettings {
+                    command: Some(vec![]),
+                    ..Default::default()
+                },
+                "command",
+            ),
+            (
+                WebShellSettings {
+                    user: Some("root; rm -rf /".to_string()),
+                    ..Default::default()
+                },
+                "user",
+            ),
+            (
+                WebShellSettings {
+                    working_dir: Some("relative/path".to_string()),
+                    ..Default::default()
+                },
+                "working_dir",
+            ),
+            (
+                WebShellSettings {
+                    env: BTreeMap::from([("ALTAIR_FLAG_STEP_1".to_string(), "x".to_string())]),
+                    ..Default::default()
+                },
+                "reserved",
+            ),
+            (
+                WebShellSettings {
+                    container: Some("Bad_Name".to_string()),
+                    ..Default::default()
+                },
+                "container",
+            ),
+            (
+                WebShellSettings {
+                    output_excerpt_max_bytes: Some(0),
+                    ..Default::default()
+                },
+                "output_excerpt_max_bytes",
+            ),
+        ];
+
+        for (settings, expected) in cases {
+            let error = validate_webshell_settings(&settings).unwrap_err();
+            assert!(error.contains(expected), "{error}");
+        }
+    }
+}
@@ -0,0 +1,126 @@
+//! Detect the runtime's flag values in terminal output without forwarding them.
+
+use k8s_openapi::api::core::v1::Pod;
+
+use super::terminal_command_secret_redaction_rules::runtime_flag_values;
+
+struct WatchedFlag {
+    step: u32,
+    value: Vec<u8>,
+    revealed: bool,
+}
+
+/// Scans the PTY stream for flag values, keeping a tail of each read so that
+/// values split across reads are still found. Each step is reported once.
+pub(super) struct TerminalFlagRevealScanner {
+    flags: Vec<WatchedFlag>,
+    carry: Vec<u8>,
+    carry_len: usize,
+}
+
+impl TerminalFlagRevealScanner {
+    pub(super) fn for_pod(pod: &Pod) -> Self {
+        let flags: Vec<WatchedFlag> = runtime_flag_values(pod)
+            .into_iter()
+            .filter_map(|(step, value)| {
+                Some(WatchedFlag {
+                    step: step.parse().ok()?,
+                    value: value.into_bytes(),
+                    revealed: false,
+                })
+            })
+            .collect();
+        let carry_len = flags
+            .iter()
+            .map(|flag| flag.value.len().saturating_sub(1))
+            .max()
+            .unwrap_or_default();
+
+        Self {
+            flags,
+            carry: Vec::new(),
+            carry_len,
+        }
+    }
+
+    /// Returns the steps whose flag value appeared for the first time in `output`.
+    pub(super) fn scan(&mut self, output: &[u8]) -> Vec<u32> {
+        if self.flags.iter().all(|flag| flag.revealed) {
+            return Vec::new();
+        }
+
+        let mut window = std::mem::take(&mut self.carry);
+        window.extend_from_slice(output);
+
+        let mut revealed = Vec::new();
+        for flag in self.flags.iter_mut().filter(|flag| !flag.revealed) {
+            if window
+                .windows(flag.value.len())
+                .any(|candidate| candidate == flag.value.as_slice())
+            {
+                flag.revealed = true;
+                revealed.push(flag.step);
+            }
+        }
+
+        let keep_from = window.len().saturating_sub(self.carry_len);
+        self.carry = window.split_off(keep_from);
+
+        revealed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use k8s_openapi::api::core::v1::{Container, EnvVar, Pod, PodSpec};
+
+    use super::TerminalFlagRevealScanner;
+
+    fn scanner(flags: &[(&str, &str)]) -> TerminalFlagRevealScanner {
+        let pod = Pod {
+            spec: Some(PodSpec {
+                containers: vec![Container {
+                    name: "lab-container".to_string(),
+                    env: Some(
+                        flags
+                            .iter()
+                            .map(|(step, value)| EnvVar {
+                                name: format!("ALTAIR_FLAG_STEP_{step}"),
+                                value: Some(value.to_string()),
+                                ..Default::default()
+                            })
+                            .collect(),
+                    ),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        TerminalFlagRevealScanner::for_pod(&pod)
+    }
+
+    #[test]
+    fn flags_split_across_reads_are_detected() {
+        let mut scanner = scanner(&[("1", "ALTAIR{first_step}"), ("2", "ALTAIR{second}")]);
+
+        assert!(scanner.scan(b"cat flag.txt\r\nALTAIR{fir").is_empty());
+        assert!(scanner.scan(b"st_").is_empty());
+        assert_eq!(scanner.scan(b"step}\r\n$ "), vec![1]);
+    }
+
+    #[test]
+    fn each_step_is_reported_once() {
+        let mut scanner = scanner(&[("3", "ALTAIR{again}")]);
+
+        assert_eq!(scanner.scan(b"ALTAIR{again}"), vec![3]);
+        assert!(scanner.scan(b"ALTAIR{again}").is_empty());
+    }
+
+    #[test]
+    fn runtimes_without_flags_never_match() {
+        let mut scanner = scanner(&[]);
+
+        assert!(scanner.scan(b"ALTAIR{anything}").is_empty());
+    }
+}
@@ -0,0 +1,307 @@
+//! Close web shell sessions that stay idle or outlive their maximum duration.
+//! The SSH gateway applies the same limits to its shell and command sessions.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use chrono::{DateTime, Utc};
+use k8s_openapi::api::core::v1::Pod;
+use kube::Api;
+use serde::Serialize;
+use tokio::sync::mpsc;
+use tokio::time::{sleep, Duration, Instant};
+use tracing::warn;
+use uuid::Uuid;
+
+use super::terminal_command_event_forwarding_to_sessions_ms::load_terminal_event_context;
+use crate::services::sessions_ms_client::SessionsMsClient;
+
+const DEFAULT_IDLE_TIMEOUT_SECS: u64 = 1800;
+const DEFAULT_MAX_SESSION_SECS: u64 = 7200;
+const DEFAULT_WARNING_SECS: u64 = 60;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum TerminalLimitReason {
+    IdleTimeout,
+    MaxDuration,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum TerminalLimitState {
+    Ok {
+        recheck_in: Duration,
+    },
+    Warning {
+        reason: TerminalLimitReason,
+        remaining: Duration,
+    },
+    Expired(TerminalLimitReason),
+}
+
+pub(crate) struct TerminalSessionLimits {
+    idle_timeout: Option<Duration>,
+    max_duration: Option<Duration>,
+    warning: Duration,
+    pub(super) notify_runtime_idle: bool,
+}
+
+#[derive(Serialize)]
+struct RuntimeIdlePayload {
+    session_id: Uuid,
+    runtime_id: Uuid,
+    event: &'static str,
+    occurred_at: DateTime<Utc>,
+    idle_secs: u64,
+}
+
+/// Tracks the last input or output seen on a terminal session.
+pub(crate) struct TerminalActivity {
+    started_at: Instant,
+    last_activity_ms: AtomicU64,
+}
+
+impl TerminalLimitReason {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            TerminalLimitReason::IdleTimeout => "idle_timeout",
+            TerminalLimitReason::MaxDuration => "max_duration",
+        }
+    }
+
+    fn warning_notice(self, remaining: Duration) -> String {
+        match self {
+            TerminalLimitReason::IdleTimeout => format!(
+                "\r\n[altair] No activity detected: this terminal closes in {}s unless you type something.\r\n",
+                remaining.as_secs().max(1)
+            ),
+            TerminalLimitReason::MaxDuration => format!(
+                "\r\n[altair] Maximum session length reached soon: this terminal closes in {}s.\r\n",
+                remaining.as_secs().max(1)
+            ),
+        }
+    }
+
+    pub(crate) fn closing_notice(self) -> &'static str {
+        match self {
+            TerminalLimitReason::IdleTimeout => {
+                "\r\n[altair] Terminal closed after a period of inactivity.\r\n"
+            }
+            TerminalLimitReason::MaxDuration => {
+                "\r\n[altair] Terminal closed: maximum session length reached.\r\n"
+            }
+        }
+    }
+}
+
+impl TerminalSessionLimits {
+    pub(crate) fn from_env() -> Self {
+        Self {
+            idle_timeout: duration_env("WEBSHELL_IDLE_TIMEOUT_SECS", DEFAULT_IDLE_TIMEOUT_SECS),
+            max_duration: duration_env("WEBSHELL_MAX_SESSION_SECS", DEFAULT_MAX_SESSION_SECS),
+            warning: duration_env("WEBSHELL_LIMIT_WARNING_SECS", DEFAULT_WARNING_SECS)
+                .unwrap_or(Duration::ZERO),
+            notify_runtime_idle: crate::parse_bool_env("WEBSHELL_IDLE_NOTIFY_SESSIONS_MS", false),
+        }
+    }
+
+    pub(crate) fn idle_timeout(&self) -> Option<Duration> {
+        self.idle_timeout
+    }
+
+    fn evaluate(&self, elapsed: Duration, idle: Duration) -> TerminalLimitState {
+        if self.max_duration.is_some_and(|max| elapsed >= max) {
+            return TerminalLimitState::Expired(TerminalLimitReason::MaxDuration);
+        }
+        if self.idle_timeout.is_some_and(|timeout| idle >= timeout) {
+            return TerminalLimitState::Expired(TerminalLimitReason::IdleTimeout);
+        }
+
+        let remaining_max = self.max_duration.map(|max| max - elapsed);
+        let remaining_idle = self.idle_timeout.map(|timeout| timeout - idle);
+
+        if let Some(remaining) = remaining_max.filter(|remaining| *remaining <= self.warning) {
+            return TerminalLimitState::Warning {
+                reason: TerminalLimitReason::MaxDuration,
+                remaining,
+            };
+        }
+        if let Some(remaining) = remaining_idle.filter(|remaining| *remaining <= self.warning) {
+            return TerminalLimitState::Warning {
+                reason: TerminalLimitReason::IdleTimeout,
+                remaining,
+            };
+        }
+
+        let recheck_in = [remaining_max, remaining_idle]
+            .into_iter()
+            .flatten()
+            .map(|remaining| remaining - self.warning)
+            .min()
+            .unwrap_or(Duration::MAX);
+
+        TerminalLimitState::Ok { recheck_in }
+    }
+}
+
+impl TerminalActivity {
+    pub(crate) fn new() -> Self {
+        Self {
+            started_at: Instant::now(),
+            last_activity_ms: AtomicU64::new(0),
+        }
+    }
+
+    pub(crate) fn touch(&self) {
+        self.last_activity_ms
+            .store(self.elapsed().as_millis() as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn elapsed(&self) -> Duration {
+        self.started_at.elapsed()
+    }
+
+    pub(crate) fn idle_for(&self) -> Duration {
+        let last_activity = Duration::from_millis(self.last_activity_ms.load(Ordering::Relaxed));
+        self.elapsed().saturating_sub(last_activity)
+    }
+}
+
+/// Resolves once a limit expires, sending warning notices to the client beforehand.
+pub(crate) async fn enforce_terminal_session_limits(
+    limits: &TerminalSessionLimits,
+    activity: &TerminalActivity,
+    notices: mpsc::Sender<String>,
+) -> TerminalLimitReason {
+    let mut warned: Option<TerminalLimitReason> = None;
+
+    loop {
+        match limits.evaluate(activity.elapsed(), activity.idle_for()) {
+            TerminalLimitState::Expired(reason) => return reason,
+            TerminalLimitState::Warning { reason, remaining } => {
+                if warned != Some(reason) {
+                    warned = Some(reason);
+                    let _ = notices.send(reason.warning_notice(remaining)).await;
+                }
+                sleep(remaining.min(Duration::from_secs(1))).await;
+            }
+            TerminalLimitState::Ok { recheck_in } => {
+                // Activity after an idle warning re-arms the warning for the next idle period.
+                warned = None;
+                sleep(recheck_in.min(Duration::from_secs(60))).await;
+            }
+        }
+    }
+}
+
+pub(super) fn duration_env(key: &str, default_secs: u64) -> Option<Duration> {
+    let secs = std::env::var(key)
+        .ok()
+        .and_then(|value| value.trim().parse::<u64>().ok())
+        .unwrap_or(default_secs);
+
+    (secs > 0).then(|| Duration::from_secs(secs))
+}
+
+/// Tells sessions-ms the runtime went idle so the platform can reclaim the pod.
+/// The ids are read from the Pod's current labels (falling back to `pod`), so
+/// this does not depend on terminal events being forwarded for the session.
+pub(super) async fn notify_runtime_idle(
+    sessions_ms: &SessionsMsClient,
+    pods: &Api<Pod>,
+    pod: &Pod,
+    idle_secs: u64,
+) {
+    let current = match pod.metadata.name.as_deref() {
+        Some(name) => pods.get_opt(name).await.ok().flatten(),
+        None => None,
+    };
+    let Some(context) = current
+        .as_ref()
+        .and_then(load_terminal_event_context)
+        .or_else(|| load_terminal_event_context(pod))
+    else {
+        warn!(
+            pod_name = ?pod.metadata.name,
+            "Runtime went idle but has no session_id/runtime_id labels to report"
+        );
+        return;
+    };
+
+    let payload = RuntimeIdlePayload {
+        session_id: context.session_id,
+        runtime_id: context.runtime_id,
+        event: "runtime_idle",
+        occurred_at: Utc::now(),
+        idle_secs,
+    };
+    if let Err(status) = sessions_ms.post_runtime_event(&payload).await {
+        warn!(
+            status = %status,
+            "Failed to send runtime idle event to sessions-ms"
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TerminalLimitReason, TerminalLimitState, TerminalSessionLimits};
+    use tokio::time::Duration;
+
+    fn limits(idle_secs: Option<u64>, max_secs: Option<u64>) -> TerminalSessionLimits {
+        TerminalSessionLimits {
+            idle_timeout: idle_secs.map(Duration::from_secs),
+            max_duration: max_secs.map(Duration::from_secs),
+            warning: Duration::from_secs(60),
+            notify_runtime_idle: false,
+        }
+    }
+
+    #[test]
+    fn active_session_rechecks_before_the_next_warning() {
+        let state = limits(Some(600), Some(3600))
+            .evaluate(Duration::from_secs(100), Duration::from_secs(30));
+
+        assert_eq!(
+            state,
+            TerminalLimitState::Ok {
+                recheck_in: Duration::from_secs(510)
+            }
+        );
+    }
+
+    #[test]
+    fn idle_session_is_warned_then_closed() {
+        let limits = limits(Some(600), None);
+
+        assert_eq!(
+            limits.evaluate(Duration::from_secs(900), Duration::from_secs(560)),
+            TerminalLimitState::Warning {
+                reason: TerminalLimitReason::IdleTimeout,
+                remaining: Duration::from_secs(40),
+            }
+        );
+        assert_eq!(
+            limits.evaluate(Duration::from_secs(960), Duration::from_secs(600)),
+            TerminalLimitState::Expired(TerminalLimitReason::IdleTimeout)
+        );
+    }
+
+    #[test]
+    fn max_duration_wins_over_activity() {
+        let limits = limits(Some(600), Some(3600));
+
+        assert_eq!(
+            limits.evaluate(Duration::from_secs(3600), Duration::ZERO),
+            TerminalLimitState::Expired(TerminalLimitReason::MaxDuration)
+        );
+    }
+
+    #[test]
+    fn disabled_limits_never_expire() {
+        assert_eq!(
+            limits(None, None).evaluate(Duration::from_secs(86_400), Duration::from_secs(86_400)),
+            TerminalLimitState::Ok {
+                recheck_in: Duration::MAX
+            }
+        );
+    }
+}
@@ -0,0 +1,456 @@
+//! Parse OSC 133/633 shell integration markers from terminal output into command events.
+
+use std::time::Instant;
+
+use super::terminal_command_output_excerpts::{OutputExcerptBuffer, OutputExcerptLimits};
+use super::terminal_command_secret_redaction_rules::TerminalCommandRedactor;
+
+const OSC_PREFIXES: [&[u8]; 2] = [b"\x1b]133;", b"\x1b]633;"];
+const OSC_PREFIX_LEN: usize = 6;
+const MAX_MARKER_BYTES: usize = 4096;
+
+/// Shell integration events emitted by the injected prompt hooks.
+#[derive(Debug, PartialEq)]
+pub(super) enum ShellIntegrationMarker {
+    PromptStart,
+    CommandStart,
+    CommandLine(String),
+    CommandFinished(Option<i32>),
+}
+
+/// A captured command ready to be forwarded, with its outcome when the shell reported one.
+#[derive(Debug, PartialEq)]
+pub(super) struct CompletedTerminalCommand {
+    pub(super) command_redacted: String,
+    pub(super) exit_status: Option<i32>,
+    pub(super) duration_ms: Option<u64>,
+    pub(super) output_excerpt: Option<String>,
+}
+
+/// A piece of PTY output, in stream order: plain output or a marker.
+#[derive(Debug, PartialEq)]
+pub(super) enum ShellIntegrationSegment {
+    Output(Vec<u8>),
+    Marker(ShellIntegrationMarker),
+}
+
+/// Strips OSC 133/633 markers from a PTY stream, keeping partial markers between reads.
+#[derive(Default)]
+pub(super) struct ShellIntegrationMarkerParser {
+    carry: Vec<u8>,
+}
+
+impl ShellIntegrationMarkerParser {
+    #[cfg(test)]
+    fn strip_markers(&mut self, chunk: &[u8]) -> (Vec<u8>, Vec<ShellIntegrationMarker>) {
+        let mut output = Vec::with_capacity(chunk.len());
+        let mut markers = Vec::new();
+
+        for segment in self.split_markers(chunk) {
+            match segment {
+                ShellIntegrationSegment::Output(bytes) => output.extend_from_slice(&bytes),
+                ShellIntegrationSegment::Marker(marker) => markers.push(marker),
+            }
+        }
+
+        (output, markers)
+    }
+
+    /// Separates markers from output, keeping both in the order they arrived.
+    pub(super) fn split_markers(&mut self, chunk: &[u8]) -> Vec<ShellIntegrationSegment> {
+        let mut data = std::mem::take(&mut self.carry);
+        data.extend_from_slice(chunk);
+
+        let mut segments = Vec::new();
+        let mut output = Vec::with_capacity(data.len());
+        let mut i = 0;
+
+        while i < data.len() {
+            if data[i] != 0x1b {
+                output.push(data[i]);
+                i += 1;
+                continue;
+            }
+
+            let rest = &data[i..];
+            if rest.len() < OSC_PREFIX_LEN {
+                if OSC_PREFIXES.iter().any(|prefix| prefix.starts_with(rest)) {
+                    self.carry = rest.to_vec();
+                    break;
+                }
+                output.push(data[i]);
+                i += 1;
+                continue;
+            }
+            if !OSC_PREFIXES.iter().any(|prefix| rest.starts_with(prefix)) {
+                output.push(data[i]);
+                i += 1;
+                continue;
+            }
+
+            let body = &rest[OSC_PREFIX_LEN..];
+            match find_terminator(body) {
+                Some((end, terminator_len)) => {
+                    if let Some(marker) = parse_marker(&body[..end]) {
+                        if !output.is_empty() {
+                            segments
+                                .push(ShellIntegrationSegment::Output(std::mem::take(&mut output)));
+                        }
+                        segments.push(ShellIntegrationSegment::Marker(marker));
+                    }
+                    i += OSC_PREFIX_LEN + end + terminator_len;
+                }
+                None if rest.len() <= MAX_MARKER_BYTES => {
+                    self.carry = rest.to_vec();
+                    break;
+                }
+                None => {
+                    // Not a marker we can finish; let the terminal deal with it.
+                    output.push(data[i]);
+                    i += 1;
+                }
+            }
+        }
+
+        if !output.is_empty() {
+            segments.push(ShellIntegrationSegment::Output(output));
+        }
+        segments
+    }
+}
+
+/// Pairs the command line reported by the shell with its exit status and duration.
+///
+/// Until the first marker is seen the shell is assumed to have no hooks
+/// (custom commands, shells without prompt expansion), and keystroke-captured
+/// commands are released immediately. Shells that only report exit status
+/// (the POSIX `sh` prompt hook) keep the keystroke-captured line until the
+/// next prompt finishes it. Once the shell reports command lines itself
+/// (bash history) it is authoritative and keystroke captures are ignored.
+///
+/// With output excerpts enabled, output seen between a command starting and
+/// finishing is attached to it; shells without hooks never get excerpts.
+#[derive(Default)]
+pub(super) struct TerminalCommandExitTracker {
+    redactor: TerminalCommandRedactor,
+    integrated: bool,
+    prompt_hooked: bool,
+    started_at: Option<Instant>,
+    command_line: Option<String>,
+    /// Keystroke-captured, already redacted line awaiting its exit status.
+    entered_command: Option<String>,
+    output: Option<OutputExcerptBuffer>,
+    output_excerpt: Option<String>,
+}
+
+impl TerminalCommandExitTracker {
+    pub(super) fn new(redactor: TerminalCommandRedactor) -> Self {
+        Self {
+            redactor,
+            ..Default::default()
+        }
+    }
+
+    pub(super) fn with_output_excerpts(mut self, limits: OutputExcerptLimits) -> Self {
+        self.output = Some(OutputExcerptBuffer::new(limits));
+        self
+    }
+
+    /// Records PTY output for the command that is currently running.
+    pub(super) fn output(&mut self, output: &[u8]) {
+        if self.started_at.is_none() {
+            return;
+        }
+        if let Some(buffer) = self.output.as_mut() {
+            buffer.push(output);
+        }
+    }
+
+    pub(super) fn command_entered(
+        &mut self,
+        command_redacted: String,
+    ) -> Vec<CompletedTerminalCommand> {
+        if self.integrated {
+            return Vec::new();
+        }
+        if !self.prompt_hooked {
+            return vec![without_outcome(command_redacted)];
+        }
+        // No `C` marker without a pre-exec hook, so the command starts on Enter.
+        self.started_at = Some(Instant::now());
+        self.entered_command = Some(command_redacted);
+        Vec::new()
+    }
+
+    pub(super) fn marker(
+        &mut self,
+        marker: ShellIntegrationMarker,
+    ) -> Vec<CompletedTerminalCommand> {
+        self.prompt_hooked = true;
+
+        match marker {
+            ShellIntegrationMarker::PromptStart => Vec::new(),
+            ShellIntegrationMarker::CommandStart => {
+                self.started_at = Some(Instant::now());
+                Vec::new()
+            }
+            ShellIntegrationMarker::CommandLine(command_line) => {
+                // The prompt hook reports the line after the command ran, so its output ends here.
+                self.output_excerpt = self.take_output_excerpt();
+                self.integrated = true;
+                self.entered_command = None;
+                self.command_line = Some(command_line);
+                Vec::new()
+            }
+            ShellIntegrationMarker::CommandFinished(exit_status) => {
+                let output_excerpt = self
+                    .output_excerpt
+                    .take()
+                    .or_else(|| self.take_output_excerpt());
+                let started_at = self.started_at.take();
+                let command_redacted = match (self.command_line.take(), self.entered_command.take())
+                {
+                    (Some(command_line), _) => self.redactor.redact(&command_line),
+                    (None, Some(entered_command)) => entered_command,
+                    // Empty lines and interrupted prompts finish without a new history entry.
+                    (None, None) => return Vec::new(),
+                };
+                vec![CompletedTerminalCommand {
+                    command_redacted,
+                    exit_status,
+                    duration_ms: started_at
+                        .map(|started_at| started_at.elapsed().as_millis() as u64),
+                    output_excerpt,
+                }]
+            }
+        }
+    }
+
+    /// Releases a command whose line was reported but never finished.
+    pub(super) fn finish(&mut self) -> Vec<CompletedTerminalCommand> {
+        let output_excerpt = self.output_excerpt.take();
+        self.command_line
+            .take()
+            .map(|command_line| self.redactor.redact(&command_line))
+            .or_else(|| self.entered_command.take())
+            .map(|command_redacted| CompletedTerminalCommand {
+                output_excerpt,
+                ..without_outcome(command_redacted)
+            })
+            .into_iter()
+            .collect()
+    }
+
+    fn take_output_excerpt(&mut self) -> Option<String> {
+        self.output
+            .as_mut()
+            .and_then(|buffer| buffer.take(&self.redactor))
+    }
+}
+
+fn without_outcome(command_redacted: String) -> CompletedTerminalCommand {
+    CompletedTerminalCommand {
+        command_redacted,
+        exit_status: None,
+        duration_ms: None,
+        output_excerpt: None,
+    }
+}
+
+fn find_terminator(body: &[u8]) -> Option<(usize, usize)> {
+    body.iter()
+        .enumerate()
+        .find_map(|(index, byte)| match byte {
+            0x07 => Some((index, 1)),
+            0x1b if body.get(index + 1) == Some(&b'\\') => Some((index, 2)),
+            _ => None,
+        })
+}
+
+fn parse_marker(body: &[u8]) -> Option<ShellIntegrationMarker> {
+    let body = String::from_utf8_lossy(body);
+    let (kind, params) = body.split_once(';').unwrap_or((&body, ""));
+
+    match kind {
+        "A" => Some(ShellIntegrationMarker::PromptStart),
+        "C" => Some(ShellIntegrationMarker::CommandStart),
+        // The command line may itself contain `;`, so it takes the rest of the body.
+        "E" => Some(ShellIntegrationMarker::CommandLine(
+            params.trim().to_string(),
+        ))
+        .filter(|_| !params.trim().is_empty()),
+        "D" => Some(ShellIntegrationMarker::CommandFinished(
+            params
+                .split(';')
+                .next()
+                .and_then(|status| status.trim().parse().ok()),
+        )),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        CompletedTerminalCommand, ShellIntegrationMarker, ShellIntegrationMarkerParser,
+        ShellIntegrationSegment, TerminalCommandExitTracker,
+    };
+    use crate::services::web_shell::terminal_command_output_excerpts::OutputExcerptLimits;
+
+    #[test]
+    fn strips_markers_and_keeps_other_escapes() {
+        let mut parser = ShellIntegrationMarkerParser::default();
+        let (output, markers) =
+            parser.strip_markers(b"\x1b]133;D;2\x07\x1b]133;A\x07\x1b[1mroot@altair\x1b]0;t\x07");
+
+        assert_eq!(output, b"\x1b[1mroot@altair\x1b]0;t\x07");
+        assert_eq!(
+            markers,
+            vec![
+                ShellIntegrationMarker::CommandFinished(Some(2)),
+                ShellIntegrationMarker::PromptStart
+            ]
+        );
+    }
+
+    #[test]
+    fn markers_split_across_reads_are_reassembled() {
+        let mut parser = ShellIntegrationMarkerParser::default();
+
+        let (output, markers) = parser.strip_markers(b"out\x1b]13");
+        assert_eq!(output, b"out");
+        assert!(markers.is_empty());
+
+        let (output, markers) = parser.strip_markers(b"3;C\x1b\\more");
+        assert_eq!(output, b"more");
+        assert_eq!(markers, vec![ShellIntegrationMarker::CommandStart]);
+    }
+
+    #[test]
+    fn command_line_markers_keep_semicolons() {
+        let mut parser = ShellIntegrationMarkerParser::default();
+        let (output, markers) = parser.strip_markers(b"\x1b]633;E;cd /tmp; ls\x07$ ");
+
+        assert_eq!(output, b"$ ");
+        assert_eq!(
+            markers,
+            vec![ShellIntegrationMarker::CommandLine(
+                "cd /tmp; ls".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn shells_without_hooks_release_commands_immediately() {
+        let mut tracker = TerminalCommandExitTracker::default();
+
+        assert_eq!(
+            tracker.command_entered("ls".to_string()),
+            vec![CompletedTerminalCommand {
+                command_redacted: "ls".to_string(),
+                exit_status: None,
+                duration_ms: None,
+                output_excerpt: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn reported_command_line_is_paired_with_exit_status() {
+        let mut tracker = TerminalCommandExitTracker::default();
+        assert!(tracker
+            .marker(ShellIntegrationMarker::CommandFinished(Some(0)))
+            .is_empty());
+
+        assert!(tracker.command_entered("\x1b[A".to_string()).is_empty());
+        tracker.marker(ShellIntegrationMarker::CommandStart);
+        tracker.marker(ShellIntegrationMarker::CommandLine(
+            "mysql --password hunter2".to_string(),
+        ));
+        let released = tracker.marker(ShellIntegrationMarker::CommandFinished(Some(1)));
+
+        assert_eq!(released.len(), 1);
+        assert_eq!(released[0].command_redacted, "mysql --password [redacted]");
+        assert_eq!(released[0].exit_status, Some(1));
+        assert!(released[0].duration_ms.is_some());
+    }
+
+    #[test]
+    fn sh_prompt_hooks_finish_keystroke_captured_commands() {
+        let mut parser = ShellIntegrationMarkerParser::default();
+        let mut tracker = TerminalCommandExitTracker::default()
+            .with_output_excerpts(OutputExcerptLimits::default());
+        let mut feed = |tracker: &mut TerminalCommandExitTracker, chunk: &[u8]| {
+            let mut released = Vec::new();
+            for segment in parser.split_markers(chunk) {
+                match segment {
+                    ShellIntegrationSegment::Output(bytes) => tracker.output(&bytes),
+                    ShellIntegrationSegment::Marker(marker) => {
+                        released.extend(tracker.marker(marker))
+                    }
+                }
+            }
+            released
+        };
+
+        assert!(feed(&mut tracker, b"\x1b]133;D;0\x07\x1b]133;A\x07u@altair:/$ ").is_empty());
+        assert!(tracker.command_entered("cat nope".to_string()).is_empty());
+        let released = feed(
+            &mut tracker,
+            b"cat: nope: No such file\r\n\x1b]133;D;1\x07\x1b]133;A\x07u@altair:/$ ",
+        );
+
+        assert_eq!(released.len(), 1);
+        assert_eq!(released[0].command_redacted, "cat nope");
+        assert_eq!(released[0].exit_status, Some(1));
+        assert!(released[0].duration_ms.is_some());
+        assert_eq!(
+            released[0].output_excerpt.as_deref(),
+            Some("cat: nope: No such file")
+        );
+    }
+
+    #[test]
+    fn prompts_without_a_new_history_entry_are_not_reported() {
+        let mut tracker = TerminalCommandExitTracker::default();
+        tracker.marker(ShellIntegrationMarker::PromptStart);
+
+        assert!(tracker
+            .marker(ShellIntegrationMarker::CommandFinished(Some(130)))
+            .is_empty());
+    }
+
+    #[test]
+    fn output_and_markers_keep_stream_order() {
+        let mut parser = ShellIntegrationMarkerParser::default();
+
+        assert_eq!(
+            parser.split_markers(b"$ ls\r\n\x1b]133;C\x07a b\r\n\x1b]633;E;ls\x07"),
+            vec![
+                ShellIntegrationSegment::Output(b"$ ls\r\n".to_vec()),
+                ShellIntegrationSegment::Marker(ShellIntegrationMarker::CommandStart),
+                ShellIntegrationSegment::Output(b"a b\r\n".to_vec()),
+                ShellIntegrationSegment::Marker(ShellIntegrationMarker::CommandLine(
+                    "ls".to_string()
+                )),
+            ]
+        );
+    }
+
+    #[test]
+    fn output_between_start_and_finish_is_attached_as_an_excerpt() {
+        let mut tracker = TerminalCommandExitTracker::default()
+            .with_output_excerpts(OutputExcerptLimits::default());
+        tracker.output(b"$ cat notes\r\n");
+        tracker.marker(ShellIntegrationMarker::CommandStart);
+        tracker.output(b"\x1b[1mtoken=abc123\x1b[0m\r\n");
+        tracker.marker(ShellIntegrationMarker::CommandLine("cat notes".to_string()));
+        tracker.output(b"user@altair:~$ ");
+        let released = tracker.marker(ShellIntegrationMarker::CommandFinished(Some(0)));
+
+        assert_eq!(
+            released[0].output_excerpt.as_deref(),
+            Some("token=[redacted]")
+        );
+    }
+}
@@ -0,0 +1,840 @@
+/**
+ * @file web_traffic_capture — request events for web lab runtimes.
+ *
+ * @remarks
+ * The web proxy records every request it forwards to a runtime. Records are
+ * batched per runtime and published through the terminal event sinks, with
+ * the same identifiers and capture policy as the runtime's terminal.
+ *
+ * Events:
+ *
+ *  - `http_request` → method, redacted path and query, status and upstream
+ *    time, plus a redacted body excerpt under `commands-with-output`
+ *  - `payload_detected` → first request of a session whose path, query or
+ *    body looks like a known attack payload (SQL injection such as
+ *    `' OR 1=1`, XSS, path traversal, command or template injection)
+ *  - `command` → redacted command typed in a browser IDE terminal whose
+ *    input the proxy can read, when the policy captures commands
+ *
+ * Key characteristics:
+ *
+ *  - Nothing is recorded for runtimes whose capture policy is `off`
+ *  - Bodies are capped by `WEB_CAPTURE_BODY_MAX_BYTES` (2048, `0` disables)
+ *  - Values are decoded before redaction so encoded flags are still masked
+ *  - Recording never blocks the proxy; a full queue drops the record
+ *  - Runtime settings are read in background tasks, so a slow API server
+ *    never stalls capture for other runtimes; records arriving meanwhile
+ *    wait, up to `PENDING_RECORDS_MAX` per runtime
+ *  - Runtime labels are re-read every `TERMINAL_EVENT_CONTEXT_REFRESH_SECS`
+ *
+ * @packageDocumentation
+ */
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, OnceLock};
+
+use axum::body::Bytes;
+use chrono::{DateTime, Utc};
+use k8s_openapi::api::core::v1::Pod;
+use kube::{Api, Client};
+use regex::Regex;
+use tokio::sync::mpsc;
+use tokio::time::{interval, Duration, Instant};
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::{
+    models::TerminalCapturePolicy,
+    services::{
+        spawn::namespace_for_delivery,
+        terminal_event_outbox::{TerminalEventDropReason, TerminalEventOutbox},
+        terminal_event_sinks::{TerminalEvent, TerminalEventKind, TerminalEventSinks},
+        web_shell::{
+            load_terminal_event_context, publish_terminal_events, resolve_terminal_capture_policy,
+            TerminalCommandRedactor, TerminalEventContext,
+        },
+    },
+};
+
+const EVENT_BATCH_SIZE: usize = 10;
+const EVENT_FLUSH_SECS: u64 = 2;
+const EVENT_QUEUE_SIZE: usize = 1024;
+const DEFAULT_CONTEXT_REFRESH_SECS: u64 = 30;
+const DEFAULT_BODY_MAX_BYTES: usize = 2048;
+// Extra bytes kept past the cap so a flag cut by it is still redacted whole.
+const BODY_REDACTION_SLACK_BYTES: usize = 256;
+const FORGET_IDLE_RUNTIME_SECS: u64 = 600;
+const PENDING_RECORDS_MAX: usize = 64;
+const REDACTED: &str = "[redacted]";
+
+/// One request forwarded by the web proxy.
+pub struct WebExchange {
+    pub container_id: String,
+    pub occurred_at: DateTime<Utc>,
+    pub method: String,
+    pub path: String,
+    pub query: Option<String>,
+    pub content_type: Option<String>,
+    /// Start of the request body, see `WebTrafficCapture::body_prefix`.
+    pub body: Bytes,
+    pub status: u16,
+    pub duration_ms: u64,
+}
+
+/// What the web proxy hands to the forwarder.
+enum WebTrafficRecord {
+    Exchange(WebExchange),
+    /// Raw command typed in an IDE terminal; redacted by the forwarder.
+    TerminalCommand {
+        container_id: String,
+        occurred_at: DateTime<Utc>,
+        command: String,
+    },
+}
+
+impl WebTrafficRecord {
+    fn container_id(&self) -> &str {
+        match self {
+            Self::Exchange(exchange) => &exchange.container_id,
+            Self::TerminalCommand { container_id, .. } => container_id,
+        }
+    }
+}
+
+/// Queue between the web proxy and the background forwarder.
+pub struct WebTrafficCapture {
+    tx: mpsc::Sender<WebTrafficRecord>,
+    outbox: Arc<TerminalEventOutbox>,
+    body_max_bytes: usize,
+}
+
+impl WebTrafficCapture {
+    /// Starts the forwarder for runtimes in the web namespace.
+    pub fn start(
+        kube_client: Client,
+        sinks: Arc<TerminalEventSinks>,
+        outbox: Arc<TerminalEventOutbox>,
+    ) -> Self {
+        let pods = Api::namespaced(kube_client, &namespace_for_delivery("web"));
+        let refresh = std::env::var("TERMINAL_EVENT_CONTEXT_REFRESH_SECS")
+            .ok()
+            .and_then(|value| value.trim().parse().ok())
+            .filter(|secs| *secs > 0)
+            .unwrap_or(DEFAULT_CONTEXT_REFRESH_SECS);
+        let body_max_bytes = std::env::var("WEB_CAPTURE_BODY_MAX_BYTES")
+            .ok()
+            .and_then(|value| value.trim().parse().ok())
+            .unwrap_or(DEFAULT_BODY_MAX_BYTES);
+        let (tx, rx) = mpsc::channel(EVENT_QUEUE_SIZE);
+        tokio::spawn(forward_web_traffic(
+            pods,
+            Duration::from_secs(refresh),
+            body_max_bytes,
+            sinks,
+            outbox.clone(),
+            rx,
+        ));
+
+        Self {
+            tx,
+            outbox,
+            body_max_bytes,
+        }
+    }
+
+    /// The part of a request body worth handing to `record`.
+    pub fn body_prefix(&self, body: &Bytes) -> Bytes {
+        if self.body_max_bytes == 0 {
+            return Bytes::new();
+        }
+        body.slice(
+            ..body
+                .len()
+                .min(self.body_max_bytes + BODY_REDACTION_SLACK_BYTES),
+        )
+    }
+
+    pub fn record(&self, exchange: WebExchange) {
+        self.send(WebTrafficRecord::Exchange(exchange));
+    }
+
+    pub fn record_terminal_command(&self, container_id: &str, command: String) {
+        self.send(WebTrafficRecord::TerminalCommand {
+            container_id: container_id.to_string(),
+            occurred_at: Utc::now(),
+            command,
+        });
+    }
+
+    fn send(&self, record: WebTrafficRecord) {
+        if self.tx.try_send(record).is_err() {
+            warn!(
+                action = "web_traffic_capture",
+                "Dropped web traffic event because the analytics queue is full"
+            );
+            self.outbox
+                .record_dropped(TerminalEventDropReason::QueueFull, 1);
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum PayloadCategory {
+    SqlInjection,
+    CrossSiteScripting,
+    PathTraversal,
+    CommandInjection,
+    TemplateInjection,
+}
+
+impl PayloadCategory {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::SqlInjection => "sql_injection",
+            Self::CrossSiteScripting => "xss",
+            Self::PathTraversal => "path_traversal",
+            Self::CommandInjection => "command_injection",
+            Self::TemplateInjection => "template_injection",
+        }
+    }
+}
+
+fn payload_patterns() -> &'static [(PayloadCategory, Regex)] {
+    static PATTERNS: OnceLock<Vec<(PayloadCategory, Regex)>> = OnceLock::new();
+    PATTERNS.get_or_init(|| {
+        [
+            (
+                PayloadCategory::SqlInjection,
+                r#"(?i)['"]\s*(?:or|and)\s+(?:'[^']*'|"[^"]*"|\d+)\s*(?:=|<|>|like\b)|\bunion\s+(?:all\s+)?select\b|['"]\s*(?:--|#|/\*)|;\s*(?:drop|delete|insert|update)\s|\b(?:sleep|pg_sleep|benchmark)\s*\("#,
+            ),
+            (
+                PayloadCategory::CrossSiteScripting,
+                r"(?i)<\s*script\b|\bjavascript\s*:|<[a-z][^>]*\son[a-z]+\s*=",
+            ),
+            (
+                PayloadCategory::PathTraversal,
+                r"(?i)(?:^|[/\\=])\.\.[/\\]|/etc/(?:passwd|shadow)\b|\bwin\.ini\b",
+            ),
+            (
+                PayloadCategory::CommandInjection,
+                r"(?i)(?:;|&&|\|\|?|\$\(|`)\s*(?:cat|id|whoami|ls|uname|nc|curl|wget|sh|bash|ping)\b",
+            ),
+            (
+                PayloadCategory::TemplateInjection,
+                r"\{\{\s*\d+\s*\*\s*\d+\s*\}\}|\$\{\s*\d+\s*\*\s*\d+\s*\}|<%=\s*\d+\s*\*\s*\d+\s*%>|\{\{[^}]*(?:__class__|config|self\.)",
+            ),
+        ]
+        .into_iter()
+        .map(|(category, pattern)| (category, Regex::new(pattern).expect("static pattern")))
+        .collect()
+    })
+}
+
+fn sensitive_json_value() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| {
+        Regex::new(
+            r#"(?i)("[^"]*(?:pass|secret|token|auth|credential|api_?key)[^"]*"\s*:\s*)"(?:[^"\\]|\\.)*""#,
+        )
+        .expect("static pattern")
+    })
+}
+
+/// What lab-api may capture for one runtime, from its Pod.
+struct RuntimeCapture {
+    context: TerminalEventContext,
+    policy: TerminalCapturePolicy,
+    redactor: TerminalCommandRedactor,
+}
+
+impl RuntimeCapture {
+    fn for_pod(pod: &Pod) -> Option<Self> {
+        Some(Self {
+            context: load_terminal_event_context(pod)?,
+            policy: resolve_terminal_capture_policy(pod),
+            redactor: TerminalCommandRedactor::for_pod(pod),
+        })
+    }
+
+    /// Events for `exchange`; payload categories already in `detected` are not
+    /// reported again.
+    fn events(
+        &self,
+        exchange: &WebExchange,
+        body_max_bytes: usize,
+        detected: &mut HashSet<PayloadCategory>,
+    ) -> Vec<TerminalEvent> {
+        if self.policy == TerminalCapturePolicy::Off {
+            return Vec::new();
+        }
+
+        let body_text = is_text_body(exchange.content_type.as_deref())
+            .then(|| String::from_utf8_lossy(&exchange.body).into_owned());
+        let is_form = exchange
+            .content_type
+            .as_deref()
+            .is_some_and(|value| value.starts_with("application/x-www-form-urlencoded"));
+        let body_excerpt = body_text
+            .as_deref()
+            .filter(|_| self.policy == TerminalCapturePolicy::CommandsWithOutput)
+            .filter(|body| body_max_bytes > 0 && !body.trim().is_empty())
+            .map(|body| {
+                let redacted = if is_form {
+                    self.redact_pairs(body)
+                } else {
+                    self.redact_text(body)
+                };
+                truncate_chars(&redacted, body_max_bytes)
+            });
+
+        let mut events = vec![self.event(
+            exchange.occurred_at,
+            TerminalEventKind::HttpRequest {
+                method: exchange.method.clone(),
+                path_redacted: self.redact_path(&exchange.path),
+                query_redacted: exchange
+                    .query
+                    .as_deref()
+                    .filter(|query| !query.is_empty())
+                    .map(|query| self.redact_pairs(query)),
+                status: exchange.status,
+                duration_ms: exchange.duration_ms,
+                body_excerpt,
+            },
+        )];
+
+        let locations = [
+            ("path", Some(percent_decode(&exchange.path, false))),
+            (
+                "query",
+                exchange.query.as_deref().map(|q| percent_decode(q, true)),
+            ),
+            (
+                "body",
+                body_text.map(|body| {
+                    if is_form {
+                        percent_decode(&body, true)
+                    } else {
+                        body
+                    }
+                }),
+            ),
+        ];
+        for (location, value) in locations {
+            let Some(value) = value else {
+                continue;
+            };
+            for (category, pattern) in payload_patterns() {
+                if detected.contains(category) || !pattern.is_match(&value) {
+                    continue;
+                }
+                detected.insert(*category);
+                events.push(self.event(
+                    exchange.occurred_at,
+                    TerminalEventKind::PayloadDetected {
+                        category: category.as_str().to_string(),
+                        location: location.to_string(),
+                    },
+                ));
+            }
+        }
+
+        events
+    }
+
+    /// IDE terminals report no exit status, so only the command is known.
+    fn command_event(&self, occurred_at: DateTime<Utc>, command: &str) -> Option<TerminalEvent> {
+        if !self.policy.captures_commands() {
+            return None;
+        }
+        let command_redacted = self.redactor.redact(command);
+
+        (!command_redacted.is_empty()).then(|| {
+            self.event(
+                occurred_at,
+                TerminalEventKind::Command {
+                    command_redacted,
+                    exit_status: None,
+                    duration_ms: None,
+                    output_excerpt: None,
+                },
+            )
+        })
+    }
+
+    fn event(&self, occurred_at: DateTime<Utc>, kind: TerminalEventKind) -> TerminalEvent {
+        TerminalEvent {
+            event_id: Uuid::new_v4(),
+            occurred_at,
+            kind,
+        }
+    }
+
+    fn redact_path(&self, path: &str) -> String {
+        path.split('/')
+            .map(|segment| self.redactor.redact(&percent_decode(segment, false)))
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    /// `a=1&b=2` style values, redacted pair by pair after decoding.
+    fn redact_pairs(&self, pairs: &str) -> String {
+        pairs
+            .split('&')
+            .map(|pair| self.redactor.redact(&percent_decode(pair, true)))
+            .collect::<Vec<_>>()
+            .join("&")
+    }
+
+    fn redact_text(&self, text: &str) -> String {
+        let text = sensitive_json_value().replace_all(text, format!("${{1}}\"{REDACTED}\""));
+        self.redactor.redact(&text)
+    }
+}
+
+fn is_text_body(content_type: Option<&str>) -> bool {
+    let Some(content_type) = content_type else {
+        return false;
+    };
+    let essence = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+
+    essence.starts_with("text/")
+        || essence == "application/x-www-form-urlencoded"
+        || essence.ends_with("json")
+        || essence.ends_with("xml")
+}
+
+fn percent_decode(value: &str, plus_as_space: bool) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        match bytes[index] {
+            b'+' if plus_as_space => decoded.push(b' '),
+            b'%' => match bytes
+                .get(index + 1..index + 3)
+                .and_then(|hex| std::str::from_utf8(hex).ok())
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            {
+                Some(byte) => {
+                    decoded.push(byte);
+                    index += 2;
+                }
+                None => decoded.push(b'%'),
+            },
+            byte => decoded.push(byte),
+        }
+        index += 1;
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn truncate_chars(value: &str, max_bytes: usize) -> String {
+    if value.len() <= max_bytes {
+        return value.to_string();
+    }
+    let mut end = max_bytes;
+    while !value.is_char_boundary(end) {
+        end -= 1;
+    }
+
+    value[..end].to_string()
+}
+
+/// Pending events and capture settings for one runtime.
+struct RuntimeTraffic {
+    capture: Option<RuntimeCapture>,
+    loaded_at: Instant,
+    last_seen: Instant,
+    /// Set while the runtime's settings are being read in the background.
+    loading: bool,
+    /// Records received before the runtime's settings were first known.
+    pending: Vec<WebTrafficRecord>,
+    events: Vec<TerminalEvent>,
+    detected: HashSet<PayloadCategory>,
+}
+
+/// Settings read for a runtime, sent back to the forwarder loop.
+type LoadedRuntime = (String, Option<RuntimeCapture>);
+
+impl RuntimeTraffic {
+    fn new() -> Self {
+        let now = Instant::now();
+        Self {
+            capture: None,
+            loaded_at: now,
+            last_seen: now,
+            loading: true,
+            pending: Vec::new(),
+            events: Vec::new(),
+            detected: HashSet::new(),
+        }
+    }
+
+    /// Turns a record into events, or holds it until the first load finishes.
+    /// Returns false when the record had to be dropped.
+    fn record(&mut self, record: WebTrafficRecord, body_max_bytes: usize) -> bool {
+        if self.loading && self.capture.is_none() {
+            if self.pending.len() >= PENDING_RECORDS_MAX {
+                return false;
+            }
+            self.pending.push(record);
+            return true;
+        }
+        let Some(capture) = &self.capture else {
+            return true;
+        };
+        match &record {
+            WebTrafficRecord::Exchange(exchange) => {
+                let events = capture.events(exchange, body_max_bytes, &mut self.detected);
+                self.events.extend(events);
+            }
+            WebTrafficRecord::TerminalCommand {
+                occurred_at,
+                command,
+                ..
+            } => self
+                .events
+                .extend(capture.command_event(*occurred_at, command)),
+        }
+        true
+    }
+
+    /// Switches to freshly read settings. Events queued so far are flushed
+    /// first so they keep the identifiers they were recorded under; a new
+    /// session starts with no payloads detected. Held records are then
+    /// recorded under the new settings.
+    async fn apply(
+        &mut self,
+        capture: Option<RuntimeCapture>,
+        body_max_bytes: usize,
+        sinks: &TerminalEventSinks,
+    ) {
+        let current = self.capture.as_ref().map(|capture| &capture.context);
+        if current != capture.as_ref().map(|capture| &capture.context) {
+            self.flush(sinks).await;
+            self.detected.clear();
+        }
+        self.capture = capture;
+        self.loaded_at = Instant::now();
+        self.loading = false;
+        for record in std::mem::take(&mut self.pending) {
+            self.record(record, body_max_bytes);
+        }
+    }
+
+    async fn flush(&mut self, sinks: &TerminalEventSinks) {
+        match &self.capture {
+            Some(capture) if !self.events.is_empty() => {
+                publish_terminal_events(&capture.context, sinks, &mut self.events).await;
+            }
+            _ => self.events.clear(),
+        }
+    }
+}
+
+/// Reads a runtime's settings off the forwarder loop.
+fn start_runtime_load(pods: &Api<Pod>, container_id: &str, loaded: mpsc::Sender<LoadedRuntime>) {
+    let pods = pods.clone();
+    let container_id = container_id.to_string();
+    tokio::spawn(async move {
+        let capture = load_runtime_capture(&pods, &container_id).await;
+        let _ = loaded.send((container_id, capture)).await;
+    });
+}
+
+async fn load_runtime_capture(pods: &Api<Pod>, container_id: &str) -> Option<RuntimeCapture> {
+    match pods.get_opt(container_id).await {
+        Ok(pod) => pod.as_ref().and_then(RuntimeCapture::for_pod),
+        Err(error) => {
+            warn!(
+                container_id = %container_id,
+                action = "web_traffic_capture",
+                "Failed to read web runtime for request events: {}",
+                error
+            );
+            None
+        }
+    }
+}
+
+async fn forward_web_traffic(
+    pods: Api<Pod>,
+    refresh: Duration,
+    body_max_bytes: usize,
+    sinks: Arc<TerminalEventSinks>,
+    outbox: Arc<TerminalEventOutbox>,
+    mut rx: mpsc::Receiver<WebTrafficRecord>,
+) {
+    let mut runtimes: HashMap<String, RuntimeTraffic> = HashMap::new();
+    let (loaded_tx, mut loaded_rx) = mpsc::channel::<LoadedRuntime>(EVENT_QUEUE_SIZE);
+    let mut ticker = interval(Duration::from_secs(EVENT_FLUSH_SECS));
+    let forget_after = Duration::from_secs(FORGET_IDLE_RUNTIME_SECS);
+
+    loop {
+        tokio::select! {
+            maybe_record = rx.recv() => {
+                let Some(record) = maybe_record else {
+                    break;
+                };
+                let container_id = record.container_id().to_string();
+                let runtime = runtimes.entry(container_id.clone()).or_insert_with(|| {
+                    start_runtime_load(&pods, &container_id, loaded_tx.clone());
+                    RuntimeTraffic::new()
+                });
+                if !runtime.loading && runtime.loaded_at.elapsed() >= refresh {
+                    runtime.loading = true;
+                    start_runtime_load(&pods, &container_id, loaded_tx.clone());
+                }
+
+                runtime.last_seen = Instant::now();
+                if !runtime.record(record, body_max_bytes) {
+                    outbox.record_dropped(TerminalEventDropReason::QueueFull, 1);
+                }
+                if runtime.events.len() >= EVENT_BATCH_SIZE {
+                    runtime.flush(&sinks).await;
+                }
+            }
+            Some((container_id, capture)) = loaded_rx.recv() => {
+                let Some(runtime) = runtimes.get_mut(&container_id) else {
+                    continue;
+                };
+                runtime.apply(capture, body_max_bytes, &sinks).await;
+                if runtime.events.len() >= EVENT_BATCH_SIZE {
+                    runtime.flush(&sinks).await;
+                }
+            }
+            _ = ticker.tick() => {
+                for runtime in runtimes.values_mut() {
+                    runtime.flush(&sinks).await;
+                }
+                runtimes.retain(|_, runtime| runtime.last_seen.elapsed() < forget_after);
+            }
+        }
+    }
+
+    for runtime in runtimes.values_mut() {
+        runtime.flush(&sinks).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, HashSet};
+
+    use axum::body::Bytes;
+    use chrono::Utc;
+    use k8s_openapi::api::core::v1::{Container, EnvVar, Pod, PodSpec};
+    use kube::api::ObjectMeta;
+    use uuid::Uuid;
+
+    use super::{
+        percent_decode, RuntimeCapture, RuntimeTraffic, WebExchange, WebTrafficRecord,
+        PENDING_RECORDS_MAX,
+    };
+    use crate::services::terminal_event_sinks::{TerminalEventKind, TerminalEventSinks};
+
+    fn runtime_pod(policy: &str) -> Pod {
+        Pod {
+            metadata: ObjectMeta {
+                labels: Some(BTreeMap::from([
+                    ("session_id".to_string(), Uuid::new_v4().to_string()),
+                    ("runtime_id".to_string(), Uuid::new_v4().to_string()),
+                    ("terminal_capture".to_string(), policy.to_string()),
+                ])),
+                ..Default::default()
+            },
+            spec: Some(PodSpec {
+                containers: vec![Container {
+                    env: Some(vec![EnvVar {
+                        name: "ALTAIR_FLAG_STEP_1".to_string(),
+                        value: Some("FLAG{sqli_master}".to_string()),
+                        ..Default::default()
+                    }]),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn exchange(query: Option<&str>, body: &str) -> WebExchange {
+        WebExchange {
+            container_id: "ctf-runtime-42".to_string(),
+            occurred_at: Utc::now(),
+            method: "POST".to_string(),
+            path: "/login".to_string(),
+            query: query.map(str::to_string),
+            content_type: Some("application/x-www-form-urlencoded".to_string()),
+            body: Bytes::from(body.to_string()),
+            status: 302,
+            duration_ms: 12,
+        }
+    }
+
+    #[tokio::test]
+    async fn records_wait_for_the_runtime_settings_to_load() {
+        let sinks = TerminalEventSinks::new(Vec::new());
+        let mut runtime = RuntimeTraffic::new();
+
+        for _ in 0..PENDING_RECORDS_MAX {
+            assert!(runtime.record(WebTrafficRecord::Exchange(exchange(None, "")), 2048));
+        }
+        assert!(!runtime.record(WebTrafficRecord::Exchange(exchange(None, "")), 2048));
+        assert!(runtime.events.is_empty());
+
+        let capture = RuntimeCapture::for_pod(&runtime_pod("commands-only"));
+        runtime.apply(capture, 2048, &sinks).await;
+
+        assert!(runtime.pending.is_empty());
+        assert_eq!(runtime.events.len(), PENDING_RECORDS_MAX);
+    }
+
+    #[test]
+    fn requests_are_redacted_and_payloads_reported_once_per_session() {
+        let capture = RuntimeCapture::for_pod(&runtime_pod("commands-with-output")).unwrap();
+        let mut detected = HashSet::new();
+
+        let events = capture.events(
+            &exchange(
+                Some("user=admin%27+OR+1%3D1--&flag=FLAG%7Bsqli_master%7D"),
+                "username=alice&password=hunter2",
+            ),
+            2048,
+            &mut detected,
+        );
+        let kinds: Vec<_> = events.into_iter().map(|event| event.kind).collect();
+
+        assert_eq!(
+            kinds,
+            vec![
+                TerminalEventKind::HttpRequest {
+                    method: "POST".to_string(),
+                    path_redacted: "/login".to_string(),
+                    query_redacted: Some("user=admin' OR 1=1--&flag=[redacted]".to_string()),
+                    status: 302,
+                    duration_ms: 12,
+                    body_excerpt: Some("username=alice&password=[redacted]".to_string()),
+                },
+                TerminalEventKind::PayloadDetected {
+                    category: "sql_injection".to_string(),
+                    location: "query".to_string(),
+                },
+            ]
+        );
+
+        let again = capture.events(&exchange(Some("id=1'+or+'a'='a"), ""), 2048, &mut detected);
+        assert_eq!(again.len(), 1);
+    }
+
+    #[test]
+    fn bodies_follow_the_capture_policy() {
+        let body = r#"{"comment":"<script>alert(1)</script>","api_key":"abc"}"#;
+        let mut json = exchange(None, body);
+        json.content_type = Some("application/json".to_string());
+
+        let commands_only = RuntimeCapture::for_pod(&runtime_pod("commands-only")).unwrap();
+        let kinds: Vec<_> = commands_only
+            .events(&json, 2048, &mut HashSet::new())
+            .into_iter()
+            .map(|event| event.kind)
+            .collect();
+        assert!(matches!(
+            &kinds[0],
+            TerminalEventKind::HttpRequest {
+                body_excerpt: None,
+                ..
+            }
+        ));
+        assert_eq!(
+            kinds[1],
+            TerminalEventKind::PayloadDetected {
+                category: "xss".to_string(),
+                location: "body".to_string(),
+            }
+        );
+
+        let with_output = RuntimeCapture::for_pod(&runtime_pod("commands-with-output")).unwrap();
+        let excerpt = |max_bytes| match with_output
+            .events(&json, max_bytes, &mut HashSet::new())
+            .remove(0)
+            .kind
+        {
+            TerminalEventKind::HttpRequest { body_excerpt, .. } => body_excerpt,
+            _ => None,
+        };
+        assert_eq!(
+            excerpt(2048).as_deref(),
+            Some(r#"{"comment":"<script>alert(1)</script>","api_key":"[redacted]"}"#)
+        );
+        assert_eq!(excerpt(24).as_deref(), Some(r#"{"comment":"<script>aler"#));
+
+        let off = RuntimeCapture::for_pod(&runtime_pod("off")).unwrap();
+        assert!(off.events(&json, 2048, &mut HashSet::new()).is_empty());
+    }
+
+    #[test]
+    fn ide_terminal_commands_are_redacted_unless_capture_is_off() {
+        let capture = RuntimeCapture::for_pod(&runtime_pod("commands-only")).unwrap();
+
+        assert_eq!(
+            capture
+                .command_event(Utc::now(), "echo FLAG{sqli_master}")
+                .map(|event| event.kind),
+            Some(TerminalEventKind::Command {
+                command_redacted: "echo [redacted]".to_string(),
+                exit_status: None,
+                duration_ms: None,
+                output_excerpt: None,
+            })
+        );
+
+        let off = RuntimeCapture::for_pod(&runtime_pod("off")).unwrap();
+        assert!(off.command_event(Utc::now(), "ls").is_none());
+    }
+
+    #[test]
+    fn payload_categories_are_detected_in_decoded_values() {
+        let capture = RuntimeCapture::for_pod(&runtime_pod("commands-only")).unwrap();
+        let categories = |path: &str, query: &str| -> Vec<String> {
+            let mut request = exchange(Some(query), "");
+            request.path = path.to_string();
+            capture
+                .events(&request, 2048, &mut HashSet::new())
+                .into_iter()
+                .filter_map(|event| match event.kind {
+                    TerminalEventKind::PayloadDetected { category, .. } => Some(category),
+                    _ => None,
+                })
+                .collect()
+        };
+
+        assert_eq!(
+            categories("/download", "file=..%2F..%2Fetc%2Fpasswd"),
+            vec!["path_traversal"]
+        );
+        assert_eq!(
+            categories("/ping", "host=127.0.0.1%3Bid"),
+            vec!["command_injection"]
+        );
+        assert_eq!(
+            categories("/hello", "name=%7B%7B7*7%7D%7D"),
+            vec!["template_injection"]
+        );
+        assert_eq!(
+            categories(
+                "/search",
+                "q=x%27%20UNION%20SELECT%20password%20FROM%20users"
+            ),
+            vec!["sql_injection"]
+        );
+        assert!(categories("/products", "q=o%27reilly+books&page=2").is_empty());
+        assert_eq!(percent_decode("a+b%2", true), "a b%2");
+    }
+}
@@ -61,6 +61,10 @@ fn create_test_spawn_request() -> SpawnRequest {
         lab_delivery: "terminal".to_string(),
         app_port: None,
         session_flags: serde_json::json!({}),
+        webshell: None,
+        terminal_capture: None,
+        web_rewrite: None,
+        ide: None,
     }
 }
 
@@ -647,6 +651,7 @@ fn test_spawn_response_serialize() {
                 "ws://lab-api-service:8080/spawn/webshell/ctf-session-123".to_string(),
             ),
             app_url: None,
+            desktop_url: None,
         },
     };
 